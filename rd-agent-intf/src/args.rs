@@ -2,6 +2,8 @@
 use serde::{Deserialize, Serialize};
 use util::*;
 
+use super::Slice;
+
 const HELP_BODY: &str = "\
 Resource-control demo agent.
 
@@ -49,6 +51,15 @@ lazy_static::lazy_static! {
              --keep-reports     'Don't delete expired report files, also affects --reset'
              --bypass           'Skip startup and periodic health checks'
              --passive=[MODE]   'Avoid system config changes (MODE=all|keep-crit-mem-prot)'
+             --iocost=[on|off]  'Force iocost on or off regardless of the disable sequences'
+             --no-iocost-preset 'Use the kernel default iocost model until iocost is benchmarked'
+             --cgroup-root=[PATH] 'cgroup2 root to operate under, e.g. a delegated subtree (default: /sys/fs/cgroup)'
+             --io-ctrl-ignore=[GLOBS] 'Comma-separated cgroup path globs whose io.latency/low/max configs are ignored'
+             --max-concurrent-fixes=[NR] 'Maximum number of cgroup config fixes per verification pass'
+             --doctor           'Verify cgroup enforcement with a throwaway slice and exit'
+             --audit            'Report slice config drift without fixing anything and exit'
+             --clear-slice=[SLICE] 'Clear the resctl configurations of SLICE and exit'
+             --keep-running     'Leave start/stop slices such as sideload.slice running when clearing'
          -v...                  'Sets the level of verbosity'",
         dfl_dir = Args::default().dir,
         dfl_rep_ret = Args::default().rep_retention as f64 / 3600.0,
@@ -103,6 +114,9 @@ pub struct Args {
     pub rep_retention: u64,
     pub rep_1min_retention: u64,
     pub systemd_timeout: f64,
+    pub io_ctrl_ignore: Vec<String>,
+    pub cgroup_root: String,
+    pub max_concurrent_fixes: Option<u64>,
 
     #[serde(skip)]
     pub no_iolat: bool,
@@ -127,6 +141,18 @@ pub struct Args {
     #[serde(skip)]
     pub keep_crit_mem_prot: bool,
     #[serde(skip)]
+    pub iocost: Option<bool>,
+    #[serde(skip)]
+    pub no_iocost_preset: bool,
+    #[serde(skip)]
+    pub doctor: bool,
+    #[serde(skip)]
+    pub audit: bool,
+    #[serde(skip)]
+    pub clear_slice: Option<Slice>,
+    #[serde(skip)]
+    pub keep_running: bool,
+    #[serde(skip)]
     pub verbosity: u32,
 
     pub bandit: Option<Bandit>,
@@ -141,6 +167,9 @@ impl Default for Args {
             rep_retention: 3600,
             rep_1min_retention: 24 * 3600,
             systemd_timeout: systemd::SYSTEMD_DFL_TIMEOUT,
+            io_ctrl_ignore: vec![],
+            cgroup_root: "/sys/fs/cgroup".into(),
+            max_concurrent_fixes: None,
             no_iolat: false,
             force: false,
             force_running: false,
@@ -152,6 +181,12 @@ impl Default for Args {
             bypass: false,
             passive: false,
             keep_crit_mem_prot: false,
+            iocost: None,
+            no_iocost_preset: false,
+            doctor: false,
+            audit: false,
+            clear_slice: None,
+            keep_running: false,
             verbosity: 0,
             bandit: None,
         }
@@ -283,6 +318,38 @@ impl JsonArgs for Args {
             updated_base = true;
         }
 
+        if let Some(v) = matches.value_of("io-ctrl-ignore") {
+            self.io_ctrl_ignore = v
+                .split(',')
+                .filter(|x| x.len() > 0)
+                .map(|x| x.trim_matches('/').to_string())
+                .collect();
+            updated_base = true;
+        }
+
+        if let Some(v) = matches.value_of("cgroup-root") {
+            self.cgroup_root = match v.trim_end_matches('/') {
+                "" => dfl.cgroup_root.clone(),
+                v => v.to_string(),
+            };
+            updated_base = true;
+        }
+
+        if let Some(v) = matches.value_of("max-concurrent-fixes") {
+            self.max_concurrent_fixes = if v.len() > 0 {
+                match v
+                    .parse::<u64>()
+                    .expect("failed to parse \"max-concurrent-fixes\"")
+                {
+                    0 => panic!("\"max-concurrent-fixes\" must be positive"),
+                    v => Some(v),
+                }
+            } else {
+                None
+            };
+            updated_base = true;
+        }
+
         self.no_iolat = matches.is_present("no-iolat");
         self.force = matches.is_present("force");
         self.force_running = matches.is_present("force-running");
@@ -293,6 +360,13 @@ impl JsonArgs for Args {
         self.keep_reports = matches.is_present("keep-reports");
         self.verbosity = Self::verbosity(&matches);
         self.bypass = matches.is_present("bypass");
+        self.doctor = matches.is_present("doctor");
+        self.audit = matches.is_present("audit");
+        self.clear_slice = matches.value_of("clear-slice").map(|v| {
+            v.parse::<Slice>()
+                .unwrap_or_else(|e| panic!("Unknown --clear-slice value {:?} ({})", &v, &e))
+        });
+        self.keep_running = matches.is_present("keep-running");
         if let Some(v) = matches.value_of("passive") {
             self.passive = true;
             self.force = true;
@@ -305,6 +379,15 @@ impl JsonArgs for Args {
             }
         }
 
+        if let Some(v) = matches.value_of("iocost") {
+            self.iocost = match v {
+                "on" => Some(true),
+                "off" => Some(false),
+                v => panic!("Unknown --iocost value {:?}", &v),
+            };
+        }
+        self.no_iocost_preset = matches.is_present("no-iocost-preset");
+
         if let (bandit, Some(subm)) = matches.subcommand() {
             updated_base |= self.process_bandit(bandit, subm);
         }
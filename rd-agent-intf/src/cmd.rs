@@ -1,6 +1,6 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use util::*;
 
 use rd_hashd_intf;
@@ -50,6 +50,7 @@ lazy_static::lazy_static! {
 //  hashd[].weight: Relative weight between the two hashd instances
 //  sysloads{{}}: \"NAME\": \"DEF_ID\" pairs for active sysloads
 //  sideloads{{}}: \"NAME\": \"DEF_ID\" pairs for active sideloads
+//  frozen_slices[]: Slices to freeze with cgroup.freeze, e.g. \"sideload.slice\"
 //
 ",
                 dfl_bench_balloon = Cmd::default().bench_hashd_balloon_size,
@@ -114,6 +115,8 @@ pub struct Cmd {
     pub sideloads: BTreeMap<String, String>,
     pub swappiness: Option<u32>,
     pub balloon_ratio: f64,
+    #[serde(default)]
+    pub frozen_slices: BTreeSet<String>,
 }
 
 impl Cmd {
@@ -136,6 +139,7 @@ impl Default for Cmd {
             sideloads: BTreeMap::new(),
             swappiness: None,
             balloon_ratio: 0.0,
+            frozen_slices: Default::default(),
         }
     }
 }
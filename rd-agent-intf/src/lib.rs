@@ -24,14 +24,18 @@ pub use cmd_ack::CmdAck;
 pub use index::Index;
 pub use oomd::{OomdKnobs, OomdSliceMemPressureKnobs, OomdSliceSenpaiKnobs};
 pub use report::{
-    BenchHashdReport, BenchIoCostReport, HashdReport, IoCostModelReport, IoCostQoSReport,
-    IoCostReport, IoLatReport, OomdReport, Report, ReportIter, ReportPathIter, ResCtlReport,
-    SideloadReport, SideloaderReport, StatMap, SvcReport, SvcStateReport, SysloadReport,
-    UsageReport,
+    BenchHashdReport, BenchIoCostReport, CpuStatReport, HashdReport, IoCostModelReport,
+    IoCostQoSReport, IoCostReport, IoLatReport, IoStatReport, OomdReport, PsiReport, Report,
+    ReportIter, ReportPathIter, ResCtlReport, SideloadReport, SideloaderReport, StatMap, SvcReport,
+    SvcStateReport, SysloadReport, UsageReport,
 };
 pub use side_defs::{SideloadDefs, SideloadSpec};
-pub use slices::{DisableSeqKnobs, MemoryKnob, Slice, SliceConfig, SliceKnobs, ROOT_SLICE};
-pub use sysreqs::{SysReq, SysReqsReport, ALL_SYSREQS_SET};
+pub use slices::{
+    DisableSeqKnobs, MemoryKnob, Slice, SliceConfig, SliceKnobs, WorkSenpaiKnobs, ROOT_SLICE,
+};
+pub use sysreqs::{
+    CpuFreqInfo, SysReq, SysReqCategory, SysReqSeverity, SysReqsReport, ALL_SYSREQS_SET,
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RunnerState {
@@ -140,4 +144,9 @@ impl AgentFiles {
         Self::refresh_one(&mut self.slices, &index.slices);
         Self::refresh_one(&mut self.oomd, &index.oomd);
     }
+
+    /// The vrate from the latest report, `None` if iocost is disabled.
+    pub fn current_vrate(&self) -> Option<f64> {
+        self.report.data.iocost.current_vrate()
+    }
 }
@@ -20,6 +20,10 @@ const REPORT_DOC: &str = "\
 //  timestamp: When this report was generated
 //  seq: Incremented on each execution, used for temporary settings
 //  state: Idle, Running, BenchHashd or BenchIoCost
+//  resctl.work_senpai_mem_high: workload.slice memory.high chosen by the
+//                               agent's senpai, absent if not enabled
+//  resctl.fixes: Number of cgroup values corrected since the agent started,
+//                keyed by SLICE/FIELD
 //  oomd.svc.name: OOMD systemd service name
 //  oomd.svc.state: OOMD systemd service state
 //  oomd.work_mem_pressure: Memory pressure based kill enabled in workload.slice
@@ -53,6 +57,14 @@ const REPORT_DOC: &str = "\
 //  iocost.model: iocost model parameters currently in effect
 //  iocost.qos: iocost QoS parameters currently in effect
 //  swappiness: vm.swappiness
+//  psi{}.{cpu|mem|io}: Per-slice some/full avg10 pressures, absent without PSI
+//  psi{}.{cpu|mem|io}_total: Per-slice cumulative some/full stall seconds
+//  slice_io{}.{rbytes|wbytes|rios|wios}: Cumulative per-slice io.stat counters
+//                                        for the scratch device
+//  cpu_stat[].{busy|total}: Cumulative per-CPU busy and total seconds
+//  mem_events{}: Cumulative per-slice memory.events counters
+//  nr_ctxsw_all: Cumulative number of all, voluntary and involuntary, context
+//                switches, /proc/stat ctxt
 //  iolat.{read|write|discard|flush}.p*: IO latency distributions
 //  iolat_cum.{read|write|discard|flush}.p*: Cumulative IO latency distributions
 //
@@ -84,6 +96,10 @@ pub struct ResCtlReport {
     pub cpu: bool,
     pub mem: bool,
     pub io: bool,
+    #[serde(default)]
+    pub work_senpai_mem_high: Option<u64>,
+    #[serde(default)]
+    pub fixes: BTreeMap<String, u64>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Default)]
@@ -436,6 +452,14 @@ impl<T: Into<f64>> ops::DivAssign<T> for IoCostReport {
 }
 
 impl IoCostReport {
+    /// The vrate in effect, `None` if iocost is disabled.
+    pub fn current_vrate(&self) -> Option<f64> {
+        match self.qos.enable {
+            0 => None,
+            _ => Some(self.vrate),
+        }
+    }
+
     pub fn read(devnr: (u32, u32)) -> Result<Self> {
         let kf = read_cgroup_nested_keyed_file("/sys/fs/cgroup/io.stat")?;
         let vrate = match kf.get(&format!("{}:{}", devnr.0, devnr.1)) {
@@ -456,6 +480,78 @@ impl IoCostReport {
 
 pub type StatMap = BTreeMap<String, f64>;
 
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PsiReport {
+    pub cpu: Option<(f64, f64)>,
+    pub mem: Option<(f64, f64)>,
+    pub io: Option<(f64, f64)>,
+    pub cpu_total: Option<(f64, f64)>,
+    pub mem_total: Option<(f64, f64)>,
+    pub io_total: Option<(f64, f64)>,
+}
+
+impl PsiReport {
+    /// Average some/full pressures over the `dur` seconds since `base`,
+    /// computed from the cumulative stall times. The totals are left empty.
+    pub fn pressure_since(&self, base: &Self, dur: f64) -> Self {
+        let avg = |end: &Option<(f64, f64)>, start: &Option<(f64, f64)>| match (end, start) {
+            (Some(end), Some(start)) if dur > 0.0 => Some((
+                ((end.0 - start.0) / dur).max(0.0),
+                ((end.1 - start.1) / dur).max(0.0),
+            )),
+            _ => None,
+        };
+        Self {
+            cpu: avg(&self.cpu_total, &base.cpu_total),
+            mem: avg(&self.mem_total, &base.mem_total),
+            io: avg(&self.io_total, &base.io_total),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IoStatReport {
+    pub rbytes: u64,
+    pub wbytes: u64,
+    pub rios: u64,
+    pub wios: u64,
+}
+
+impl IoStatReport {
+    /// Counter increase from `base`, zero for counters which went
+    /// backwards, e.g. because the cgroup was recreated.
+    pub fn delta(&self, base: &Self) -> Self {
+        Self {
+            rbytes: self.rbytes.saturating_sub(base.rbytes),
+            wbytes: self.wbytes.saturating_sub(base.wbytes),
+            rios: self.rios.saturating_sub(base.rios),
+            wios: self.wios.saturating_sub(base.wios),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CpuStatReport {
+    pub busy: f64,
+    pub total: f64,
+}
+
+impl CpuStatReport {
+    /// Utilization between `base` and `self`, zero if no time passed.
+    pub fn util_since(&self, base: &Self) -> f64 {
+        let total = self.total - base.total;
+        if total > 0.0 {
+            ((self.busy - base.busy) / total).max(0.0).min(1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Report {
     pub timestamp: DateTime<Local>,
@@ -477,6 +573,16 @@ pub struct Report {
     pub iolat_cum: IoLatReport,
     pub iocost: IoCostReport,
     pub swappiness: u32,
+    #[serde(default)]
+    pub psi: BTreeMap<String, PsiReport>,
+    #[serde(default)]
+    pub slice_io: BTreeMap<String, IoStatReport>,
+    #[serde(default)]
+    pub cpu_stat: Vec<CpuStatReport>,
+    #[serde(default, alias = "nr_ctxsw")]
+    pub nr_ctxsw_all: u64,
+    #[serde(default)]
+    pub mem_events: BTreeMap<String, StatMap>,
 }
 
 impl Default for Report {
@@ -501,6 +607,11 @@ impl Default for Report {
             iolat_cum: Default::default(),
             iocost: Default::default(),
             swappiness: 60,
+            psi: Default::default(),
+            slice_io: Default::default(),
+            cpu_stat: Default::default(),
+            nr_ctxsw_all: 0,
+            mem_events: Default::default(),
         }
     }
 }
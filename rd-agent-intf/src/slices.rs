@@ -1,5 +1,5 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
-use anyhow::Result;
+use anyhow::{bail, Result};
 use enum_iterator::IntoEnumIterator;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -12,7 +12,14 @@ const SLICE_DOC: &str = "\
 //
 // rd-agent top-level systemd slice resource configurations
 //
-// Memory configuration can be either None or Bytes.
+// Memory configuration can be either None, Bytes or PercentOfParent.
+// PercentOfParent is resolved against the parent cgroup's memory.max or
+// memory.high, whichever is lower, or total memory if both are unlimited.
+//
+// Besides the built-in slices, SLICE_ID can be any other top-level
+// \"*.slice\" without a dash, which then gets the same cpu, io and memory
+// configurations when all controls are enforced. Memory protection isn't
+// propagated and oom_group is ignored for them.
 //
 //  disable_seqs.cpu: Disable CPU control if >= report::seq
 //  disable_seqs.mem: Disable memory control if >= report::seq
@@ -22,6 +29,16 @@ const SLICE_DOC: &str = "\
 //  slices.SLICE_ID.mem_min: memory.min
 //  slices.SLICE_ID.mem_low: memory.low
 //  slices.SLICE_ID.mem_high: memory.high
+//  slices.SLICE_ID.propagate_mem_min: Propagate memory.min to all descendants
+//  slices.SLICE_ID.propagate_mem_low: Propagate memory.low to all descendants
+//  slices.SLICE_ID.oom_group: memory.oom.group, null leaves it untouched
+//  work_senpai.enable: Drive workload.slice memory.high toward a pressure
+//                      target instead of verifying the static mem_high,
+//                      ignored if oomd senpai is enabled on workload.slice
+//  work_senpai.target_pressure: Target memory some pressure (avg10, fraction)
+//  work_senpai.min_bytes_frac: Lower bound of memory.high in total memory fraction
+//  work_senpai.max_bytes_frac: Upper bound of memory.high in total memory fraction
+//  work_senpai.step: Fraction memory.high is adjusted by on each verification
 //
 ";
 
@@ -47,15 +64,32 @@ impl Slice {
         }
     }
 
-    pub fn cgrp(&self) -> &'static str {
-        match self {
-            Slice::Init => "/sys/fs/cgroup/init.scope",
-            Slice::Host => "/sys/fs/cgroup/hostcritical.slice",
-            Slice::User => "/sys/fs/cgroup/user.slice",
-            Slice::Sys => "/sys/fs/cgroup/system.slice",
-            Slice::Work => "/sys/fs/cgroup/workload.slice",
-            Slice::Side => "/sys/fs/cgroup/sideload.slice",
+    /// Path of the slice under the cgroup2 root `root`, see
+    /// Args::cgroup_root.
+    pub fn cgrp(&self, root: &str) -> String {
+        format!("{}/{}", root, self.name())
+    }
+
+    /// Name without the ".slice" or ".scope" suffix, e.g. "workload".
+    pub fn short_name(&self) -> &'static str {
+        let name = self.name();
+        name.strip_suffix(".slice")
+            .or_else(|| name.strip_suffix(".scope"))
+            .unwrap_or(name)
+    }
+}
+
+impl std::str::FromStr for Slice {
+    type Err = anyhow::Error;
+
+    /// Accepts both the full unit name and the short name.
+    fn from_str(input: &str) -> Result<Self> {
+        for slice in Slice::into_enum_iter() {
+            if input == slice.name() || input == slice.short_name() {
+                return Ok(slice);
+            }
         }
+        bail!("unknown slice {:?}", input);
     }
 }
 
@@ -63,6 +97,7 @@ impl Slice {
 pub enum MemoryKnob {
     None,
     Bytes(u64),
+    PercentOfParent(f64),
 }
 
 impl Default for MemoryKnob {
@@ -72,7 +107,27 @@ impl Default for MemoryKnob {
 }
 
 impl MemoryKnob {
+    /// Percentages are resolved against total memory. Use
+    /// `nr_bytes_of_parent()` to resolve against the parent cgroup.
     pub fn nr_bytes(&self, is_limit: bool) -> u64 {
+        self.nr_bytes_of_parent(is_limit, None)
+    }
+
+    /// `parent_limit` is the lower of the parent's memory.max and
+    /// memory.high, `None` if the parent is unlimited, in which case total
+    /// memory is used instead.
+    pub fn nr_bytes_of_parent(&self, is_limit: bool, parent_limit: Option<u64>) -> u64 {
+        self.nr_bytes_with_total(is_limit, parent_limit, total_memory() as u64)
+    }
+
+    /// Same as `nr_bytes_of_parent()` but against the specified amount of
+    /// total memory instead of the machine's.
+    pub fn nr_bytes_with_total(
+        &self,
+        is_limit: bool,
+        parent_limit: Option<u64>,
+        total: u64,
+    ) -> u64 {
         let nocfg = match is_limit {
             true => std::u64::MAX,
             false => 0,
@@ -80,6 +135,10 @@ impl MemoryKnob {
         match self {
             Self::None => nocfg,
             Self::Bytes(s) => *s,
+            Self::PercentOfParent(pct) => {
+                let base = parent_limit.unwrap_or(std::u64::MAX).min(total);
+                (base as f64 * pct.max(0.0).min(100.0) / 100.0) as u64
+            }
         }
     }
 }
@@ -91,6 +150,13 @@ pub struct SliceConfig {
     pub mem_min: MemoryKnob,
     pub mem_low: MemoryKnob,
     pub mem_high: MemoryKnob,
+    // Only consulted for slices which need memory protection propagation.
+    #[serde(default = "SliceConfig::dfl_propagate_mem_prot")]
+    pub propagate_mem_min: bool,
+    #[serde(default = "SliceConfig::dfl_propagate_mem_prot")]
+    pub propagate_mem_low: bool,
+    #[serde(default)]
+    pub oom_group: Option<bool>,
 }
 
 impl Default for SliceConfig {
@@ -101,6 +167,9 @@ impl Default for SliceConfig {
             mem_min: Default::default(),
             mem_low: Default::default(),
             mem_high: Default::default(),
+            propagate_mem_min: Self::dfl_propagate_mem_prot(),
+            propagate_mem_low: Self::dfl_propagate_mem_prot(),
+            oom_group: None,
         }
     }
 }
@@ -110,6 +179,10 @@ impl SliceConfig {
     pub const DFL_SYS_IO_RATIO: f64 = 0.1;
     pub const DFL_MEM_MARGIN: f64 = 0.25;
 
+    fn dfl_propagate_mem_prot() -> bool {
+        true
+    }
+
     pub fn dfl_mem_margin(total: usize, fb_prod: bool) -> u64 {
         let margin = (total as f64 * Self::DFL_MEM_MARGIN) as u64;
         if fb_prod {
@@ -168,11 +241,34 @@ pub struct DisableSeqKnobs {
     pub io: u64,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkSenpaiKnobs {
+    pub enable: bool,
+    pub target_pressure: f64,
+    pub min_bytes_frac: f64,
+    pub max_bytes_frac: f64,
+    pub step: f64,
+}
+
+impl Default for WorkSenpaiKnobs {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            target_pressure: 0.01,
+            min_bytes_frac: 0.25,
+            max_bytes_frac: 1.0,
+            step: 0.02,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default)]
 pub struct SliceKnobs {
     pub disable_seqs: DisableSeqKnobs,
     pub slices: BTreeMap<String, SliceConfig>,
+    pub work_senpai: WorkSenpaiKnobs,
     #[serde(skip)]
     pub work_mem_low_none: bool,
 }
@@ -186,6 +282,7 @@ impl Default for SliceKnobs {
         Self {
             disable_seqs: Default::default(),
             slices,
+            work_senpai: Default::default(),
             work_mem_low_none: false,
         }
     }
@@ -193,6 +290,21 @@ impl Default for SliceKnobs {
 
 impl JsonLoad for SliceKnobs {
     fn loaded(&mut self, _prev: Option<&mut Self>) -> Result<()> {
+        for (name, _) in self.custom_slices() {
+            // A dash nests the slice in systemd, e.g. a-b.slice lives
+            // under a.slice, and only top-level slices are supported.
+            let stem = name.strip_suffix(".slice").unwrap_or("");
+            if stem.len() == 0 || stem.contains('/') || name == ROOT_SLICE {
+                bail!("invalid custom slice name {:?}", name);
+            }
+            if stem.contains('-') {
+                bail!(
+                    "custom slice {:?} must be top-level, nested slices aren't supported",
+                    name
+                );
+            }
+        }
+
         let sk = self.slices.get(Slice::Work.name()).unwrap();
         self.work_mem_low_none = if let MemoryKnob::None = sk.mem_low {
             true
@@ -214,6 +326,16 @@ impl SliceKnobs {
         let dseqs = &self.disable_seqs;
         dseqs.cpu >= seq || dseqs.mem >= seq || dseqs.io >= seq
     }
+
+    /// User-defined entries in `slices`, i.e. the ones which don't belong
+    /// to a built-in `Slice`.
+    pub fn custom_slices(&self) -> Vec<(&str, &SliceConfig)> {
+        self.slices
+            .iter()
+            .filter(|(name, _)| name.parse::<Slice>().is_err())
+            .map(|(name, sk)| (name.as_str(), sk))
+            .collect()
+    }
 }
 
 impl Index<Slice> for SliceKnobs {
@@ -229,3 +351,87 @@ impl IndexMut<Slice> for SliceKnobs {
         self.slices.get_mut(slc.name()).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryKnob, Slice, SliceKnobs};
+    use enum_iterator::IntoEnumIterator;
+
+    #[test]
+    fn test_slice_from_str() {
+        for slice in Slice::into_enum_iter() {
+            assert_eq!(slice.name().parse::<Slice>().unwrap(), slice);
+            assert_eq!(slice.short_name().parse::<Slice>().unwrap(), slice);
+        }
+        assert_eq!("workload".parse::<Slice>().unwrap(), Slice::Work);
+        assert_eq!("init.scope".parse::<Slice>().unwrap(), Slice::Init);
+        assert!("".parse::<Slice>().is_err());
+        assert!("foo.slice".parse::<Slice>().is_err());
+        assert!("workload.scope".parse::<Slice>().is_err());
+    }
+
+    #[test]
+    fn test_slice_knobs_custom_slices() {
+        let mut knobs = SliceKnobs::default();
+        assert!(knobs.custom_slices().is_empty());
+        knobs
+            .slices
+            .insert("batch.slice".into(), Default::default());
+        let custom = knobs.custom_slices();
+        assert_eq!(custom.len(), 1);
+        assert_eq!(custom[0].0, "batch.slice");
+    }
+
+    #[test]
+    fn test_memory_knob_percent_of_finite_parent() {
+        let total = 16 << 30;
+        let parent = 8 << 30;
+        let knob = MemoryKnob::PercentOfParent(50.0);
+        assert_eq!(
+            knob.nr_bytes_with_total(false, Some(parent), total),
+            parent / 2
+        );
+        assert_eq!(
+            knob.nr_bytes_with_total(true, Some(parent), total),
+            parent / 2
+        );
+        assert_eq!(
+            MemoryKnob::PercentOfParent(150.0).nr_bytes_with_total(true, Some(parent), total),
+            parent
+        );
+    }
+
+    #[test]
+    fn test_memory_knob_with_total() {
+        let total = 16 << 30;
+        let knob = MemoryKnob::PercentOfParent(50.0);
+        assert_eq!(knob.nr_bytes_with_total(false, None, total), 8 << 30);
+        assert_eq!(
+            knob.nr_bytes_with_total(false, Some(4 << 30), total),
+            2 << 30
+        );
+        assert_eq!(
+            knob.nr_bytes_with_total(false, Some(32 << 30), total),
+            8 << 30
+        );
+        assert_eq!(
+            MemoryKnob::Bytes(1 << 30).nr_bytes_with_total(false, None, total),
+            1 << 30
+        );
+        assert_eq!(
+            MemoryKnob::None.nr_bytes_with_total(true, None, total),
+            std::u64::MAX
+        );
+    }
+
+    #[test]
+    fn test_memory_knob_percent_of_unlimited_parent() {
+        let total = 16 << 30;
+        let knob = MemoryKnob::PercentOfParent(25.0);
+        assert_eq!(knob.nr_bytes_with_total(false, None, total), 4 << 30);
+        assert_eq!(
+            knob.nr_bytes_with_total(false, Some(std::u64::MAX), total),
+            knob.nr_bytes_with_total(false, None, total)
+        );
+    }
+}
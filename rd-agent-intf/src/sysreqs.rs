@@ -1,7 +1,7 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
 use enum_iterator::IntoEnumIterator;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use util::*;
 
 const SYSREQ_DOC: &str = "\
@@ -13,6 +13,11 @@ const SYSREQ_DOC: &str = "\
 // scr_dev_model: Scratch storage device model string
 // scr_dev_fwrev: Scratch storage device firmware revision string
 // scr_dev_size: Scratch storage device size
+// scr_dev_rotational: Whether the scratch storage device is rotational
+// scr_dev_lbs: Scratch storage device logical block size
+// scr_dev_pbs: Scratch storage device physical block size
+// scr_fstype: Scratch filesystem type
+// cpufreq: Per-CPU cpufreq governor and min/max frequencies in kHz
 // swap_size: Swap size
 //
 ";
@@ -40,6 +45,7 @@ pub enum SysReq {
     MemCgRecursiveProt,
     IoCost,
     IoCostVer,
+    IoWeight,
     NoOtherIoControllers,
     AnonBalance,
     Btrfs,
@@ -55,6 +61,91 @@ pub enum SysReq {
     Dependencies,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum SysReqSeverity {
+    // Resource control results are not meaningful without it.
+    Fatal,
+    // Results may be skewed or less reliable.
+    Advisory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum SysReqCategory {
+    System,
+    Mem,
+    Io,
+    Oomd,
+}
+
+impl SysReq {
+    pub fn severity(&self) -> SysReqSeverity {
+        match self {
+            Self::Controllers
+            | Self::Freezer
+            | Self::MemCgRecursiveProt
+            | Self::IoCost
+            | Self::IoCostVer
+            | Self::IoWeight
+            | Self::Swap
+            | Self::Oomd
+            | Self::Dependencies => SysReqSeverity::Fatal,
+            Self::NoOtherIoControllers
+            | Self::AnonBalance
+            | Self::Btrfs
+            | Self::BtrfsAsyncDiscard
+            | Self::NoCompositeStorage
+            | Self::IoSched
+            | Self::NoWbt
+            | Self::SwapOnScratch
+            | Self::NoSysOomd
+            | Self::HostCriticalServices => SysReqSeverity::Advisory,
+        }
+    }
+
+    pub fn category(&self) -> SysReqCategory {
+        match self {
+            Self::Controllers | Self::Freezer | Self::HostCriticalServices | Self::Dependencies => {
+                SysReqCategory::System
+            }
+            Self::MemCgRecursiveProt | Self::AnonBalance | Self::Swap | Self::SwapOnScratch => {
+                SysReqCategory::Mem
+            }
+            Self::IoCost
+            | Self::IoCostVer
+            | Self::IoWeight
+            | Self::NoOtherIoControllers
+            | Self::Btrfs
+            | Self::BtrfsAsyncDiscard
+            | Self::NoCompositeStorage
+            | Self::IoSched
+            | Self::NoWbt => SysReqCategory::Io,
+            Self::Oomd | Self::NoSysOomd => SysReqCategory::Oomd,
+        }
+    }
+
+    /// Group `reqs` by severity and category, most severe first.
+    pub fn classify<'a, I>(reqs: I) -> BTreeMap<(SysReqSeverity, SysReqCategory), Vec<SysReq>>
+    where
+        I: IntoIterator<Item = &'a SysReq>,
+    {
+        let mut groups = BTreeMap::<_, Vec<SysReq>>::new();
+        for req in reqs.into_iter() {
+            groups
+                .entry((req.severity(), req.category()))
+                .or_default()
+                .push(*req);
+        }
+        groups
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CpuFreqInfo {
+    pub governor: String,
+    pub min_freq: u64,
+    pub max_freq: u64,
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct SysReqsReport {
     pub satisfied: BTreeSet<SysReq>,
@@ -68,7 +159,17 @@ pub struct SysReqsReport {
     pub scr_dev_model: String,
     pub scr_dev_fwrev: String,
     pub scr_dev_size: u64,
+    #[serde(default)]
+    pub scr_dev_rotational: Option<bool>,
+    #[serde(default)]
+    pub scr_dev_lbs: Option<u64>,
+    #[serde(default)]
+    pub scr_dev_pbs: Option<u64>,
     pub scr_dev_iosched: String,
+    #[serde(default)]
+    pub scr_fstype: String,
+    #[serde(default)]
+    pub cpufreq: BTreeMap<usize, CpuFreqInfo>,
 }
 
 impl JsonLoad for SysReqsReport {}
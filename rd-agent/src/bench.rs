@@ -14,8 +14,88 @@ use rd_agent_intf::{Slice, HASHD_BENCH_SVC_NAME, IOCOST_BENCH_SVC_NAME};
 
 use super::{hashd, Config, HashdSel};
 
-pub const IOCOST_QOS_PATH: &str = "/sys/fs/cgroup/io.cost.qos";
-const IOCOST_MODEL_PATH: &str = "/sys/fs/cgroup/io.cost.model";
+pub fn iocost_qos_path(cfg: &Config) -> String {
+    format!("{}/io.cost.qos", &cfg.cgrp_root)
+}
+
+fn iocost_model_path(cfg: &Config) -> String {
+    format!("{}/io.cost.model", &cfg.cgrp_root)
+}
+
+// Ballpark linear models for some common devices, used as the starting
+// point when iocost hasn't been benchmarked yet. Matched as case-insensitive
+// substrings of the device model string in order.
+//
+// The SSD numbers are the vendors' product spec sheet ratings: sequential
+// read/write MB/s for [rw]bps and 4KiB QD32 random read/write IOPS for
+// [rw]randiops. The spec sheets don't rate 4KiB sequential IOPS and
+// [rw]seqiops are set to the random ratings. For models sold in several
+// capacities, the ratings of the following capacity are used.
+//
+//   Samsung SSD 970 EVO   - 1TB, Samsung 970 EVO NVMe M.2 SSD data sheet
+//   Samsung SSD 860       - 1TB, Samsung 860 EVO SATA SSD data sheet
+//   INTEL SSDPE2KX        - 2TB, Intel SSD DC P4510 product brief
+//   WDC WD40EFRX          - WD Red NAS hard drive spec sheet, which only
+//                           rates the 150MB/s sustained transfer rate. The
+//                           IOPS are rough estimates for a 5400rpm class
+//                           drive.
+//
+// Spec sheet ratings are peak numbers and usually overestimate what the
+// device sustains. Benchmark iocost for anything more than a starting
+// point. --no-iocost-preset restores the kernel default model.
+const IOCOST_MODEL_PRESETS: &[(&str, IoCostModelParams)] = &[
+    (
+        "Samsung SSD 970 EVO",
+        IoCostModelParams {
+            rbps: 3_400_000_000,
+            rseqiops: 500_000,
+            rrandiops: 500_000,
+            wbps: 2_500_000_000,
+            wseqiops: 480_000,
+            wrandiops: 480_000,
+        },
+    ),
+    (
+        "Samsung SSD 860",
+        IoCostModelParams {
+            rbps: 550_000_000,
+            rseqiops: 98_000,
+            rrandiops: 98_000,
+            wbps: 520_000_000,
+            wseqiops: 90_000,
+            wrandiops: 90_000,
+        },
+    ),
+    (
+        "INTEL SSDPE2KX",
+        IoCostModelParams {
+            rbps: 3_200_000_000,
+            rseqiops: 637_000,
+            rrandiops: 637_000,
+            wbps: 2_000_000_000,
+            wseqiops: 81_500,
+            wrandiops: 81_500,
+        },
+    ),
+    (
+        "WDC WD40EFRX",
+        IoCostModelParams {
+            rbps: 150_000_000,
+            rseqiops: 300,
+            rrandiops: 180,
+            wbps: 150_000_000,
+            wseqiops: 300,
+            wrandiops: 180,
+        },
+    ),
+];
+
+fn iocost_model_preset(dev_model: &str) -> Option<&'static (&'static str, IoCostModelParams)> {
+    let dev_model = dev_model.to_lowercase();
+    IOCOST_MODEL_PRESETS
+        .iter()
+        .find(|(pat, _)| dev_model.contains(&pat.to_lowercase()))
+}
 
 pub fn start_hashd_bench(
     cfg: &Config,
@@ -139,7 +219,7 @@ pub fn iocost_on_off(enable: bool, cfg: &Config) -> Result<()> {
         return Ok(());
     }
     write_one_line(
-        IOCOST_QOS_PATH,
+        &iocost_qos_path(cfg),
         &format!(
             "{}:{} enable={}",
             cfg.scr_devnr.0,
@@ -153,16 +233,68 @@ pub fn apply_iocost(knobs: &BenchKnobs, cfg: &Config) -> Result<()> {
     if !cfg.enforce.all {
         return Ok(());
     }
+    // Measured models always take precedence over the presets.
     if knobs.iocost_seq == 0 {
-        info!(
-            "iocost: Enabling on {:?} with default parameters",
-            &cfg.scr_dev
-        );
+        let dev_model = match devname_to_model_fwrev_size(&cfg.scr_dev) {
+            Ok((model, _, _)) => model,
+            Err(e) => {
+                warn!(
+                    "iocost: Failed to read the device model of {:?} ({:#})",
+                    &cfg.scr_dev, &e
+                );
+                String::new()
+            }
+        };
+        match iocost_model_preset(&dev_model) {
+            Some((name, model)) if cfg.iocost_preset => {
+                info!(
+                    "iocost: Enabling on {:?} with preset parameters for {:?}",
+                    &cfg.scr_dev, name
+                );
+                write_iocost_model(model, cfg)?;
+            }
+            preset => {
+                match preset {
+                    Some(_) => info!(
+                        "iocost: Enabling on {:?} with default parameters, presets disabled",
+                        &cfg.scr_dev
+                    ),
+                    None => info!(
+                        "iocost: Enabling on {:?} with default parameters, no preset for {:?}",
+                        &cfg.scr_dev,
+                        dev_model.trim()
+                    ),
+                }
+                // A preset model from an earlier run stays in effect
+                // until replaced. Restore the kernel default.
+                let (maj, min) = cfg.scr_devnr;
+                write_one_line(
+                    &iocost_model_path(cfg),
+                    &format!("{}:{} ctrl=auto", maj, min),
+                )?;
+            }
+        }
         return iocost_on_off(true, cfg);
     }
 
+    info!(
+        "iocost: Enabling on {:?} with benchmarked parameters",
+        &cfg.scr_dev
+    );
+    write_iocost_model(&knobs.iocost.model, cfg)?;
+
+    let (maj, min) = cfg.scr_devnr;
+    let qos = &knobs.iocost.qos;
+    let qos_line = format!(
+        "{}:{} rpct={:.2} rlat={} wpct={:.2} wlat={} min={:.2} max={:.2}",
+        maj, min, qos.rpct, qos.rlat, qos.wpct, qos.wlat, qos.min, qos.max
+    );
+    debug!("iocost.qos: {}", &qos_line);
+    write_one_line(&iocost_qos_path(cfg), &qos_line)
+}
+
+fn write_iocost_model(model: &IoCostModelParams, cfg: &Config) -> Result<()> {
     let (maj, min) = cfg.scr_devnr;
-    let model = &knobs.iocost.model;
     let model_line = format!(
         "{}:{} model=linear rbps={} rseqiops={} rrandiops={} wbps={} wseqiops={} wrandiops={}",
         maj,
@@ -174,18 +306,6 @@ pub fn apply_iocost(knobs: &BenchKnobs, cfg: &Config) -> Result<()> {
         model.wseqiops,
         model.wrandiops
     );
-    info!(
-        "iocost: Enabling on {:?} with benchmarked parameters",
-        &cfg.scr_dev
-    );
     debug!("iocost.model: {}", &model_line);
-    write_one_line(IOCOST_MODEL_PATH, &model_line)?;
-
-    let qos = &knobs.iocost.qos;
-    let qos_line = format!(
-        "{}:{} rpct={:.2} rlat={} wpct={:.2} wlat={} min={:.2} max={:.2}",
-        maj, min, qos.rpct, qos.rlat, qos.wpct, qos.wlat, qos.min, qos.max
-    );
-    debug!("iocost.qos: {}", &qos_line);
-    write_one_line(IOCOST_QOS_PATH, &qos_line)
+    write_one_line(&iocost_model_path(cfg), &model_line)
 }
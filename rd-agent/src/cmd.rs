@@ -1,5 +1,6 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
 use anyhow::{Context, Result};
+use enum_iterator::IntoEnumIterator;
 use log::{debug, error, info, warn};
 use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
@@ -26,6 +27,13 @@ pub struct RunnerData {
     warned_bench: bool,
     warned_init: bool,
     force_apply: bool,
+    frozen_slices: Vec<Slice>,
+    // Frozen slices which the kernel hasn't finished freezing yet.
+    freezing: Vec<(Slice, Instant)>,
+    // Freezer updates which failed, not retried until the target changes.
+    freezer_failed: Vec<(Slice, bool)>,
+    // memory.high set by work_senpai on the last health check.
+    pub work_senpai_mem_high: Option<u64>,
 
     pub bench_hashd: Option<TransientService>,
     pub bench_iocost: Option<TransientService>,
@@ -44,6 +52,10 @@ impl RunnerData {
             warned_bench: false,
             warned_init: false,
             force_apply: false,
+            frozen_slices: vec![],
+            freezing: vec![],
+            freezer_failed: vec![],
+            work_senpai_mem_high: None,
             bench_hashd: None,
             bench_iocost: None,
             hashd_set: HashdSet::new(&cfg),
@@ -58,13 +70,21 @@ impl RunnerData {
         if self.bench_hashd.is_some() {
             svcs.insert((
                 HASHD_BENCH_SVC_NAME.to_owned(),
-                format!("{}/{}", Slice::Work.cgrp(), HASHD_BENCH_SVC_NAME),
+                format!(
+                    "{}/{}",
+                    Slice::Work.cgrp(&self.cfg.cgrp_root),
+                    HASHD_BENCH_SVC_NAME
+                ),
             ));
         }
         if self.bench_iocost.is_some() {
             svcs.insert((
                 IOCOST_BENCH_SVC_NAME.to_owned(),
-                format!("{}/{}", Slice::Work.cgrp(), HASHD_BENCH_SVC_NAME),
+                format!(
+                    "{}/{}",
+                    Slice::Work.cgrp(&self.cfg.cgrp_root),
+                    HASHD_BENCH_SVC_NAME
+                ),
             ));
         }
         for svc in self
@@ -206,6 +226,64 @@ impl RunnerData {
         Ok(())
     }
 
+    // Only thaw the slices we froze, leave the ones frozen by others alone.
+    fn apply_frozen_slices(&mut self) {
+        if !self.cfg.enforce.all {
+            return;
+        }
+        let root = &self.cfg.cgrp_root;
+        let target = &self.sobjs.cmd_file.data.frozen_slices;
+        for slice in Slice::into_enum_iter() {
+            let want = target.contains(slice.name());
+            let is = self.frozen_slices.contains(&slice);
+            self.freezer_failed
+                .retain(|(failed, failed_want)| *failed != slice || *failed_want == want);
+            if want == is || self.freezer_failed.contains(&(slice, want)) {
+                continue;
+            }
+            let res = match want {
+                true => slices::freeze_slice(slice, root),
+                false => slices::thaw_slice(slice, root),
+            };
+            // Record as soon as cgroup.freeze is written so that a slice
+            // which is slow to freeze still gets thawed later.
+            match (res, want) {
+                (Ok(()), true) => {
+                    info!("cmd: Freezing {}", slice.name());
+                    self.frozen_slices.push(slice);
+                    self.freezing.push((slice, Instant::now()));
+                }
+                (Ok(()), false) => {
+                    info!("cmd: Thawed {}", slice.name());
+                    self.frozen_slices.retain(|x| *x != slice);
+                    self.freezing.retain(|(x, _)| *x != slice);
+                }
+                (Err(e), _) => {
+                    warn!("cmd: Failed to update freezer state ({:#})", &e);
+                    self.freezer_failed.push((slice, want));
+                }
+            }
+        }
+
+        self.freezing.retain(
+            |(slice, started_at)| match slices::slice_frozen(*slice, root) {
+                Ok(true) => {
+                    info!("cmd: Froze {}", slice.name());
+                    false
+                }
+                _ if started_at.elapsed() >= slices::FREEZE_TIMEOUT => {
+                    warn!(
+                        "cmd: {} didn't become frozen in {}s",
+                        slice.name(),
+                        slices::FREEZE_TIMEOUT.as_secs()
+                    );
+                    false
+                }
+                _ => true,
+            },
+        );
+    }
+
     fn apply_workloads(&mut self) -> Result<()> {
         let cmd = &self.sobjs.cmd_file.data;
         let bench = &self.sobjs.bench_file.data;
@@ -222,6 +300,8 @@ impl RunnerData {
         removed_sysloads: &mut Vec<Sysload>,
         removed_sideloads: &mut Vec<Sideload>,
     ) -> Result<bool> {
+        self.apply_frozen_slices();
+
         let cmd = &self.sobjs.cmd_file.data;
         let bench = &self.sobjs.bench_file.data;
         let mut repeat = false;
@@ -379,6 +459,19 @@ impl RunnerData {
     }
 }
 
+// Thaw what we froze before the slice configurations are cleared in
+// SysObjs::drop() so that nothing is left frozen after exit.
+impl Drop for RunnerData {
+    fn drop(&mut self) {
+        for slice in self.frozen_slices.drain(..) {
+            match slices::thaw_slice(slice, &self.cfg.cgrp_root) {
+                Ok(()) => info!("cmd: Thawed {}", slice.name()),
+                Err(e) => warn!("cmd: Failed to thaw {} ({:#})", slice.name(), &e),
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Runner {
     pub data: Arc<Mutex<RunnerData>>,
@@ -449,12 +542,13 @@ impl Runner {
                 && (now.duration_since(last_health_check_at) >= HEALTH_CHECK_INTV || verify_pending)
             {
                 let workload_senpai = data.sobjs.oomd.workload_senpai_enabled();
-                if let Err(e) = slices::verify_and_fix_slices(
+                match slices::verify_and_fix_slices(
                     &data.sobjs.slice_file.data,
                     workload_senpai,
                     &data.cfg,
                 ) {
-                    warn!("cmd: Health check failed ({:?})", &e);
+                    Ok(high) => data.work_senpai_mem_high = high,
+                    Err(e) => warn!("cmd: Health check failed ({:?})", &e),
                 }
 
                 let iosched = match data.state {
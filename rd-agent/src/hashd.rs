@@ -229,6 +229,7 @@ impl Hashd {
 
 pub struct HashdSet {
     hashd: [Hashd; 2],
+    cgrp_root: String,
 }
 
 impl HashdSet {
@@ -258,6 +259,7 @@ impl HashdSet {
                     started_at: None,
                 },
             ],
+            cgrp_root: cfg.cgrp_root.clone(),
         }
     }
 
@@ -355,13 +357,13 @@ impl HashdSet {
         if self.hashd[0].svc.is_some() {
             svcs.insert((
                 HASHD_A_SVC_NAME.to_owned(),
-                format!("{}/{}", Slice::Work.cgrp(), HASHD_A_SVC_NAME),
+                format!("{}/{}", Slice::Work.cgrp(&self.cgrp_root), HASHD_A_SVC_NAME),
             ));
         }
         if self.hashd[1].svc.is_some() {
             svcs.insert((
                 HASHD_B_SVC_NAME.to_owned(),
-                format!("{}/{}", Slice::Work.cgrp(), HASHD_B_SVC_NAME),
+                format!("{}/{}", Slice::Work.cgrp(&self.cgrp_root), HASHD_B_SVC_NAME),
             ));
         }
         svcs
@@ -28,8 +28,8 @@ mod sideloader;
 mod slices;
 
 use rd_agent_intf::{
-    Args, BenchKnobs, Cmd, CmdAck, Report, SideloadDefs, SliceKnobs, SvcReport, SvcStateReport,
-    SysReq, SysReqsReport, ALL_SYSREQS_SET, OOMD_SVC_NAME,
+    Args, BenchKnobs, Cmd, CmdAck, CpuFreqInfo, OomdKnobs, Report, SideloadDefs, SliceKnobs,
+    SvcReport, SvcStateReport, SysReq, SysReqsReport, ALL_SYSREQS_SET, OOMD_SVC_NAME,
 };
 use report::clear_old_report_files;
 
@@ -138,6 +138,8 @@ pub struct EnforceConfig {
     pub all: bool,
     pub none: bool,
     pub crit_mem_prot: bool,
+    // Overrides the disable_seqs based iocost enable/disable if set.
+    pub iocost: Option<bool>,
 }
 
 #[derive(Debug)]
@@ -183,6 +185,22 @@ pub struct Config {
     pub bypass: bool,
     pub verbosity: u32,
     pub enforce: EnforceConfig,
+    // Don't stop start/stop slices when clearing their configurations.
+    pub keep_running: bool,
+    // Use the device model presets for io.cost.model until benchmarked.
+    pub iocost_preset: bool,
+    pub io_ctrl_ignore: Vec<glob::Pattern>,
+    // cgroup2 root slices.rs operates under, /sys/fs/cgroup unless running
+    // inside a delegated subtree.
+    pub cgrp_root: String,
+    // cgroup2 is mounted read-only, see slices::cgroup_read_only().
+    pub cgrp_read_only: bool,
+    // Wanted controllers missing from cgroup2, see
+    // slices::check_wanted_controllers().
+    pub missing_ctrls: BTreeSet<String>,
+    pub max_fixes_per_pass: Option<u64>,
+    // Total memory percentage memory knobs are resolved against.
+    pub mem_total: u64,
 
     pub sr_failed: BTreeSet<SysReq>,
     sr_wbt: Option<u64>,
@@ -192,7 +210,16 @@ pub struct Config {
 }
 
 impl Config {
-    fn prep_dir(path: &str) -> String {
+    // In read-only mode (--audit), nothing is created and missing paths are
+    // returned as-is.
+    fn prep_dir(path: &str, read_only: bool) -> String {
+        if read_only {
+            return match fs::canonicalize(path) {
+                Ok(p) => p.to_str().unwrap().to_string(),
+                Err(_) => path.to_string(),
+            };
+        }
+
         debug!("creating dir {:?}", &path);
 
         if let Err(e) = fs::create_dir_all(&path) {
@@ -279,17 +306,20 @@ impl Config {
 
     fn new(args_file: &JsonConfigFile<Args>) -> Self {
         let args = &args_file.data;
-        let top_path = Self::prep_dir(&args.dir);
-        if let Err(e) = Self::sgid_top(&top_path, args_file.path.as_ref()) {
-            info!(
-                "cfg: Failed to set group ownership on {:?} ({:?})",
-                &top_path, &e
-            );
+        let prep_dir = |path: &str| Self::prep_dir(path, args.audit);
+        let top_path = prep_dir(&args.dir);
+        if !args.audit {
+            if let Err(e) = Self::sgid_top(&top_path, args_file.path.as_ref()) {
+                info!(
+                    "cfg: Failed to set group ownership on {:?} ({:?})",
+                    &top_path, &e
+                );
+            }
         }
 
         let scr_path = match &args.scratch {
-            Some(scr) => Self::prep_dir(&scr),
-            None => Self::prep_dir(&(top_path.clone() + "/scratch")),
+            Some(scr) => prep_dir(&scr),
+            None => prep_dir(&(top_path.clone() + "/scratch")),
         };
 
         let scr_dev = match &args.dev {
@@ -325,7 +355,7 @@ impl Config {
         };
 
         let misc_bin_path = top_path.clone() + "/misc-bin";
-        Self::prep_dir(&misc_bin_path);
+        prep_dir(&misc_bin_path);
 
         let biolatpcts_bin = if args.no_iolat {
             None
@@ -336,14 +366,14 @@ impl Config {
         let side_bin_path = top_path.clone() + "/sideload-bin";
         let side_scr_path = scr_path.clone() + "/sideload";
         let sys_scr_path = scr_path.clone() + "/sysload";
-        Self::prep_dir(&side_bin_path);
-        Self::prep_dir(&side_scr_path);
-        Self::prep_dir(&sys_scr_path);
+        prep_dir(&side_bin_path);
+        prep_dir(&side_scr_path);
+        prep_dir(&sys_scr_path);
 
         let report_d_path = top_path.clone() + "/report.d";
         let report_1min_d_path = top_path.clone() + "/report-1min.d";
-        Self::prep_dir(&report_d_path);
-        Self::prep_dir(&report_1min_d_path);
+        prep_dir(&report_d_path);
+        prep_dir(&report_1min_d_path);
 
         let bench_path = top_path.clone()
             + "/"
@@ -352,24 +382,27 @@ impl Config {
                 Some(name) => name,
             };
 
-        Self::prep_dir(&(top_path.clone() + "/hashd-A"));
-        Self::prep_dir(&(top_path.clone() + "/hashd-B"));
-        Self::prep_dir(&(top_path.clone() + "/oomd"));
+        prep_dir(&(top_path.clone() + "/hashd-A"));
+        prep_dir(&(top_path.clone() + "/hashd-B"));
+        prep_dir(&(top_path.clone() + "/oomd"));
 
         let sideloader_jobs_d = top_path.clone() + "/sideloader/jobs.d";
-        Self::prep_dir(&sideloader_jobs_d);
-        for path in glob::glob(&format!("{}/*.json", &sideloader_jobs_d))
-            .unwrap()
-            .filter_map(Result::ok)
-        {
-            if let Err(e) = fs::remove_file(&path) {
-                error!(
-                    "cfg: Failed to remove stale sideloader job {:?} ({:?})",
-                    &path, &e
-                );
-                panic!();
-            } else {
-                debug!("cfg: Removed stale sideloader job {:?}", &path);
+        prep_dir(&sideloader_jobs_d);
+        // Audit must not touch anything, leave the stale jobs alone.
+        if !args.audit {
+            for path in glob::glob(&format!("{}/*.json", &sideloader_jobs_d))
+                .unwrap()
+                .filter_map(Result::ok)
+            {
+                if let Err(e) = fs::remove_file(&path) {
+                    error!(
+                        "cfg: Failed to remove stale sideloader job {:?} ({:?})",
+                        &path, &e
+                    );
+                    panic!();
+                } else {
+                    debug!("cfg: Removed stale sideloader job {:?}", &path);
+                }
             }
         }
 
@@ -394,7 +427,7 @@ impl Config {
                     args: top_path.clone() + "/hashd-A/args.json",
                     params: top_path.clone() + "/hashd-A/params.json",
                     report: top_path.clone() + "/hashd-A/report.json",
-                    tf: Self::prep_dir(&(scr_path.clone() + "/hashd-A/testfiles")),
+                    tf: prep_dir(&(scr_path.clone() + "/hashd-A/testfiles")),
                     log_dir: scr_path.clone() + "/hashd-A/logs",
                 },
                 HashdPaths {
@@ -402,7 +435,7 @@ impl Config {
                     args: top_path.clone() + "/hashd-B/args.json",
                     params: top_path.clone() + "/hashd-B/params.json",
                     report: top_path.clone() + "/hashd-B/report.json",
-                    tf: Self::prep_dir(&(scr_path.clone() + "/hashd-B/testfiles")),
+                    tf: prep_dir(&(scr_path.clone() + "/hashd-B/testfiles")),
                     log_dir: scr_path.clone() + "/hashd-B/logs",
                 },
             ],
@@ -410,7 +443,7 @@ impl Config {
             biolatpcts_bin,
             iocost_paths: IoCostPaths {
                 bin: misc_bin_path.clone() + "/iocost_coef_gen.py",
-                working: Self::prep_dir(&(scr_path.clone() + "/iocost-coef")),
+                working: prep_dir(&(scr_path.clone() + "/iocost-coef")),
                 result: scr_path.clone() + "/iocost-coef/iocost-coef.json",
             },
             oomd_bin,
@@ -443,11 +476,30 @@ impl Config {
             force_running: args.force_running,
             bypass: args.bypass,
             verbosity: args.verbosity,
+            keep_running: args.keep_running,
+            iocost_preset: !args.no_iocost_preset,
             enforce: EnforceConfig {
                 all: !args.passive,
                 none: !args.keep_crit_mem_prot,
                 crit_mem_prot: !args.passive || args.keep_crit_mem_prot,
+                iocost: args.iocost,
             },
+            max_fixes_per_pass: args.max_concurrent_fixes,
+            cgrp_root: args.cgroup_root.clone(),
+            cgrp_read_only: false,
+            missing_ctrls: Default::default(),
+            mem_total: total_memory() as u64,
+            io_ctrl_ignore: args
+                .io_ctrl_ignore
+                .iter()
+                .map(|x| match glob::Pattern::new(x) {
+                    Ok(pat) => pat,
+                    Err(e) => {
+                        error!("cfg: Invalid --io-ctrl-ignore pattern {:?} ({})", x, &e);
+                        panic!();
+                    }
+                })
+                .collect(),
 
             sr_failed: BTreeSet::new(),
             sr_wbt: None,
@@ -458,14 +510,15 @@ impl Config {
     }
 
     fn check_iocost(&mut self, enforce: bool) {
-        if !Path::new("/sys/fs/cgroup/io.cost.qos").exists() {
+        if !Path::new(&bench::iocost_qos_path(self)).exists() {
             warn!("cfg: cgroup2 iocost controller unavailable");
             self.sr_failed.insert(SysReq::IoCost);
             return;
         }
 
-        if !Path::new("/sys/fs/cgroup/io.stat").exists() {
-            warn!("cfg: /sys/fs/cgroup/io.stat doesn't exist");
+        let io_stat_path = format!("{}/io.stat", &self.cgrp_root);
+        if !Path::new(&io_stat_path).exists() {
+            warn!("cfg: {} doesn't exist", &io_stat_path);
             self.sr_failed.insert(SysReq::IoCostVer);
             return;
         }
@@ -481,17 +534,17 @@ impl Config {
             return;
         }
 
-        match read_cgroup_nested_keyed_file("/sys/fs/cgroup/io.stat") {
+        match read_cgroup_nested_keyed_file(&io_stat_path) {
             Ok(is) => {
                 if let Some(stat) = is.get(&format!("{}:{}", self.scr_devnr.0, self.scr_devnr.1)) {
                     if let None = stat.get("cost.usage") {
-                        warn!("cfg: /sys/fs/cgroup/io.stat doesn't contain cost.usage");
+                        warn!("cfg: {} doesn't contain cost.usage", &io_stat_path);
                         self.sr_failed.insert(SysReq::IoCostVer);
                     }
                 }
             }
             Err(e) => {
-                warn!("cfg: failed to read /sys/fs/cgroup/io.stat ({:?})", &e);
+                warn!("cfg: failed to read {} ({:?})", &io_stat_path, &e);
                 self.sr_failed.insert(SysReq::IoCostVer);
             }
         }
@@ -603,10 +656,10 @@ impl Config {
         let sys = sysinfo::System::new();
 
         // check cgroup2 & controllers
-        match path_to_mountpoint("/sys/fs/cgroup") {
+        match path_to_mountpoint(&self.cgrp_root) {
             Ok(mi) => {
                 if mi.fstype != "cgroup2" {
-                    warn!("cfg: /sys/fs/cgroup is not cgroup2 fs");
+                    warn!("cfg: {} is not cgroup2 fs", &self.cgrp_root);
                     self.sr_failed.insert(SysReq::Controllers);
                 }
 
@@ -645,31 +698,32 @@ impl Config {
             }
             Err(e) => {
                 warn!(
-                    "cfg: failed to obtain mountinfo for /sys/fs/cgroup ({:?})",
-                    &e
+                    "cfg: failed to obtain mountinfo for {} ({:?})",
+                    &self.cgrp_root, &e
                 );
                 self.sr_failed.insert(SysReq::Controllers);
             }
         }
 
-        let mut buf = String::new();
-        fs::File::open("/sys/fs/cgroup/cgroup.controllers")
-            .and_then(|mut f| f.read_to_string(&mut buf))?;
-        for ctrl in ["cpu", "memory", "io"].iter() {
-            if !buf.contains(ctrl) {
-                warn!("cfg: cgroup2 {} controller not available", ctrl);
-                self.sr_failed.insert(SysReq::Controllers);
-            }
-        }
+        self.missing_ctrls = slices::check_wanted_controllers(&mut self.sr_failed, &self.cgrp_root);
 
-        if !Path::new("/sys/fs/cgroup/system.slice/cgroup.freeze").exists() {
+        let sys_cgrp = slices::slice_cgrp(&self.cgrp_root, rd_agent_intf::Slice::Sys);
+        if !Path::new(&(sys_cgrp + "/cgroup.freeze")).exists() {
             warn!("cfg: cgroup2 freezer not available");
             self.sr_failed.insert(SysReq::Freezer);
         }
 
         // IO controllers
         self.check_iocost(self.enforce.all);
-        slices::check_other_io_controllers(&mut self.sr_failed);
+        slices::check_io_weight(&mut self.sr_failed, &self.cgrp_root, self.enforce.all);
+        if self.enforce.all {
+            slices::check_io_mechanisms(&mut self.sr_failed, &self.cgrp_root, self.scr_devnr);
+        }
+        slices::check_other_io_controllers(
+            &mut self.sr_failed,
+            &self.io_ctrl_ignore,
+            &self.cgrp_root,
+        );
 
         // anon memory balance
         match read_cgroup_flat_keyed_file("/proc/vmstat") {
@@ -698,6 +752,17 @@ impl Config {
             }
         };
 
+        let scr_fstype = match path_to_fstype(&self.scr_path) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(
+                    "cfg: Failed to determine scratch filesystem type ({:#})",
+                    &e
+                );
+                String::new()
+            }
+        };
+
         if mi.is_none() || mi.unwrap().dest != AsRef::<Path>::as_ref("/") {
             if let Err(e) = Self::check_one_fs("/", &mut self.sr_failed, self.enforce.all) {
                 warn!("cfg: Root fs: {}", &e);
@@ -921,6 +986,13 @@ impl Config {
                 ),
             };
 
+        // Virtual devices may not expose these, omit if unavailable.
+        let scr_dev_rotational = devname_to_rotational(&self.scr_dev).ok();
+        let (scr_dev_lbs, scr_dev_pbs) = match devname_to_block_sizes(&self.scr_dev) {
+            Ok((lbs, pbs)) => (Some(lbs), Some(pbs)),
+            Err(_) => (None, None),
+        };
+
         SysReqsReport {
             satisfied: &*ALL_SYSREQS_SET ^ &self.sr_failed,
             missed: self.sr_failed.clone(),
@@ -935,7 +1007,24 @@ impl Config {
             scr_dev_model,
             scr_dev_fwrev,
             scr_dev_size,
+            scr_dev_rotational,
+            scr_dev_lbs,
+            scr_dev_pbs,
             scr_dev_iosched,
+            scr_fstype,
+            cpufreq: read_cpufreq()
+                .into_iter()
+                .map(|(cpu, governor, min_freq, max_freq)| {
+                    (
+                        cpu,
+                        CpuFreqInfo {
+                            governor,
+                            min_freq,
+                            max_freq,
+                        },
+                    )
+                })
+                .collect(),
         }
         .save(&self.sysreqs_path)?;
 
@@ -1067,6 +1156,8 @@ pub struct SysObjs {
     pub cmd_file: JsonConfigFile<Cmd>,
     pub cmd_ack_file: JsonReportFile<CmdAck>,
     enforce_cfg: EnforceConfig,
+    cgrp_root: String,
+    keep_running: bool,
 }
 
 impl SysObjs {
@@ -1097,6 +1188,8 @@ impl SysObjs {
             cmd_file,
             cmd_ack_file,
             enforce_cfg: cfg.enforce.clone(),
+            cgrp_root: cfg.cgrp_root.clone(),
+            keep_running: cfg.keep_running,
         }
     }
 }
@@ -1104,9 +1197,16 @@ impl SysObjs {
 impl Drop for SysObjs {
     fn drop(&mut self) {
         debug!("cfg: Clearing slice configurations");
-        if let Err(e) = slices::clear_slices(&self.enforce_cfg) {
+        if let Err(e) = slices::clear_slices(&self.enforce_cfg, &self.cgrp_root, self.keep_running)
+        {
             warn!("cfg: Failed to clear slice configurations ({:?})", &e);
         }
+        if let Err(e) = slices::clear_custom_slices(&self.slice_file.data, &self.enforce_cfg) {
+            warn!(
+                "cfg: Failed to clear custom slice configurations ({:?})",
+                &e
+            );
+        }
     }
 }
 
@@ -1141,6 +1241,33 @@ fn update_index(cfg: &Config) -> Result<()> {
     index.save(&cfg.index_path)
 }
 
+// Compare the current cgroup configs against the configured slice knobs
+// without touching anything. Returns the number of mismatches.
+fn audit(cfg: &Config) -> Result<usize> {
+    let knobs = match Path::new(&cfg.slices_path).exists() {
+        true => SliceKnobs::load(&cfg.slices_path)?,
+        false => Default::default(),
+    };
+
+    if let Ok(rep) = Report::load(&cfg.report_path) {
+        INSTANCE_SEQ.store(rep.seq + 1, Ordering::Relaxed);
+    }
+    let workload_senpai = match OomdKnobs::load(&cfg.oomd_cfg_path) {
+        Ok(oknobs) => oknobs.disable_seq < instance_seq() && oknobs.workload.senpai.enable,
+        Err(_) => false,
+    };
+
+    let mismatches = slices::audit_slices(&knobs, workload_senpai, cfg)?;
+    for ev in mismatches.iter() {
+        warn!(
+            "audit: {}/{} is {:?}, expected {:?}",
+            &ev.cgrp, &ev.field, &ev.old, &ev.new
+        );
+    }
+    info!("audit: {} mismatches found", mismatches.len());
+    Ok(mismatches.len())
+}
+
 fn main() {
     setup_prog_state();
     unsafe {
@@ -1161,10 +1288,32 @@ fn main() {
 
     let mut cfg = Config::new(&args_file);
 
+    if args_file.data.audit {
+        cfg.missing_ctrls = slices::check_wanted_controllers(&mut cfg.sr_failed, &cfg.cgrp_root);
+        match audit(&cfg) {
+            Ok(0) => std::process::exit(0),
+            Ok(_) => std::process::exit(1),
+            Err(e) => {
+                error!("audit: Failed to audit slice configurations ({:?})", &e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    if let Some(slice) = args_file.data.clear_slice {
+        if let Err(e) = slices::clear_slice(slice, &cfg.enforce, &cfg.cgrp_root, cfg.keep_running) {
+            error!("cfg: Failed to clear {:?} ({:?})", slice.name(), &e);
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    }
+
     if args_file.data.reset {
         reset_agent_states(&cfg);
     }
 
+    cfg.cgrp_read_only = slices::cgroup_read_only(&cfg.cgrp_root);
+
     if let Err(e) = update_index(&cfg) {
         error!("cfg: Failed to update {:?} ({:?})", &cfg.index_path, &e);
         panic!();
@@ -1206,6 +1355,11 @@ fn main() {
         }
     }
 
+    if args_file.data.doctor {
+        let passed = slices::doctor(&cfg);
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
     if args_file.data.prepare {
         // ReportFiles init is responsible for clearing old report files
         // but we aren't gonna get there. Clear them explicitly.
@@ -1253,6 +1407,7 @@ fn main() {
         );
         panic!();
     }
+    slices::warn_if_nothing_enforced(&sobjs.slice_file.data, &cfg);
 
     if !cfg.enforce.all {
         info!("cfg: Enforcement off, not starting oomd");
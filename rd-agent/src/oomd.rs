@@ -201,6 +201,7 @@ fn oomd_cfg_slice_senpai(knobs: &OomdSliceSenpaiKnobs, slice: Slice, mem_size: u
 pub struct Oomd {
     bin: Option<String>,
     daemon_cfg_path: String,
+    cgrp_root: String,
     svc: Option<TransientService>,
 
     pub file: JsonConfigFile<OomdKnobs>,
@@ -218,6 +219,7 @@ impl Oomd {
         Ok(Self {
             bin,
             daemon_cfg_path: cfg.oomd_daemon_cfg_path.clone(),
+            cgrp_root: cfg.cgrp_root.clone(),
             file,
             svc: None,
         })
@@ -229,7 +231,7 @@ impl Oomd {
 
         // clean up after senpai
         for slice in &[Slice::Work, Slice::Sys] {
-            let path = format!("/sys/fs/cgroup/{}/memory.high", slice.name());
+            let path = slice.cgrp(&self.cgrp_root) + "/memory.high";
             debug!("oomd: clearing {:?}", &path);
             if let Err(e) = write_one_line(&path, "max") {
                 warn!(
@@ -22,8 +22,8 @@ use util::*;
 use super::cmd::Runner;
 use super::Config;
 use rd_agent_intf::{
-    report::StatMap, BenchHashdReport, BenchIoCostReport, HashdReport, IoCostReport, IoLatReport,
-    Report, ResCtlReport, Slice, UsageReport, ROOT_SLICE,
+    report::StatMap, BenchHashdReport, BenchIoCostReport, CpuStatReport, HashdReport, IoCostReport,
+    IoLatReport, Report, ResCtlReport, Slice, UsageReport, ROOT_SLICE,
 };
 
 #[derive(Debug, Default)]
@@ -71,7 +71,7 @@ fn read_stat_file(path: &str) -> Result<StatMap> {
     Ok(map.iter().map(|(k, v)| (k.clone(), *v as f64)).collect())
 }
 
-fn read_system_usage(devnr: (u32, u32)) -> Result<(Usage, f64)> {
+fn read_system_usage(devnr: (u32, u32), cgrp_root: &str) -> Result<(Usage, f64)> {
     let kstat = procfs::KernelStats::new()?;
     let cpu = &kstat.total;
     let mut cpu_total = cpu.user as f64
@@ -104,7 +104,7 @@ fn read_system_usage(devnr: (u32, u32)) -> Result<(Usage, f64)> {
         }
     }
 
-    let mem_stat_path = "/sys/fs/cgroup/memory.stat";
+    let mem_stat_path = format!("{}/memory.stat", cgrp_root);
     let mem_stat = match read_stat_file(&mem_stat_path) {
         Ok(v) => v,
         Err(e) => {
@@ -115,7 +115,7 @@ fn read_system_usage(devnr: (u32, u32)) -> Result<(Usage, f64)> {
 
     let mut io_usage = 0;
     let mut io_stat = Default::default();
-    if let Ok(mut is) = read_cgroup_nested_keyed_file("/sys/fs/cgroup/io.stat") {
+    if let Ok(mut is) = read_cgroup_nested_keyed_file(&format!("{}/io.stat", cgrp_root)) {
         if let Some(is) = is.remove(&format!("{}:{}", devnr.0, devnr.1)) {
             if let Some(val) = is.get("cost.usage") {
                 io_usage = scan_fmt!(&val, "{}", u64).unwrap_or(0);
@@ -147,15 +147,40 @@ fn read_system_usage(devnr: (u32, u32)) -> Result<(Usage, f64)> {
     ))
 }
 
-fn read_swap_free(cgrp: &str) -> Result<u64> {
-    if !cgrp.starts_with("/sys/fs/cgroup/") {
-        bail!("cgroup path doesn't start with /sys/fs/cgroup");
+fn read_cpu_stat() -> Result<(Vec<CpuStatReport>, u64)> {
+    let kstat = procfs::KernelStats::new()?;
+    let tps = procfs::ticks_per_second()? as f64;
+    let per_cpu = kstat
+        .cpu_time
+        .iter()
+        .map(|cpu| {
+            let idle = cpu.idle as f64 + cpu.iowait.unwrap_or(0) as f64;
+            // guest and guest_nice are already included in user and nice.
+            let total = idle
+                + cpu.user as f64
+                + cpu.nice as f64
+                + cpu.system as f64
+                + cpu.irq.unwrap_or(0) as f64
+                + cpu.softirq.unwrap_or(0) as f64
+                + cpu.steal.unwrap_or(0) as f64;
+            CpuStatReport {
+                busy: (total - idle) / tps,
+                total: total / tps,
+            }
+        })
+        .collect();
+    Ok((per_cpu, kstat.ctxt))
+}
+
+fn read_swap_free(cgrp: &str, cgrp_root: &str) -> Result<u64> {
+    if !cgrp.starts_with(&format!("{}/", cgrp_root)) {
+        bail!("cgroup path doesn't start with {}", cgrp_root);
     }
     // Walk up the hierarchy and take the min. We should expose this in
     // memory.stat from kernel side eventually.
     let mut free = procfs::Meminfo::new()?.swap_free;
     let mut path = std::path::PathBuf::from(cgrp);
-    while path != std::path::Path::new("/sys/fs/cgroup") {
+    while path != std::path::Path::new(cgrp_root) {
         path.push("memory.swap.max");
         let max = match read_one_line(path.to_str().unwrap())
             .unwrap_or("max".to_owned())
@@ -178,7 +203,17 @@ fn read_swap_free(cgrp: &str) -> Result<u64> {
     Ok(free)
 }
 
-fn read_cgroup_usage(cgrp: &str, devnr: (u32, u32)) -> Usage {
+/// Read a per-slice stat with `read`, leaving out the slices it fails for.
+fn read_per_slice<T, F>(mut read: F) -> BTreeMap<String, T>
+where
+    F: FnMut(Slice) -> Option<T>,
+{
+    Slice::into_enum_iter()
+        .filter_map(|slice| read(slice).map(|v| (slice.name().to_string(), v)))
+        .collect()
+}
+
+fn read_cgroup_usage(cgrp: &str, devnr: (u32, u32), cgrp_root: &str) -> Usage {
     let mut usage: Usage = Default::default();
 
     if let Ok(cs) = read_cgroup_flat_keyed_file(&(cgrp.to_string() + "/cpu.stat")) {
@@ -202,7 +237,7 @@ fn read_cgroup_usage(cgrp: &str, devnr: (u32, u32)) -> Usage {
         }
     }
 
-    if let Ok(v) = read_swap_free(cgrp) {
+    if let Ok(v) = read_swap_free(cgrp, cgrp_root) {
         usage.swap_free = v;
     }
 
@@ -248,6 +283,7 @@ fn read_cgroup_usage(cgrp: &str, devnr: (u32, u32)) -> Usage {
 
 pub struct UsageTracker {
     devnr: (u32, u32),
+    cgrp_root: String,
     at: Instant,
     cpu_total: f64,
     usages: HashMap<String, Usage>,
@@ -255,9 +291,10 @@ pub struct UsageTracker {
 }
 
 impl UsageTracker {
-    fn new(devnr: (u32, u32), runner: Runner) -> Self {
+    fn new(devnr: (u32, u32), cgrp_root: &str, runner: Runner) -> Self {
         let mut us = Self {
             devnr,
+            cgrp_root: cgrp_root.into(),
             at: Instant::now(),
             cpu_total: 0.0,
             usages: HashMap::new(),
@@ -278,18 +315,18 @@ impl UsageTracker {
     fn read_usages(&self) -> Result<(HashMap<String, Usage>, f64)> {
         let mut usages = HashMap::new();
 
-        let (us, cpu_total) = read_system_usage(self.devnr)?;
+        let (us, cpu_total) = read_system_usage(self.devnr, &self.cgrp_root)?;
         usages.insert(ROOT_SLICE.into(), us);
         for slice in Slice::into_enum_iter() {
             usages.insert(
                 slice.name().to_string(),
-                read_cgroup_usage(slice.cgrp(), self.devnr),
+                read_cgroup_usage(&slice.cgrp(&self.cgrp_root), self.devnr, &self.cgrp_root),
             );
         }
 
         let all_svcs = self.runner.data.lock().unwrap().all_svcs();
         for (svc, cgrp) in all_svcs.into_iter() {
-            usages.insert(svc, read_cgroup_usage(&cgrp, self.devnr));
+            usages.insert(svc, read_cgroup_usage(&cgrp, self.devnr, &self.cgrp_root));
         }
         Ok((usages, cpu_total))
     }
@@ -388,6 +425,7 @@ struct ReportFile {
     iolat_acc: IoLatReport,
     iocost_acc: IoCostReport,
     nr_samples: u32,
+    cgrp_root: String,
 }
 
 pub fn clear_old_report_files(d_path: &str, retention: Option<u64>, now: u64) -> Result<()> {
@@ -429,6 +467,7 @@ impl ReportFile {
         path: &str,
         d_path: &str,
         devnr: (u32, u32),
+        cgrp_root: &str,
         runner: Runner,
     ) -> ReportFile {
         let now = unix_now();
@@ -439,7 +478,7 @@ impl ReportFile {
             path: path.into(),
             d_path: d_path.into(),
             next_at: ((now / intv) + 1) * intv,
-            usage_tracker: UsageTracker::new(devnr, runner),
+            usage_tracker: UsageTracker::new(devnr, cgrp_root, runner),
             hashd_acc: Default::default(),
             mem_stat_acc: Default::default(),
             io_stat_acc: Default::default(),
@@ -447,6 +486,7 @@ impl ReportFile {
             iolat_acc: Default::default(),
             iocost_acc: Default::default(),
             nr_samples: 0,
+            cgrp_root: cgrp_root.into(),
         };
 
         if let Err(e) = clear_old_report_files(d_path, retention, now) {
@@ -564,6 +604,26 @@ impl ReportFile {
             }
         }
 
+        let (root, devnr) = (&self.cgrp_root, self.usage_tracker.devnr);
+        report.psi = read_per_slice(|slice| super::slices::read_slice_psi(slice, root));
+        report.slice_io =
+            read_per_slice(|slice| super::slices::read_slice_io_stat(slice, devnr, root));
+        report.mem_events = read_per_slice(|slice| {
+            read_stat_file(&format!(
+                "{}/memory.events",
+                super::slices::slice_cgrp(root, slice)
+            ))
+            .ok()
+        });
+
+        match read_cpu_stat() {
+            Ok((per_cpu, ctxt)) => {
+                report.cpu_stat = per_cpu;
+                report.nr_ctxsw_all = ctxt;
+            }
+            Err(e) => warn!("report: Failed to read per-cpu stat ({:?})", &e),
+        }
+
         match read_stat_file("/proc/vmstat") {
             Ok(map) => report.vmstat = map,
             Err(e) => warn!("report: Failed to read vmstat ({:?})", &e),
@@ -720,6 +780,7 @@ impl ReportWorker {
         // and unlock it.
         let cfg = &rdata.cfg;
         let scr_devnr = cfg.scr_devnr;
+        let cgrp_root = cfg.cgrp_root.clone();
         let (rep_ret, rep_path, rep_d_path) = (
             cfg.rep_retention,
             cfg.report_path.clone(),
@@ -740,6 +801,7 @@ impl ReportWorker {
                 &rep_path,
                 &rep_d_path,
                 scr_devnr,
+                &cgrp_root,
                 runner.clone(),
             ),
             report_file_1min: ReportFile::new(
@@ -748,6 +810,7 @@ impl ReportWorker {
                 &rep_1min_path,
                 &rep_1min_d_path,
                 scr_devnr,
+                &cgrp_root,
                 runner.clone(),
             ),
 
@@ -784,6 +847,8 @@ impl ReportWorker {
             cpu: dseqs.cpu < seq,
             mem: dseqs.mem < seq,
             io: dseqs.io < seq,
+            work_senpai_mem_high: runner.work_senpai_mem_high,
+            fixes: super::slices::slice_fix_counts(),
         };
 
         Ok(Report {
@@ -378,12 +378,12 @@ impl SideRunner {
         let mut svcs = HashSet::<(String, String)>::new();
         for (name, _) in self.sysloads.iter() {
             let name = sysload_svc_name(name);
-            let cgrp = format!("{}/{}", Slice::Sys.cgrp(), &name);
+            let cgrp = format!("{}/{}", Slice::Sys.cgrp(&self.cfg.cgrp_root), &name);
             svcs.insert((name, cgrp));
         }
         for (name, _) in self.sideloads.iter() {
             let name = sideload_svc_name(name);
-            let cgrp = format!("{}/{}", Slice::Side.cgrp(), &name);
+            let cgrp = format!("{}/{}", Slice::Side.cgrp(&self.cfg.cgrp_root), &name);
             svcs.insert((name, cgrp));
         }
         svcs
@@ -1,29 +1,66 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
-use anyhow::Result;
+use anyhow::{bail, Result};
 use enum_iterator::IntoEnumIterator;
 use glob::glob;
 use log::{debug, error, info, trace, warn};
 use scan_fmt::scan_fmt;
-use std::collections::BTreeSet;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::{OsStr, OsString};
 use std::fmt::Write;
 use std::fs;
 use std::io::prelude::*;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
 use util::systemd::UnitState as US;
 use util::*;
 
 use super::{Config, EnforceConfig};
-use rd_agent_intf::{DisableSeqKnobs, MemoryKnob, Slice, SliceConfig, SliceKnobs, SysReq};
+use rd_agent_intf::{
+    DisableSeqKnobs, IoStatReport, MemoryKnob, PsiReport, Slice, SliceConfig, SliceKnobs, SysReq,
+    WorkSenpaiKnobs, ROOT_SLICE,
+};
+
+// Set after drive_work_senpai() failure is warned about, cleared on success
+// so that the warning isn't repeated on every pass.
+static WORK_SENPAI_WARNED: AtomicBool = AtomicBool::new(false);
+// Number of fixes applied and deferred during the current verification
+// pass, see Config::max_fixes_per_pass.
+static NR_PASS_FIXES: AtomicU64 = AtomicU64::new(0);
+static NR_PASS_DEFERRED: AtomicU64 = AtomicU64::new(0);
+
+lazy_static::lazy_static! {
+    // Number of fixes applied since startup keyed by "SLICE/FIELD".
+    static ref SLICE_FIX_COUNTS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+    // Slice cgroup dirs which existed on the last verification pass, None
+    // before the first pass.
+    static ref SEEN_SLICE_CGRPS: Mutex<Option<BTreeSet<String>>> = Mutex::new(None);
+}
+
+// Mismatches collected instead of fixed when auditing, see audit_slices().
+type AuditLog = RefCell<Vec<SliceFixEvent>>;
+
+/// Path of `slice` under the cgroup2 root `root`, see Config::cgrp_root.
+pub fn slice_cgrp(root: &str, slice: Slice) -> String {
+    slice.cgrp(root)
+}
 
-pub fn check_other_io_controllers(sr_failed: &mut BTreeSet<SysReq>) {
+// Cgroups matching one of the `ignore` patterns, relative to the cgroup2
+// root, are known to run their own io controller configs and skipped.
+pub fn check_other_io_controllers(
+    sr_failed: &mut BTreeSet<SysReq>,
+    ignore: &[glob::Pattern],
+    root: &str,
+) {
     let mut failed = None;
     let mut nr_fails = 0;
 
-    for path in glob("/sys/fs/cgroup/**/io.latency")
+    for path in glob(&format!("{}/**/io.latency", root))
         .unwrap()
-        .chain(glob("/sys/fs/cgroup/**/io.max").unwrap())
-        .chain(glob("/sys/fs/cgroup/**/io.low").unwrap())
+        .chain(glob(&format!("{}/**/io.max", root)).unwrap())
+        .chain(glob(&format!("{}/**/io.low", root)).unwrap())
         .filter_map(Result::ok)
     {
         match read_one_line(&path) {
@@ -31,6 +68,12 @@ pub fn check_other_io_controllers(sr_failed: &mut BTreeSet<SysReq>) {
             Err(_) => continue,
             _ => {}
         }
+        if let Some(cgrp) = path.parent().and_then(|x| x.strip_prefix(root).ok()) {
+            if ignore.iter().any(|pat| pat.matches_path(cgrp)) {
+                debug!("resctl: Ignoring io controller config {:?}", &path);
+                continue;
+            }
+        }
         if failed.is_none() {
             failed = path
                 .parent()
@@ -49,6 +92,148 @@ pub fn check_other_io_controllers(sr_failed: &mut BTreeSet<SysReq>) {
     }
 }
 
+// Probe io.weight support once by writing back the current value so that
+// we don't keep failing on every verification pass. io.weight only shows
+// up after io is enabled in the root's subtree_control, which is done here
+// if enforcing. Otherwise, the probe is skipped if io isn't enabled yet.
+pub fn check_io_weight(sr_failed: &mut BTreeSet<SysReq>, root: &str, enforce: bool) {
+    let path = format!("{}/io.weight", slice_cgrp(root, Slice::Sys));
+    if !Path::new(&path).exists() {
+        if !enforce {
+            debug!("resctl: io not enabled yet, can't probe io.weight support");
+            return;
+        }
+        if let Err(e) = write_one_line(&format!("{}/cgroup.subtree_control", root), "+io") {
+            warn!(
+                "resctl: Failed to enable io controller ({:#}), not configuring io.weight",
+                &e
+            );
+            sr_failed.insert(SysReq::IoWeight);
+            return;
+        }
+    }
+    let res = read_one_line(&path).and_then(|line| write_one_line(&path, &line));
+    if let Err(e) = res {
+        warn!(
+            "resctl: io.weight not supported ({:#}), not configuring",
+            &e
+        );
+        sr_failed.insert(SysReq::IoWeight);
+    }
+}
+
+fn read_psi_avg10(path: &str) -> Option<(f64, f64)> {
+    let f = fs::OpenOptions::new().read(true).open(path).ok()?;
+    let (mut some, mut full) = (None, None);
+
+    for line in std::io::BufReader::new(f).lines().filter_map(|x| x.ok()) {
+        if let Ok((which, v)) = scan_fmt!(
+            &line,
+            "{} avg10={f} avg60={*f} avg300={*f} total={*d}",
+            String,
+            f64
+        ) {
+            match which.as_ref() {
+                "some" => some = Some(v / 100.0),
+                "full" => full = Some(v / 100.0),
+                _ => {}
+            }
+        }
+    }
+
+    some.map(|some| (some, full.unwrap_or(0.0)))
+}
+
+fn read_psi_total(path: &str) -> Option<(f64, f64)> {
+    let f = fs::OpenOptions::new().read(true).open(path).ok()?;
+    let (mut some, mut full) = (None, None);
+
+    for line in std::io::BufReader::new(f).lines().filter_map(|x| x.ok()) {
+        if let Ok((which, v)) = scan_fmt!(
+            &line,
+            "{} avg10={*f} avg60={*f} avg300={*f} total={d}",
+            String,
+            u64
+        ) {
+            match which.as_ref() {
+                "some" => some = Some(v as f64 / 1_000_000.0),
+                "full" => full = Some(v as f64 / 1_000_000.0),
+                _ => {}
+            }
+        }
+    }
+
+    some.map(|some| (some, full.unwrap_or(0.0)))
+}
+
+/// How long freezing a slice may take before it's reported as stuck.
+pub const FREEZE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn set_slice_frozen(slice: Slice, frozen: bool, root: &str) -> Result<()> {
+    write_one_line(
+        &format!("{}/cgroup.freeze", slice_cgrp(root, slice)),
+        &format!("{}", frozen as u64),
+    )
+}
+
+/// Start freezing all processes in `slice`. The kernel completes freezing
+/// asynchronously, use slice_frozen() to tell when it's done.
+pub fn freeze_slice(slice: Slice, root: &str) -> Result<()> {
+    set_slice_frozen(slice, true, root)
+}
+
+/// Undo freeze_slice().
+pub fn thaw_slice(slice: Slice, root: &str) -> Result<()> {
+    set_slice_frozen(slice, false, root)
+}
+
+/// Whether the kernel reports the whole subtree of `slice` frozen.
+pub fn slice_frozen(slice: Slice, root: &str) -> Result<bool> {
+    let events =
+        read_cgroup_flat_keyed_file(&format!("{}/cgroup.events", slice_cgrp(root, slice)))?;
+    Ok(events.get("frozen") == Some(&1))
+}
+
+/// Read some/full avg10 pressures and cumulative stall times of `slice`.
+/// Returns `None` if the kernel doesn't support PSI or the slice doesn't
+/// exist.
+pub fn read_slice_psi(slice: Slice, root: &str) -> Option<PsiReport> {
+    let cgrp = slice_cgrp(root, slice);
+    let cpu_path = format!("{}/cpu.pressure", &cgrp);
+    let mem_path = format!("{}/memory.pressure", &cgrp);
+    let io_path = format!("{}/io.pressure", &cgrp);
+    let psi = PsiReport {
+        cpu: read_psi_avg10(&cpu_path),
+        mem: read_psi_avg10(&mem_path),
+        io: read_psi_avg10(&io_path),
+        cpu_total: read_psi_total(&cpu_path),
+        mem_total: read_psi_total(&mem_path),
+        io_total: read_psi_total(&io_path),
+    };
+    if psi.cpu.is_none() && psi.mem.is_none() && psi.io.is_none() {
+        None
+    } else {
+        Some(psi)
+    }
+}
+
+/// Read the io.stat counters of `slice` for device `devnr`. A device
+/// missing from io.stat hasn't seen any IO from the slice and reads as
+/// zeros. Returns `None` if the slice doesn't exist.
+pub fn read_slice_io_stat(slice: Slice, devnr: (u32, u32), root: &str) -> Option<IoStatReport> {
+    let path = format!("{}/io.stat", slice_cgrp(root, slice));
+    let mut kf = read_cgroup_nested_keyed_file(&path).ok()?;
+    let mut stat = IoStatReport::default();
+    if let Some(is) = kf.remove(&format!("{}:{}", devnr.0, devnr.1)) {
+        let get = |key: &str| is.get(key).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+        stat.rbytes = get("rbytes");
+        stat.wbytes = get("wbytes");
+        stat.rios = get("rios");
+        stat.wios = get("wios");
+    }
+    Some(stat)
+}
+
 fn mknob_to_cgrp_string(knob: &MemoryKnob, is_limit: bool) -> String {
     match knob.nr_bytes(is_limit) {
         std::u64::MAX => "max".to_string(),
@@ -92,14 +277,14 @@ fn slice_needs_crit_mem_prot(slice: Slice) -> bool {
 }
 
 fn build_configlet(
-    slice: Slice,
+    unit_name: &str,
     cpu_weight: Option<u32>,
     io_weight: Option<u32>,
     mem_min: Option<MemoryKnob>,
     mem_low: Option<MemoryKnob>,
     mem_high: Option<MemoryKnob>,
 ) -> String {
-    let section = if slice.name().ends_with(".slice") {
+    let section = if unit_name.ends_with(".slice") {
         "Slice"
     } else {
         "Scope"
@@ -131,7 +316,11 @@ fn build_configlet(
 }
 
 fn apply_configlet(slice: Slice, configlet: &str) -> Result<bool> {
-    let path = crate::unit_configlet_path(slice.name(), "resctl");
+    apply_unit_configlet(slice.name(), configlet, slice_needs_start_stop(slice))
+}
+
+fn apply_unit_configlet(unit_name: &str, configlet: &str, needs_start: bool) -> Result<bool> {
+    let path = crate::unit_configlet_path(unit_name, "resctl");
 
     debug!("resctl: reading {:?} to test for equality", &path);
     if let Ok(mut f) = fs::OpenOptions::new().read(true).open(&path) {
@@ -144,21 +333,17 @@ fn apply_configlet(slice: Slice, configlet: &str) -> Result<bool> {
     }
 
     debug!("resctl: writing updated {:?}", &path);
-    crate::write_unit_configlet(slice.name(), "resctl", &configlet)?;
+    crate::write_unit_configlet(unit_name, "resctl", &configlet)?;
 
-    if slice_needs_start_stop(slice) {
-        match systemd::Unit::new_sys(slice.name().into()) {
+    if needs_start {
+        match systemd::Unit::new_sys(unit_name.into()) {
             Ok(mut unit) => {
                 if let Err(e) = unit.try_start_nowait() {
-                    warn!("resctl: Failed to start {:?} ({})", slice.name(), &e);
+                    warn!("resctl: Failed to start {:?} ({})", unit_name, &e);
                 }
             }
             Err(e) => {
-                warn!(
-                    "resctl: Failed to create unit for {:?} ({})",
-                    slice.name(),
-                    &e
-                );
+                warn!("resctl: Failed to create unit for {:?} ({})", unit_name, &e);
             }
         }
     }
@@ -166,13 +351,16 @@ fn apply_configlet(slice: Slice, configlet: &str) -> Result<bool> {
     Ok(true)
 }
 
-fn propagate_one_slice(slice: Slice, resctl: &systemd::UnitResCtl) -> Result<()> {
+// Returns whether the config of any of the descendant units changed.
+fn propagate_one_slice(slice: Slice, resctl: &systemd::UnitResCtl, root: &str) -> Result<bool> {
     debug!("resctl: propagating {:?} w/ {:?}", slice, &resctl);
+    let mut changed = false;
+    let cgrp = slice_cgrp(root, slice);
 
-    for path in glob(&format!("{}/**/*.service", slice.cgrp()))
+    for path in glob(&format!("{}/**/*.service", &cgrp))
         .unwrap()
-        .chain(glob(&format!("{}/**/*.scope", slice.cgrp())).unwrap())
-        .chain(glob(&format!("{}/**/*.slice", slice.cgrp())).unwrap())
+        .chain(glob(&format!("{}/**/*.scope", &cgrp)).unwrap())
+        .chain(glob(&format!("{}/**/*.slice", &cgrp)).unwrap())
         .filter_map(Result::ok)
     {
         let unit_name = path.file_name().unwrap().to_str().unwrap().to_string();
@@ -186,14 +374,14 @@ fn propagate_one_slice(slice: Slice, resctl: &systemd::UnitResCtl) -> Result<()>
         }
         let mut unit = unit.unwrap();
 
-        let trimmed = path
-            .components()
-            .skip(4)
-            .fold(OsString::new(), |mut acc, x| {
+        let trimmed = path.strip_prefix(root).unwrap_or(&path).components().fold(
+            OsString::new(),
+            |mut acc, x| {
                 acc.push("/");
                 acc.push(x);
                 acc
-            });
+            },
+        );
         match unit.props.string("ControlGroup") {
             Some(v) if AsRef::<OsStr>::as_ref(&v) == trimmed => {}
             v => {
@@ -221,49 +409,186 @@ fn propagate_one_slice(slice: Slice, resctl: &systemd::UnitResCtl) -> Result<()>
 
         unit.resctl = resctl.clone();
         match unit.apply() {
-            Ok(()) => debug!("resctl: propagated resctl config to {:?}", &unit_name),
+            Ok(()) => {
+                debug!("resctl: propagated resctl config to {:?}", &unit_name);
+                changed = true;
+            }
             Err(e) => warn!(
                 "resctl: Failed to propagate config to {:?} ({:?})",
                 &unit_name, &e
             ),
         }
     }
+    Ok(changed)
+}
+
+/// Opening a cgroup file for writing fails with EROFS if cgroup2 is
+/// mounted read-only. Probed once at startup and cached in
+/// Config::cgrp_read_only instead of failing every write.
+pub fn cgroup_read_only(root: &str) -> bool {
+    if let Err(e) = write_one_line(&format!("{}/cgroup.subtree_control", root), "") {
+        match e.downcast_ref::<std::io::Error>() {
+            Some(ioe) if ioe.raw_os_error() == Some(libc::EROFS) => {
+                error!("resctl: cgroup2 filesystem at {:?} is read-only", root);
+                return true;
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+fn check_cgroup_writable(cfg: &Config) -> Result<()> {
+    if cfg.cgrp_read_only {
+        bail!("cgroup2 filesystem is read-only; enforcement impossible");
+    }
     Ok(())
 }
 
-pub fn apply_slices(knobs: &mut SliceKnobs, hashd_mem_size: u64, cfg: &Config) -> Result<()> {
+const WANTED_CONTROLLERS: [&str; 3] = ["cpu", "io", "memory"];
+
+/// Controllers the kernel exposes at the cgroup2 root. Empty if cgroup2
+/// isn't available.
+pub fn available_controllers(root: &str) -> BTreeSet<String> {
+    match read_one_line(&format!("{}/cgroup.controllers", root)) {
+        Ok(line) => line.split_whitespace().map(|x| x.to_string()).collect(),
+        Err(e) => {
+            warn!("resctl: Failed to read cgroup.controllers ({:?})", &e);
+            Default::default()
+        }
+    }
+}
+
+/// Controllers which are bound to a cgroup1 hierarchy according to
+/// /proc/cgroups and thus can't be used on the unified hierarchy.
+pub fn v1_bound_controllers() -> BTreeSet<String> {
+    let mut ctrls = BTreeSet::new();
+    let buf = match fs::read_to_string("/proc/cgroups") {
+        Ok(v) => v,
+        Err(e) => {
+            warn!("resctl: Failed to read /proc/cgroups ({:?})", &e);
+            return ctrls;
+        }
+    };
+    for line in buf.lines().filter(|x| !x.starts_with("#")) {
+        let toks: Vec<&str> = line.split_whitespace().collect();
+        if toks.len() >= 4 && toks[1] != "0" && toks[3] == "1" {
+            ctrls.insert(toks[0].to_string());
+        }
+    }
+    ctrls
+}
+
+/// Complain about each of the wanted controllers which is missing from the
+/// unified hierarchy, spelling out when it's because cgroup1 claimed it.
+/// Called once from the startup checks, the result is cached in
+/// Config::missing_ctrls.
+pub fn check_wanted_controllers(sr_failed: &mut BTreeSet<SysReq>, root: &str) -> BTreeSet<String> {
+    let avail = available_controllers(root);
+    let v1 = v1_bound_controllers();
+    let mut missing = BTreeSet::new();
+
+    for ctrl in WANTED_CONTROLLERS.iter() {
+        if avail.contains(*ctrl) {
+            continue;
+        }
+        if v1.contains(*ctrl) {
+            error!(
+                "resctl: {} controller is bound to a cgroup1 hierarchy and unavailable \
+                 on cgroup2, boot with cgroup_no_v1=all, skipping its enforcement",
+                ctrl
+            );
+        } else {
+            warn!(
+                "resctl: {} controller not available, skipping its enforcement",
+                ctrl
+            );
+        }
+        sr_failed.insert(SysReq::Controllers);
+        missing.insert(ctrl.to_string());
+    }
+    missing
+}
+
+/// IO enforcement needs either iocost or io.weight, otherwise the io
+/// weights in the configlets don't do anything. Must be called after the
+/// iocost and io.weight checks as it builds on their results.
+pub fn check_io_mechanisms(sr_failed: &mut BTreeSet<SysReq>, root: &str, devnr: (u32, u32)) {
+    let has_iocost = !sr_failed.contains(&SysReq::IoCost)
+        && match read_cgroup_nested_keyed_file(&format!("{}/io.cost.qos", root)) {
+            Ok(qos) => qos.contains_key(&format!("{}:{}", devnr.0, devnr.1)),
+            Err(_) => false,
+        };
+    let has_io_weight = !sr_failed.contains(&SysReq::IoWeight);
+    if has_iocost || has_io_weight {
+        return;
+    }
+
+    error!(
+        "resctl: Neither iocost nor io.weight is available for {}:{}, IO won't be controlled",
+        devnr.0, devnr.1
+    );
+    sr_failed.insert(SysReq::IoCost);
+    sr_failed.insert(SysReq::IoWeight);
+}
+
+/// Apply the slice configurations. Returns the slices whose configlet or
+/// propagated descendant configs changed.
+pub fn apply_slices(
+    knobs: &mut SliceKnobs,
+    hashd_mem_size: u64,
+    cfg: &Config,
+) -> Result<Vec<Slice>> {
+    check_cgroup_writable(cfg)?;
+
+    let missing = &cfg.missing_ctrls;
+    let (has_cpu, has_io, has_mem) = (
+        !missing.contains("cpu"),
+        !missing.contains("io"),
+        !missing.contains("memory"),
+    );
+
     if knobs.work_mem_low_none {
         let sk = knobs.slices.get_mut(Slice::Work.name()).unwrap();
         sk.mem_low = MemoryKnob::Bytes((hashd_mem_size as f64 * 0.75).ceil() as u64);
     }
 
     let mut updated = false;
+    let mut changed = vec![];
     for slice in Slice::into_enum_iter() {
-        let enforce_mem =
-            cfg.enforce.all || (cfg.enforce.crit_mem_prot && slice_needs_crit_mem_prot(slice));
+        let enforce_mem = has_mem
+            && (cfg.enforce.all || (cfg.enforce.crit_mem_prot && slice_needs_crit_mem_prot(slice)));
 
         if !cfg.enforce.all && !enforce_mem {
             continue;
         }
 
         let sk = knobs.slices.get(slice.name()).unwrap();
+        let cgrp = slice_cgrp(&cfg.cgrp_root, slice);
+        let resolve = |knob, is_limit| resolve_mem_knob(knob, is_limit, &cgrp, cfg.mem_total);
         let (cpu_weight, io_weight, mem_min, mem_low, mem_high);
 
         if cfg.enforce.all {
-            cpu_weight = Some(sk.cpu_weight);
-            io_weight = Some(sk.io_weight);
+            cpu_weight = match has_cpu {
+                true => Some(sk.cpu_weight),
+                false => None,
+            };
+            io_weight = match has_io && !cfg.sr_failed.contains(&SysReq::IoWeight) {
+                true => Some(sk.io_weight),
+                false => None,
+            };
         } else {
             cpu_weight = None;
             io_weight = None;
         }
 
         if enforce_mem {
-            mem_min = Some(sk.mem_min);
-            mem_high = Some(sk.mem_high);
+            mem_min = Some(resolve(sk.mem_min, false));
+            mem_high = Some(resolve(sk.mem_high, true));
             if slice == Slice::Work && knobs.disable_seqs.mem >= super::instance_seq() {
                 mem_low = None;
             } else {
-                mem_low = Some(sk.mem_low);
+                mem_low = Some(resolve(sk.mem_low, false));
             }
         } else {
             mem_min = None;
@@ -271,66 +596,145 @@ pub fn apply_slices(knobs: &mut SliceKnobs, hashd_mem_size: u64, cfg: &Config) -
             mem_high = None;
         }
 
-        let configlet = build_configlet(slice, cpu_weight, io_weight, mem_min, mem_low, mem_high);
-        if apply_configlet(slice, &configlet)? {
-            updated = true;
-        }
+        let configlet = build_configlet(
+            slice.name(),
+            cpu_weight,
+            io_weight,
+            mem_min,
+            mem_low,
+            mem_high,
+        );
+        let mut slice_changed = apply_configlet(slice, &configlet)?;
+        updated |= slice_changed;
 
         if enforce_mem && slice_needs_mem_prot_propagation(slice) {
             let sk = knobs.slices.get(slice.name()).unwrap();
             let mut resctl = systemd::UnitResCtl::default();
 
             if !cfg.memcg_recursive_prot() {
-                resctl.mem_min = mknob_to_unit_resctl(&sk.mem_min);
-                resctl.mem_low = mknob_to_unit_resctl(&sk.mem_low);
+                if sk.propagate_mem_min {
+                    resctl.mem_min = mknob_to_unit_resctl(&resolve(sk.mem_min, false));
+                }
+                if sk.propagate_mem_low {
+                    resctl.mem_low = mknob_to_unit_resctl(&resolve(sk.mem_low, false));
+                }
             }
 
-            propagate_one_slice(slice, &resctl)?;
+            slice_changed |= propagate_one_slice(slice, &resctl, &cfg.cgrp_root)?;
+        }
+
+        if slice_changed {
+            changed.push(slice);
+        }
+    }
+    if cfg.enforce.all {
+        let seq = super::instance_seq();
+        let dseqs = &knobs.disable_seqs;
+        for (name, sk) in knobs.custom_slices() {
+            let cgrp = format!("{}/{}", &cfg.cgrp_root, name);
+            let resolve = |knob, is_limit| resolve_mem_knob(knob, is_limit, &cgrp, cfg.mem_total);
+            // Gated the same way as in verify_and_fix_slices() so that the
+            // two don't fight over disabled controllers.
+            let enable_mem = has_mem && dseqs.mem < seq;
+            let configlet = build_configlet(
+                name,
+                match has_cpu && dseqs.cpu < seq {
+                    true => Some(sk.cpu_weight),
+                    false => None,
+                },
+                match has_io && dseqs.io < seq && !cfg.sr_failed.contains(&SysReq::IoWeight) {
+                    true => Some(sk.io_weight),
+                    false => None,
+                },
+                Some(resolve(sk.mem_min, false)).filter(|_| enable_mem),
+                Some(resolve(sk.mem_low, false)).filter(|_| enable_mem),
+                Some(resolve(sk.mem_high, true)).filter(|_| enable_mem),
+            );
+            updated |= apply_unit_configlet(name, &configlet, false)?;
         }
     }
+
     if updated {
         info!("resctl: Applying updated slice configurations");
         systemd::daemon_reload()?;
     }
 
-    let enable_iocost = knobs.disable_seqs.io < super::instance_seq();
+    let enable_iocost = match cfg.enforce.iocost {
+        Some(v) => {
+            info!(
+                "resctl: iocost forced {} by --iocost, ignoring disable_seqs.io",
+                if v { "on" } else { "off" }
+            );
+            v
+        }
+        None => knobs.disable_seqs.io < super::instance_seq(),
+    };
     if let Err(e) = super::bench::iocost_on_off(enable_iocost, cfg) {
         warn!("resctl: Failed to enable/disable iocost ({:?})", &e);
         return Err(e);
     }
 
-    Ok(())
+    Ok(changed)
+}
+
+// If @keep_running, start/stop slices are left running with only the
+// configurations cleared so that live workloads in them aren't torn down.
+fn clear_one_slice(slice: Slice, mem_prot_only: bool, keep_running: bool) -> Result<bool> {
+    clear_one_unit(
+        slice.name(),
+        mem_prot_only,
+        slice_needs_start_stop(slice) && !keep_running,
+    )
 }
 
-fn clear_one_slice(slice: Slice, mem_prot_only: bool) -> Result<bool> {
-    match systemd::Unit::new_sys(slice.name().into()) {
+fn clear_one_unit(unit_name: &str, mem_prot_only: bool, needs_stop: bool) -> Result<bool> {
+    let path = crate::unit_configlet_path(unit_name, "resctl");
+    let has_configlet = Path::new(&path).exists();
+
+    match systemd::Unit::new_sys(unit_name.into()) {
         Ok(mut unit) => {
-            if mem_prot_only {
-                unit.resctl.mem_min = None;
-                unit.resctl.mem_low = None;
-            } else {
-                unit.resctl = Default::default();
+            let cleared = match mem_prot_only {
+                true => unit.resctl.mem_min.is_none() && unit.resctl.mem_low.is_none(),
+                false => unit.resctl == Default::default(),
+            };
+            let active = match unit.state {
+                US::Running | US::OtherActive(_) => true,
+                _ => false,
+            };
+
+            // Nothing to do if a previous invocation already cleared it.
+            if cleared && !has_configlet && !(needs_stop && active) {
+                trace!("resctl: {:?} already cleared", unit_name);
+                return Ok(false);
             }
-            if let Err(e) = unit.apply() {
-                error!("resctl: Failed to reset {:?} ({})", slice.name(), &e);
+
+            if !cleared {
+                if mem_prot_only {
+                    unit.resctl.mem_min = None;
+                    unit.resctl.mem_low = None;
+                } else {
+                    unit.resctl = Default::default();
+                }
+                if let Err(e) = unit.apply() {
+                    error!("resctl: Failed to reset {:?} ({})", unit_name, &e);
+                }
             }
-            if slice_needs_start_stop(slice) {
+            if needs_stop && active {
                 if let Err(e) = unit.stop() {
-                    error!("resctl: Failed to stop {:?} ({})", slice.name(), &e);
+                    error!("resctl: Failed to stop {:?} ({})", unit_name, &e);
                 }
             }
         }
         Err(e) => {
-            error!(
-                "resctl: Failed to clear unit for {:?} ({})",
-                slice.name(),
-                &e
-            );
+            if !has_configlet {
+                trace!("resctl: {:?} not found and has no configlet", unit_name);
+                return Ok(false);
+            }
+            error!("resctl: Failed to clear unit for {:?} ({})", unit_name, &e);
         }
     }
 
-    let path = crate::unit_configlet_path(slice.name(), "resctl");
-    if Path::new(&path).exists() {
+    if has_configlet {
         debug!("resctl: Removing {:?}", &path);
         fs::remove_file(&path)?;
         Ok(true)
@@ -339,29 +743,82 @@ fn clear_one_slice(slice: Slice, mem_prot_only: bool) -> Result<bool> {
     }
 }
 
-pub fn clear_slices(ecfg: &EnforceConfig) -> Result<()> {
-    let mut updated = false;
-    for slice in Slice::into_enum_iter() {
-        let enforce_crit_mem_prot = ecfg.crit_mem_prot && slice_needs_crit_mem_prot(slice);
-        let enforce = ecfg.all || enforce_crit_mem_prot;
-        let mem_prot_only = !ecfg.all && enforce_crit_mem_prot;
+// Clear the configurations of @slice as dictated by @ecfg. Returns whether
+// the unit configlet was updated and thus daemon_reload is needed.
+fn clear_slice_int(
+    slice: Slice,
+    ecfg: &EnforceConfig,
+    root: &str,
+    keep_running: bool,
+) -> Result<bool> {
+    let enforce_crit_mem_prot = ecfg.crit_mem_prot && slice_needs_crit_mem_prot(slice);
+    let enforce = ecfg.all || enforce_crit_mem_prot;
+    let mem_prot_only = !ecfg.all && enforce_crit_mem_prot;
+
+    if !enforce {
+        return Ok(false);
+    }
 
-        if !enforce {
-            continue;
+    let updated = match clear_one_slice(slice, mem_prot_only, keep_running) {
+        Ok(v) => v,
+        Err(e) => {
+            warn!(
+                "resctl: Failed to clear configurations for {:?} ({:?})",
+                slice.name(),
+                &e
+            );
+            false
         }
+    };
+
+    if slice_needs_mem_prot_propagation(slice) {
+        propagate_one_slice(slice, &Default::default(), root)?;
+    }
+    Ok(updated)
+}
 
-        match clear_one_slice(slice, mem_prot_only) {
-            Ok(true) => updated = true,
-            Ok(false) => {}
+/// Clear the configurations of a single slice leaving the rest alone. If
+/// `keep_running`, start/stop slices such as Side aren't stopped.
+pub fn clear_slice(
+    slice: Slice,
+    ecfg: &EnforceConfig,
+    root: &str,
+    keep_running: bool,
+) -> Result<()> {
+    if clear_slice_int(slice, ecfg, root, keep_running)? {
+        systemd::daemon_reload()?;
+    }
+    Ok(())
+}
+
+/// Clear the configurations of the user-defined slices in `knobs`.
+pub fn clear_custom_slices(knobs: &SliceKnobs, ecfg: &EnforceConfig) -> Result<()> {
+    if !ecfg.all {
+        return Ok(());
+    }
+    let mut updated = false;
+    for (name, _) in knobs.custom_slices() {
+        match clear_one_unit(name, false, false) {
+            Ok(v) => updated |= v,
             Err(e) => warn!(
                 "resctl: Failed to clear configurations for {:?} ({:?})",
-                slice.name(),
-                &e
+                name, &e
             ),
         }
+    }
+    if updated {
+        systemd::daemon_reload()?;
+    }
+    Ok(())
+}
 
-        if slice_needs_mem_prot_propagation(slice) {
-            propagate_one_slice(slice, &Default::default())?;
+/// Clear the configurations of all slices. If `keep_running`, start/stop
+/// slices are left running so that only the enforcement is dropped.
+pub fn clear_slices(ecfg: &EnforceConfig, root: &str, keep_running: bool) -> Result<()> {
+    let mut updated = false;
+    for slice in Slice::into_enum_iter() {
+        if clear_slice_int(slice, ecfg, root, keep_running)? {
+            updated = true;
         }
     }
     if updated {
@@ -370,26 +827,77 @@ pub fn clear_slices(ecfg: &EnforceConfig) -> Result<()> {
     Ok(())
 }
 
-fn fix_overrides(dseqs: &DisableSeqKnobs, cfg: &Config) -> Result<()> {
+// Write a fix to a cgroup file. Returns Ok(false) if the controller is
+// busy, which is transient and left to the next pass. Permission errors
+// won't go away by retrying and are reported as such.
+fn write_fix(path: &str, line: &str) -> Result<bool> {
+    match write_one_line(path, line) {
+        Ok(()) => Ok(true),
+        Err(e) => match e
+            .downcast_ref::<std::io::Error>()
+            .and_then(|x| x.raw_os_error())
+        {
+            Some(libc::EBUSY) => {
+                info!(
+                    "resctl: Controller busy writing {:?} to {:?}, will retry",
+                    line, path
+                );
+                Ok(false)
+            }
+            Some(libc::EACCES) | Some(libc::EPERM) => bail!(
+                "Permission denied writing {:?} to {:?}, run as root",
+                line,
+                path
+            ),
+            _ => Err(e.context(format!("Failed to write {:?} to {:?}", line, path))),
+        },
+    }
+}
+
+fn fix_overrides(dseqs: &DisableSeqKnobs, cfg: &Config, audit: Option<&AuditLog>) -> Result<()> {
     let seq = super::instance_seq();
     let mut disable = String::new();
     let mut enable = String::new();
 
+    let missing = &cfg.missing_ctrls;
+
     if cfg.enforce.all {
-        if dseqs.cpu < seq {
-            enable += " +cpu";
-        } else {
-            disable += " -cpu";
+        if !missing.contains("cpu") {
+            if dseqs.cpu < seq {
+                enable += " +cpu";
+            } else {
+                disable += " -cpu";
+            }
+        }
+        if !missing.contains("io") {
+            enable += " +io";
         }
-        enable += " +io";
     }
 
-    if cfg.enforce.crit_mem_prot {
+    if cfg.enforce.crit_mem_prot && !missing.contains("memory") {
         enable += " +memory";
     }
 
+    let sc_path = &format!("{}/cgroup.subtree_control", &cfg.cgrp_root);
+    if audit.is_some() {
+        let line = read_one_line(sc_path)?;
+        let cur: BTreeSet<&str> = line.split_whitespace().collect();
+        let mut expected = cur.clone();
+        for ctrl in enable.split_whitespace() {
+            expected.insert(&ctrl[1..]);
+        }
+        for ctrl in disable.split_whitespace() {
+            expected.remove(&ctrl[1..]);
+        }
+        if cur != expected {
+            let expected: Vec<&str> = expected.into_iter().collect();
+            audit_mismatch(audit, cfg, sc_path, &line, &expected.join(" "));
+        }
+        return Ok(());
+    }
+
     if disable.len() > 0 {
-        let mut scs: Vec<String> = glob("/sys/fs/cgroup/**/cgroup.subtree_control")
+        let mut scs: Vec<String> = glob(&format!("{}/**/cgroup.subtree_control", &cfg.cgrp_root))
             .unwrap()
             .filter_map(|x| x.ok())
             .map(|x| x.to_str().unwrap().to_string())
@@ -398,7 +906,7 @@ fn fix_overrides(dseqs: &DisableSeqKnobs, cfg: &Config) -> Result<()> {
 
         let mut nr_failed = 0;
         for sc in &scs {
-            if let Err(e) = write_one_line(sc, &disable) {
+            if let Err(e) = write_fix(sc, &disable) {
                 if nr_failed == 0 {
                     warn!(
                         "resctl: Failed to write {:?} to {:?} ({:?})",
@@ -418,13 +926,100 @@ fn fix_overrides(dseqs: &DisableSeqKnobs, cfg: &Config) -> Result<()> {
     }
 
     if enable.len() > 0 {
-        write_one_line("/sys/fs/cgroup/cgroup.subtree_control", &enable)?;
+        write_fix(sc_path, &enable)?;
     }
 
     Ok(())
 }
 
-fn fix_slice_cpu(sk: &SliceConfig, path: &str, enable: bool) -> Result<()> {
+/// A cgroup value correction made by `verify_and_fix_slices()` or, when
+/// auditing, a mismatch it would have corrected. `slice` is the top-level slice
+/// the fixed cgroup belongs to and `cgrp` the cgroup's path relative to the
+/// cgroup root. The two differ for propagated memory protection.
+#[derive(Debug, Clone)]
+pub struct SliceFixEvent {
+    pub slice: String,
+    pub cgrp: String,
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+fn fix_event(root: &str, path: &str, old: &str, new: &str) -> SliceFixEvent {
+    let path = Path::new(path);
+    let cgrp = match path.parent().and_then(|p| p.strip_prefix(root).ok()) {
+        Some(rel) if rel.as_os_str().len() > 0 => rel.to_string_lossy().into_owned(),
+        _ => ROOT_SLICE.to_string(),
+    };
+    SliceFixEvent {
+        slice: cgrp.split('/').next().unwrap().to_string(),
+        field: path
+            .file_name()
+            .unwrap_or(OsStr::new(""))
+            .to_string_lossy()
+            .into_owned(),
+        cgrp,
+        old: old.trim().to_string(),
+        new: new.to_string(),
+    }
+}
+
+// Returns true if auditing, in which case the mismatch is recorded and the
+// caller must leave the cgroup alone.
+fn audit_mismatch(
+    audit: Option<&AuditLog>,
+    cfg: &Config,
+    path: &str,
+    old: &str,
+    new: &str,
+) -> bool {
+    match audit {
+        Some(log) => {
+            log.borrow_mut()
+                .push(fix_event(&cfg.cgrp_root, path, old, new));
+            true
+        }
+        None => false,
+    }
+}
+
+fn notify_fix(cfg: &Config, path: &str, old: &str, new: &str) {
+    let ev = fix_event(&cfg.cgrp_root, path, old, new);
+    debug!(
+        "resctl: {}/{} changed {:?} -> {:?}",
+        &ev.cgrp, &ev.field, &ev.old, &ev.new
+    );
+    *SLICE_FIX_COUNTS
+        .lock()
+        .unwrap()
+        .entry(format!("{}/{}", &ev.slice, &ev.field))
+        .or_default() += 1;
+}
+
+fn fix_budget_exhausted(cfg: &Config) -> bool {
+    match cfg.max_fixes_per_pass {
+        Some(max) => NR_PASS_FIXES.load(Ordering::Relaxed) >= max,
+        None => false,
+    }
+}
+
+// Returns false if the fix should be deferred to the next pass.
+fn take_fix_budget(cfg: &Config) -> bool {
+    if fix_budget_exhausted(cfg) {
+        NR_PASS_DEFERRED.fetch_add(1, Ordering::Relaxed);
+        return false;
+    }
+    NR_PASS_FIXES.fetch_add(1, Ordering::Relaxed);
+    true
+}
+
+fn fix_slice_cpu(
+    sk: &SliceConfig,
+    path: &str,
+    enable: bool,
+    cfg: &Config,
+    audit: Option<&AuditLog>,
+) -> Result<()> {
     if !enable {
         return Ok(());
     }
@@ -434,17 +1029,29 @@ fn fix_slice_cpu(sk: &SliceConfig, path: &str, enable: bool) -> Result<()> {
     match scan_fmt!(&line, "{d}", u32) {
         Ok(v) if v == sk.cpu_weight => {}
         v => {
+            let new = format!("{}", sk.cpu_weight);
+            if audit_mismatch(audit, cfg, &cpu_weight_path, &line, &new) || !take_fix_budget(cfg) {
+                return Ok(());
+            }
             info!(
                 "resctl: {:?} should be {} but is {:?}, fixing",
                 &cpu_weight_path, sk.cpu_weight, &v
             );
-            write_one_line(&cpu_weight_path, &format!("{}", sk.cpu_weight))?;
+            if write_fix(&cpu_weight_path, &new)? {
+                notify_fix(cfg, &cpu_weight_path, &line, &new);
+            }
         }
     }
     Ok(())
 }
 
-fn fix_slice_io(sk: &SliceConfig, path: &str, enable: bool) -> Result<()> {
+fn fix_slice_io(
+    sk: &SliceConfig,
+    path: &str,
+    enable: bool,
+    cfg: &Config,
+    audit: Option<&AuditLog>,
+) -> Result<()> {
     if !enable {
         return Ok(());
     }
@@ -454,39 +1061,97 @@ fn fix_slice_io(sk: &SliceConfig, path: &str, enable: bool) -> Result<()> {
     match scan_fmt!(&line, "default {d}", u32) {
         Ok(v) if v == sk.io_weight => {}
         v => {
+            let new = format!("default {}", sk.io_weight);
+            if audit_mismatch(audit, cfg, &io_weight_path, &line, &new) || !take_fix_budget(cfg) {
+                return Ok(());
+            }
             info!(
                 "resctl: {:?} should be {} but is {:?}, fixing",
                 &io_weight_path, sk.io_weight, &v
             );
-            write_one_line(&io_weight_path, &format!("default {}", sk.io_weight))?;
+            if write_fix(&io_weight_path, &new)? {
+                notify_fix(cfg, &io_weight_path, &line, &new);
+            }
         }
     }
     Ok(())
 }
 
-fn fix_cgrp_mem(path: &str, is_limit: bool, knob: MemoryKnob) -> Result<()> {
-    trace!("resctl: verify: {:?}", path);
-    let line = read_one_line(path)?;
-    let cur = match line.as_ref() {
+/// Returns the lower of memory.max and memory.high of the parent of
+/// `cgrp`, `None` if unlimited.
+fn read_parent_mem_limit(cgrp: &str) -> Option<u64> {
+    let parent = Path::new(cgrp).parent()?;
+    let mut limit = None;
+    for file in &["memory.max", "memory.high"] {
+        if let Ok(line) = read_one_line(parent.join(file)) {
+            if let Ok(v) = line.parse::<u64>() {
+                limit = Some(limit.unwrap_or(std::u64::MAX).min(v));
+            }
+        }
+    }
+    limit
+}
+
+/// Resolve PercentOfParent against the parent of `cgrp` so that applying
+/// and verifying the knob agree on the number of bytes.
+fn resolve_mem_knob(knob: MemoryKnob, is_limit: bool, cgrp: &str, total: u64) -> MemoryKnob {
+    match knob {
+        MemoryKnob::PercentOfParent(_) => MemoryKnob::Bytes(knob.nr_bytes_with_total(
+            is_limit,
+            read_parent_mem_limit(cgrp),
+            total,
+        )),
+        knob => knob,
+    }
+}
+
+// Whether the cgroup memory file content `line` is close enough to `target`
+// bytes on a machine with `total` bytes of memory.
+fn cgrp_mem_matches(line: &str, target: u64, total: u64) -> bool {
+    let cur = match line {
         "max" => Some(std::u64::MAX),
         v => v.parse::<u64>().ok(),
     };
-    if let Some(mut v) = cur {
-        // max can be mapped to either u64::MAX or total_memory(), limit to
-        // the latter to avoid spurious mismatches.
-        let target = knob.nr_bytes(is_limit).min(total_memory() as u64);
-        v = v.min(total_memory() as u64);
-
-        if target == v || (target > 0 && ((v as f64 - target as f64) / target as f64).abs() < 0.1) {
-            return Ok(());
+    match cur {
+        Some(v) => {
+            // max can be mapped to either u64::MAX or total memory, limit
+            // to the latter to avoid spurious mismatches.
+            let target = target.min(total);
+            let v = v.min(total);
+            target == v || (target > 0 && ((v as f64 - target as f64) / target as f64).abs() < 0.1)
         }
+        None => false,
+    }
+}
+
+fn fix_cgrp_mem(
+    path: &str,
+    is_limit: bool,
+    mut knob: MemoryKnob,
+    cfg: &Config,
+    audit: Option<&AuditLog>,
+) -> Result<()> {
+    trace!("resctl: verify: {:?}", path);
+    if let Some(cgrp) = Path::new(path).parent().and_then(|x| x.to_str()) {
+        knob = resolve_mem_knob(knob, is_limit, cgrp, cfg.mem_total);
+    }
+
+    let line = read_one_line(path)?;
+    if cgrp_mem_matches(&line, knob.nr_bytes(is_limit), cfg.mem_total) {
+        return Ok(());
     }
     let expected = mknob_to_cgrp_string(&knob, is_limit);
+    if audit_mismatch(audit, cfg, path, &line, &expected) || !take_fix_budget(cfg) {
+        return Ok(());
+    }
     info!(
         "resctl: {:?} should be {:?} but is {:?}, fixing",
         path, &expected, &line
     );
-    write_one_line(path, &expected)?;
+    if !write_fix(path, &expected)? {
+        return Ok(());
+    }
+    notify_fix(cfg, path, &line, &expected);
 
     let file = Path::new(path)
         .file_name()
@@ -515,12 +1180,23 @@ fn fix_cgrp_mem(path: &str, is_limit: bool, knob: MemoryKnob) -> Result<()> {
     unit.apply()
 }
 
-fn fix_recursive_mem_prot(parent: &str, file: &str, knob: MemoryKnob) -> Result<()> {
+fn fix_recursive_mem_prot(
+    parent: &str,
+    file: &str,
+    knob: MemoryKnob,
+    cfg: &Config,
+    audit: Option<&AuditLog>,
+) -> Result<()> {
     for p in glob(&format!("{}/*/**/{}", parent, file))
         .unwrap()
         .filter_map(Result::ok)
     {
-        if let Err(e) = fix_cgrp_mem(p.to_str().unwrap(), false, knob) {
+        // Checking the rest is pointless if none can be fixed.
+        if fix_budget_exhausted(cfg) {
+            NR_PASS_DEFERRED.fetch_add(1, Ordering::Relaxed);
+            break;
+        }
+        if let Err(e) = fix_cgrp_mem(p.to_str().unwrap(), false, knob, cfg, audit) {
             warn!(
                 "resctl: failed to fix memory protection for {:?} ({:?})",
                 p, &e
@@ -530,6 +1206,33 @@ fn fix_recursive_mem_prot(parent: &str, file: &str, knob: MemoryKnob) -> Result<
     Ok(())
 }
 
+// systemd doesn't have a property for memory.oom.group on slices, so the
+// cgroup file is the only place to set it.
+fn fix_cgrp_oom_group(
+    path: &str,
+    oom_group: bool,
+    cfg: &Config,
+    audit: Option<&AuditLog>,
+) -> Result<()> {
+    trace!("resctl: verify: {:?}", path);
+    let line = read_one_line(path)?;
+    let new = format!("{}", oom_group as u32);
+    if line.trim() == new {
+        return Ok(());
+    }
+    if audit_mismatch(audit, cfg, path, &line, &new) || !take_fix_budget(cfg) {
+        return Ok(());
+    }
+    info!(
+        "resctl: {:?} should be {:?} but is {:?}, fixing",
+        path, &new, &line
+    );
+    if write_fix(path, &new)? {
+        notify_fix(cfg, path, &line, &new);
+    }
+    Ok(())
+}
+
 fn fix_slice_mem(
     sk: &SliceConfig,
     path: &str,
@@ -537,66 +1240,281 @@ fn fix_slice_mem(
     verify_mem_high: bool,
     propagate_mem_prot: bool,
     recursive_mem_prot: bool,
+    cfg: &Config,
+    audit: Option<&AuditLog>,
 ) -> Result<()> {
     if enable {
-        fix_cgrp_mem(&(path.to_string() + "/memory.min"), false, sk.mem_min)?;
-        fix_cgrp_mem(&(path.to_string() + "/memory.low"), false, sk.mem_low)?;
-        fix_cgrp_mem(&(path.to_string() + "/memory.max"), true, MemoryKnob::None)?;
+        fix_cgrp_mem(
+            &(path.to_string() + "/memory.min"),
+            false,
+            sk.mem_min,
+            cfg,
+            audit,
+        )?;
+        fix_cgrp_mem(
+            &(path.to_string() + "/memory.low"),
+            false,
+            sk.mem_low,
+            cfg,
+            audit,
+        )?;
+        fix_cgrp_mem(
+            &(path.to_string() + "/memory.max"),
+            true,
+            MemoryKnob::None,
+            cfg,
+            audit,
+        )?;
 
         if verify_mem_high {
-            fix_cgrp_mem(&(path.to_string() + "/memory.high"), true, sk.mem_high)?;
+            fix_cgrp_mem(
+                &(path.to_string() + "/memory.high"),
+                true,
+                sk.mem_high,
+                cfg,
+                audit,
+            )?;
+        }
+
+        if let Some(oom_group) = sk.oom_group {
+            fix_cgrp_oom_group(
+                &(path.to_string() + "/memory.oom.group"),
+                oom_group,
+                cfg,
+                audit,
+            )?;
         }
 
         if propagate_mem_prot {
-            if recursive_mem_prot {
-                fix_recursive_mem_prot(path, "memory.min", MemoryKnob::Bytes(0))?;
-                fix_recursive_mem_prot(path, "memory.low", MemoryKnob::Bytes(0))?;
-            } else {
-                fix_recursive_mem_prot(path, "memory.min", sk.mem_min)?;
-                fix_recursive_mem_prot(path, "memory.low", sk.mem_low)?;
+            // Descendants get the bytes the slice itself resolved to.
+            let (min, low) = match recursive_mem_prot {
+                true => (MemoryKnob::Bytes(0), MemoryKnob::Bytes(0)),
+                false => (
+                    resolve_mem_knob(sk.mem_min, false, path, cfg.mem_total),
+                    resolve_mem_knob(sk.mem_low, false, path, cfg.mem_total),
+                ),
+            };
+            if sk.propagate_mem_min {
+                fix_recursive_mem_prot(path, "memory.min", min, cfg, audit)?;
+            }
+            if sk.propagate_mem_low {
+                fix_recursive_mem_prot(path, "memory.low", low, cfg, audit)?;
             }
         }
     } else {
-        fix_cgrp_mem(&(path.to_string() + "/memory.min"), false, MemoryKnob::None)?;
-        fix_cgrp_mem(&(path.to_string() + "/memory.low"), false, MemoryKnob::None)?;
+        fix_cgrp_mem(
+            &(path.to_string() + "/memory.min"),
+            false,
+            MemoryKnob::None,
+            cfg,
+            audit,
+        )?;
+        fix_cgrp_mem(
+            &(path.to_string() + "/memory.low"),
+            false,
+            MemoryKnob::None,
+            cfg,
+            audit,
+        )?;
     }
     Ok(())
 }
 
+/// Cumulative counts of the fixes applied by `verify_and_fix_slices()`
+/// keyed by "SLICE/FIELD".
+pub fn slice_fix_counts() -> BTreeMap<String, u64> {
+    SLICE_FIX_COUNTS.lock().unwrap().clone()
+}
+
+// Nudge memory.high up if memory pressure is above the target, down
+// otherwise, staying within the configured bounds.
+fn drive_work_senpai(knobs: &WorkSenpaiKnobs, path: &str, total: u64) -> Result<u64> {
+    let (pressure, _) = match read_psi_avg10(&(path.to_string() + "/memory.pressure")) {
+        Some(v) => v,
+        None => bail!("failed to read memory pressure"),
+    };
+
+    let high_path = path.to_string() + "/memory.high";
+    let line = read_one_line(&high_path)?;
+    let cur = match line.as_ref() {
+        "max" => read_one_line(&(path.to_string() + "/memory.current"))?.parse::<u64>()?,
+        v => v.parse::<u64>()?,
+    } as f64;
+
+    let total = total as f64;
+    let next = if pressure > knobs.target_pressure {
+        cur * (1.0 + knobs.step)
+    } else {
+        cur * (1.0 - knobs.step)
+    };
+    let next = next
+        .min(knobs.max_bytes_frac * total)
+        .max(knobs.min_bytes_frac * total)
+        .round() as u64;
+
+    trace!(
+        "resctl: senpai: {:?} pressure={:.3} target={:.3} high={} -> {}",
+        path,
+        pressure,
+        knobs.target_pressure,
+        &line,
+        next
+    );
+    if next != cur as u64 {
+        write_one_line(&high_path, &format!("{}", next))?;
+    }
+    Ok(next)
+}
+
+/// Enforcement may be on while every controller is disabled through the
+/// slice knobs' disable_seqs or missing sysreqs, in which case nothing is
+/// actually enforced. Warn so that it doesn't go unnoticed.
+pub fn warn_if_nothing_enforced(knobs: &SliceKnobs, cfg: &Config) {
+    if !cfg.enforce.all {
+        return;
+    }
+
+    let seq = super::instance_seq();
+    let dseqs = &knobs.disable_seqs;
+    let mut off = vec![];
+    if dseqs.cpu >= seq {
+        off.push("cpu (disabled)");
+    }
+    if dseqs.io >= seq {
+        off.push("io (disabled)");
+    } else if cfg.sr_failed.contains(&SysReq::IoWeight) {
+        off.push("io (io.weight unavailable)");
+    }
+    if dseqs.mem >= seq {
+        off.push("memory (disabled)");
+    }
+
+    if off.len() == 3 {
+        warn!(
+            "resctl: Enforcement is on but nothing is enforced, controllers off: {}",
+            off.join(", ")
+        );
+    }
+}
+
+/// Returns the memory.high work_senpai picked for workload.slice if it's
+/// active.
 pub fn verify_and_fix_slices(
     knobs: &SliceKnobs,
     workload_senpai: bool,
     cfg: &Config,
-) -> Result<()> {
+) -> Result<Option<u64>> {
+    check_cgroup_writable(cfg)?;
+    verify_slices_int(knobs, workload_senpai, cfg, None)
+}
+
+/// Read-only counterpart of `verify_and_fix_slices()`. Nothing is written
+/// to cgroupfs or systemd and all mismatches are returned.
+pub fn audit_slices(
+    knobs: &SliceKnobs,
+    workload_senpai: bool,
+    cfg: &Config,
+) -> Result<Vec<SliceFixEvent>> {
+    let log = AuditLog::default();
+    verify_slices_int(knobs, workload_senpai, cfg, Some(&log))?;
+    Ok(log.into_inner())
+}
+
+// Returns whether `path` exists and has been around for at least one
+// verification pass. A slice dir which just appeared may still be being set
+// up by systemd and configuring it now can leave it half-configured.
+// Nothing is deferred on the first pass as apply_slices() has just run.
+fn slice_cgrp_settled(
+    path: &str,
+    seen: &mut BTreeSet<String>,
+    first_pass: bool,
+    audit: Option<&AuditLog>,
+) -> bool {
+    if !AsRef::<Path>::as_ref(path).exists() {
+        if audit.is_none() {
+            seen.remove(path);
+        }
+        return false;
+    }
+    if audit.is_some() {
+        return true;
+    }
+    if seen.insert(path.to_string()) && !first_pass {
+        info!(
+            "resctl: {:?} just appeared, deferring configuration to the next pass",
+            path
+        );
+        return false;
+    }
+    true
+}
+
+fn verify_slices_int(
+    knobs: &SliceKnobs,
+    workload_senpai: bool,
+    cfg: &Config,
+    audit: Option<&AuditLog>,
+) -> Result<Option<u64>> {
+    NR_PASS_FIXES.store(0, Ordering::Relaxed);
+    NR_PASS_DEFERRED.store(0, Ordering::Relaxed);
+
     let seq = super::instance_seq();
     let dseqs = &knobs.disable_seqs;
-    let line = read_one_line("/sys/fs/cgroup/cgroup.subtree_control")?;
+    let line = read_one_line(&format!("{}/cgroup.subtree_control", &cfg.cgrp_root))?;
+
+    let missing = &cfg.missing_ctrls;
+    let (has_cpu, has_io, has_mem) = (
+        !missing.contains("cpu"),
+        !missing.contains("io"),
+        !missing.contains("memory"),
+    );
 
-    if (cfg.enforce.all && ((dseqs.cpu < seq) != line.contains("cpu") || !line.contains("io")))
-        || (cfg.enforce.crit_mem_prot && !line.contains("memory"))
+    if (cfg.enforce.all
+        && ((has_cpu && (dseqs.cpu < seq) != line.contains("cpu"))
+            || (has_io && !line.contains("io"))))
+        || (cfg.enforce.crit_mem_prot && has_mem && !line.contains("memory"))
     {
         info!("resctl: Controller enable state disagrees with overrides, fixing");
-        fix_overrides(dseqs, cfg)?;
+        fix_overrides(dseqs, cfg, audit)?;
     }
 
     let recursive_mem_prot = cfg.memcg_recursive_prot();
+    // oomd senpai takes precedence if enabled.
+    let work_senpai = knobs.work_senpai.enable && !workload_senpai;
+    let mut work_senpai_high = None;
+
+    let mut seen_guard = SEEN_SLICE_CGRPS.lock().unwrap();
+    let first_pass = seen_guard.is_none();
+    let mut audit_seen = BTreeSet::new();
+    let seen = match audit {
+        None => seen_guard.get_or_insert_with(BTreeSet::new),
+        Some(_) => &mut audit_seen,
+    };
 
     for slice in Slice::into_enum_iter() {
         let sk = knobs.slices.get(slice.name()).unwrap();
 
-        let path = slice.cgrp();
-        if !AsRef::<Path>::as_ref(path).exists() {
+        let path = &slice_cgrp(&cfg.cgrp_root, slice);
+        if !slice_cgrp_settled(path, seen, first_pass, audit) {
             continue;
         }
 
         if cfg.enforce.all {
-            fix_slice_cpu(&sk, path, dseqs.cpu < seq)?;
-            fix_slice_io(&sk, path, dseqs.io < seq)?;
+            fix_slice_cpu(&sk, path, has_cpu && dseqs.cpu < seq, cfg, audit)?;
+            fix_slice_io(
+                &sk,
+                path,
+                has_io && dseqs.io < seq && !cfg.sr_failed.contains(&SysReq::IoWeight),
+                cfg,
+                audit,
+            )?;
         }
 
-        if cfg.enforce.all || (cfg.enforce.crit_mem_prot && slice_needs_crit_mem_prot(slice)) {
+        if has_mem
+            && (cfg.enforce.all || (cfg.enforce.crit_mem_prot && slice_needs_crit_mem_prot(slice)))
+        {
             let (enable_mem, verify_mem_high) = match slice {
-                Slice::Work => (dseqs.mem < seq, !workload_senpai),
+                Slice::Work => (dseqs.mem < seq, !workload_senpai && !work_senpai),
                 _ => (true, true),
             };
             let propagate_mem_prot = slice_needs_mem_prot_propagation(slice);
@@ -608,12 +1526,204 @@ pub fn verify_and_fix_slices(
                 verify_mem_high,
                 propagate_mem_prot,
                 recursive_mem_prot,
+                cfg,
+                audit,
             )?;
+
+            if slice == Slice::Work && enable_mem && work_senpai && audit.is_none() {
+                match drive_work_senpai(&knobs.work_senpai, path, cfg.mem_total) {
+                    Ok(high) => {
+                        work_senpai_high = Some(high);
+                        WORK_SENPAI_WARNED.store(false, Ordering::Relaxed);
+                    }
+                    Err(e) => {
+                        if WORK_SENPAI_WARNED.swap(true, Ordering::Relaxed) {
+                            debug!("resctl: senpai: Failed to drive {:?} ({:#})", path, &e);
+                        } else {
+                            warn!("resctl: senpai: Failed to drive {:?} ({:#})", path, &e);
+                        }
+                    }
+                }
+            }
         }
     }
 
     if cfg.enforce.all {
-        check_other_io_controllers(&mut BTreeSet::new());
+        for (name, sk) in knobs.custom_slices() {
+            let path = &format!("{}/{}", &cfg.cgrp_root, name);
+            if !slice_cgrp_settled(path, seen, first_pass, audit) {
+                continue;
+            }
+            fix_slice_cpu(&sk, path, has_cpu && dseqs.cpu < seq, cfg, audit)?;
+            fix_slice_io(
+                &sk,
+                path,
+                has_io && dseqs.io < seq && !cfg.sr_failed.contains(&SysReq::IoWeight),
+                cfg,
+                audit,
+            )?;
+            if has_mem {
+                let sk = SliceConfig {
+                    oom_group: None,
+                    ..sk.clone()
+                };
+                fix_slice_mem(
+                    &sk,
+                    path,
+                    dseqs.mem < seq,
+                    true,
+                    false,
+                    recursive_mem_prot,
+                    cfg,
+                    audit,
+                )?;
+            }
+        }
     }
-    Ok(())
+    drop(seen_guard);
+
+    if NR_PASS_DEFERRED.load(Ordering::Relaxed) > 0 {
+        info!(
+            "resctl: Applied {} fixes, deferring the rest to the next pass",
+            NR_PASS_FIXES.load(Ordering::Relaxed)
+        );
+    }
+
+    if cfg.enforce.all {
+        check_other_io_controllers(&mut BTreeSet::new(), &cfg.io_ctrl_ignore, &cfg.cgrp_root);
+    }
+    Ok(work_senpai_high)
+}
+
+fn read_cgrp_mem_knob(path: &str) -> Result<MemoryKnob> {
+    let line = read_one_line(path)?;
+    match line.as_ref() {
+        "max" => Ok(MemoryKnob::None),
+        v => Ok(MemoryKnob::Bytes(v.parse::<u64>()?)),
+    }
+}
+
+// The effective configs are read per controller so that a missing
+// controller doesn't hide the state of the others.
+fn read_effective_cpu_weight(cgrp: &str) -> Result<u32> {
+    let line = read_one_line(&format!("{}/cpu.weight", cgrp))?;
+    Ok(line.trim().parse::<u32>()?)
+}
+
+fn read_effective_io_weight(cgrp: &str) -> Result<u32> {
+    let line = read_one_line(&format!("{}/io.weight", cgrp))?;
+    Ok(scan_fmt!(&line, "default {d}", u32)?)
+}
+
+// Returns memory.min, low and high.
+fn read_effective_mem_knobs(cgrp: &str) -> Result<(MemoryKnob, MemoryKnob, MemoryKnob)> {
+    Ok((
+        read_cgrp_mem_knob(&format!("{}/memory.min", cgrp))?,
+        read_cgrp_mem_knob(&format!("{}/memory.low", cgrp))?,
+        read_cgrp_mem_knob(&format!("{}/memory.high", cgrp))?,
+    ))
+}
+
+const DOCTOR_SLICE: &str = "rd-doctor.slice";
+
+fn doctor_one(ctrl: &str, passed: Result<bool>) -> bool {
+    match passed {
+        Ok(true) => {
+            info!("doctor: {:<8} PASS", ctrl);
+            true
+        }
+        Ok(false) => {
+            error!("doctor: {:<8} FAIL (knobs not reflected in cgroupfs)", ctrl);
+            false
+        }
+        Err(e) => {
+            error!("doctor: {:<8} FAIL ({:#})", ctrl, &e);
+            false
+        }
+    }
+}
+
+/// Program a throwaway slice with known knobs, read them back from cgroupfs
+/// and report whether each controller enforces them. Returns whether all
+/// checks passed.
+pub fn doctor(cfg: &Config) -> bool {
+    let want = SliceConfig {
+        cpu_weight: 123,
+        io_weight: 234,
+        mem_min: MemoryKnob::Bytes(16 << 20),
+        mem_low: MemoryKnob::Bytes(32 << 20),
+        mem_high: MemoryKnob::Bytes(1 << 30),
+        ..Default::default()
+    };
+    let cgrp = format!("{}/{}", &cfg.cgrp_root, DOCTOR_SLICE);
+
+    let ctrls = available_controllers(&cfg.cgrp_root);
+    info!(
+        "doctor: available controllers: {}",
+        ctrls.iter().cloned().collect::<Vec<String>>().join(" ")
+    );
+
+    let configlet = build_configlet(
+        DOCTOR_SLICE,
+        Some(want.cpu_weight),
+        Some(want.io_weight),
+        Some(want.mem_min),
+        Some(want.mem_low),
+        Some(want.mem_high),
+    );
+
+    let setup = apply_unit_configlet(DOCTOR_SLICE, &configlet, false)
+        .and_then(|_| systemd::daemon_reload())
+        .and_then(|_| {
+            let mut unit = systemd::Unit::new_sys(DOCTOR_SLICE.into())?;
+            unit.try_start()
+        });
+
+    let mut passed = match &setup {
+        Ok(_) => {
+            let mem_eq = |a: &MemoryKnob, b: &MemoryKnob, is_limit| {
+                a.nr_bytes(is_limit) == b.nr_bytes(is_limit)
+            };
+            let cpu = doctor_one(
+                "cpu",
+                read_effective_cpu_weight(&cgrp).map(|w| w == want.cpu_weight),
+            );
+            let io = doctor_one(
+                "io",
+                read_effective_io_weight(&cgrp).map(|w| w == want.io_weight),
+            );
+            let mem = doctor_one(
+                "memory",
+                read_effective_mem_knobs(&cgrp).map(|(min, low, high)| {
+                    mem_eq(&min, &want.mem_min, false)
+                        && mem_eq(&low, &want.mem_low, false)
+                        && mem_eq(&high, &want.mem_high, true)
+                }),
+            );
+            cpu && io && mem
+        }
+        Err(e) => {
+            for ctrl in &["cpu", "io", "memory"] {
+                doctor_one(ctrl, Err(anyhow::anyhow!("{:#}", e)));
+            }
+            false
+        }
+    };
+
+    for sr in cfg.sr_failed.iter() {
+        error!("doctor: missed sysreq {:?}", sr);
+        passed = false;
+    }
+
+    match clear_one_unit(DOCTOR_SLICE, false, true) {
+        Ok(true) => {
+            if let Err(e) = systemd::daemon_reload() {
+                warn!("doctor: Failed to reload systemd after cleanup ({:#})", &e);
+            }
+        }
+        Ok(false) => {}
+        Err(e) => warn!("doctor: Failed to clean up {:?} ({:#})", DOCTOR_SLICE, &e),
+    }
+
+    passed
 }
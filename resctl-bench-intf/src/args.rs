@@ -2,19 +2,19 @@
 use anyhow::{bail, Context, Result};
 use log::error;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
 use std::process::exit;
 use util::*;
 
-use super::{IoCostQoSOvr, JobSpec};
-use rd_agent_intf;
+use super::{IoCostQoSOvr, JobProfiles, JobSpec};
+use rd_agent_intf::{self, Slice, SysReq, ALL_SYSREQS_SET};
 
 lazy_static::lazy_static! {
     static ref TOP_ARGS_STR: String = {
         let dfl_args = Args::default();
         format!(
-            "<RESULTFILE>                 'Record the bench results into the specified json file'
+            "<RESULTFILE>                 'Record the bench results into the specified json file (.msgpack for MessagePack)'
              -d, --dir=[TOPDIR]           'Top-level dir for operation and scratch files (default: {dfl_dir})'
              -D, --dev=[DEVICE]           'Scratch device override (e.g. nvme0n1)'
              -l, --linux=[PATH]           'Path to linux.tar, downloaded automatically if not specified'
@@ -31,6 +31,21 @@ lazy_static::lazy_static! {
                  --iocost-from-sys        'Use parameters from io.cost.{{model,qos}} instead of bench.json'
                  --keep-reports           'Don't delete expired report files'
                  --clear-reports          'Remove existing report files'
+                 --annotate=[KEY=VAL,...] 'Attach free-form annotations to the results of this run'
+                 --profiles=[FILE]        'Load named job prop sets selected with \"profile=NAME\" from FILE'
+                 --baseline=[FILE]        'Fail if key metrics regress against the matching results in FILE'
+                 --baseline-thr=[THRS]    'Regression thresholds, e.g. \"default=5%,rps_max=2%\" (default: {dfl_baseline_thr}%)'
+                 --exit-summary=[FILE]    'Write JSON summary of job outcomes to FILE (\"-\" for stdout)'
+                 --nice=[LEVEL]           'Run resctl-bench itself at nice LEVEL [-20, 19]'
+                 --ionice=[CLASS[:LEVEL]] 'Run resctl-bench itself at io priority CLASS (rt|be|idle) and LEVEL [0, 7]'
+                 --minder-intv=[DUR]      'Interval to poll rd-agent status and reports at (default: 1s)'
+                 --watchdog=[CEILS]       'Abort if workload.slice stays over the ceilings, e.g. \"pids=10000,mem=32G,period=30s\"'
+                 --agent-slice=[SLICE]    'Run rd-agent in SLICE instead of hostcritical (its usage is charged to SLICE)'
+                 --raw-reports            'Store gzipped per-second reports of each job in its record (several KiB per second of run time)'
+                 --rapl                   'Measure CPU package energy consumption via RAPL'
+                 --fail-on-warn           'Fail jobs which logged warnings or errors while running'
+                 --strict-sysreqs=[ALLOW] 'Fail jobs which miss sysreqs other than the comma separated ALLOW list'
+                 --validate               'Validate the result file without running anything'
                  --test                   'Test mode for development'
              -v...                        'Sets the level of verbosity'",
             dfl_dir = dfl_args.dir,
@@ -38,6 +53,7 @@ lazy_static::lazy_static! {
             dfl_mem_prof = dfl_args.mem_profile.unwrap(),
             dfl_mem_margin = format_pct(dfl_args.mem_margin),
             dfl_systemd_timeout = format_duration(dfl_args.systemd_timeout),
+            dfl_baseline_thr = format_pct(Args::DFL_BASELINE_THR),
         )
     };
 }
@@ -49,6 +65,22 @@ pub enum Mode {
     Format,
     Summary,
     Pack,
+    Doctor,
+    List,
+    Merge,
+}
+
+/// Ceilings on workload.slice which, if exceeded for longer than `period`
+/// seconds, abort the run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchdogSpec {
+    pub pids_max: Option<u64>,
+    pub mem_max: Option<u64>,
+    pub period: f64,
+}
+
+impl WatchdogSpec {
+    pub const DFL_PERIOD: f64 = 30.0;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -74,17 +106,61 @@ pub struct Args {
     #[serde(skip)]
     pub study_rep_d: String,
     #[serde(skip)]
+    pub restudy: bool,
+    #[serde(skip)]
     pub iocost_from_sys: bool,
     #[serde(skip)]
     pub keep_reports: bool,
     #[serde(skip)]
     pub clear_reports: bool,
     #[serde(skip)]
+    pub annotations: BTreeMap<String, String>,
+    #[serde(skip)]
+    pub profiles: Option<String>,
+    #[serde(skip)]
+    pub baseline: Option<String>,
+    #[serde(skip)]
+    pub baseline_thrs: BTreeMap<String, f64>,
+    #[serde(skip)]
+    pub exit_summary: Option<String>,
+    #[serde(skip)]
+    pub nice: Option<i32>,
+    #[serde(skip)]
+    pub ionice: Option<(u32, u32)>,
+    #[serde(skip)]
+    pub agent_slice: Option<Slice>,
+    #[serde(skip)]
+    pub minder_intv: Option<f64>,
+    #[serde(skip)]
+    pub watchdog: Option<WatchdogSpec>,
+    #[serde(skip)]
+    pub merge_srcs: Vec<String>,
+    #[serde(skip)]
+    pub raw_reports: bool,
+    #[serde(skip)]
+    pub fail_on_warn: bool,
+    #[serde(skip)]
+    pub rapl: bool,
+    #[serde(skip)]
+    pub strict_sysreqs: Option<BTreeSet<SysReq>>,
+    #[serde(skip)]
+    pub validate: bool,
+    #[serde(skip)]
     pub test: bool,
     #[serde(skip)]
     pub verbosity: u32,
     #[serde(skip)]
     pub rstat: u32,
+    #[serde(skip)]
+    pub decimal_size: bool,
+    #[serde(skip)]
+    pub width: Option<usize>,
+    #[serde(skip)]
+    pub format_version: Option<u32>,
+    #[serde(skip)]
+    pub since: Option<u64>,
+    #[serde(skip)]
+    pub until: Option<u64>,
 }
 
 impl Default for Args {
@@ -99,6 +175,7 @@ impl Default for Args {
             swappiness_ovr: None,
             job_specs: Default::default(),
             study_rep_d: "".into(),
+            restudy: false,
             rep_retention: 7 * 24 * 3600,
             systemd_timeout: 120.0,
             hashd_size: None,
@@ -109,9 +186,30 @@ impl Default for Args {
             iocost_from_sys: false,
             keep_reports: false,
             clear_reports: false,
+            annotations: Default::default(),
+            profiles: None,
+            baseline: None,
+            baseline_thrs: Default::default(),
+            exit_summary: None,
+            nice: None,
+            ionice: None,
+            agent_slice: None,
+            minder_intv: None,
+            watchdog: None,
+            merge_srcs: vec![],
+            raw_reports: false,
+            fail_on_warn: false,
+            rapl: false,
+            strict_sysreqs: None,
+            validate: false,
             test: false,
             verbosity: 0,
             rstat: 0,
+            decimal_size: false,
+            width: None,
+            format_version: None,
+            since: None,
+            until: None,
         }
     }
 }
@@ -119,6 +217,7 @@ impl Default for Args {
 impl Args {
     pub const RB_BENCH_FILENAME: &'static str = "rb-bench.json";
     pub const DFL_MEM_PROFILE: u32 = 16;
+    pub const DFL_BASELINE_THR: f64 = 0.05;
 
     pub fn demo_bench_knobs_path(&self) -> String {
         self.dir.clone() + "/" + rd_agent_intf::BENCH_FILENAME
@@ -128,6 +227,73 @@ impl Args {
         self.dir.clone() + "/" + Self::RB_BENCH_FILENAME
     }
 
+    pub const IOPRIO_CLASS_NAMES: [&'static str; 4] = ["none", "rt", "be", "idle"];
+
+    // Returns (ioprio class, level).
+    fn parse_ionice(input: &str) -> Result<(u32, u32)> {
+        let mut toks = input.splitn(2, ':');
+        let class_name = toks.next().unwrap();
+        let class = match Self::IOPRIO_CLASS_NAMES[1..]
+            .iter()
+            .position(|x| *x == class_name)
+        {
+            Some(idx) => idx as u32 + 1,
+            None => bail!("unknown io priority class {:?}", class_name),
+        };
+        let level = match toks.next() {
+            Some(v) => match v.parse::<u32>() {
+                Ok(v) if v <= 7 => v,
+                _ => bail!("invalid io priority level {:?}", v),
+            },
+            None => 4,
+        };
+        Ok((class, level))
+    }
+
+    fn parse_watchdog(input: &str) -> Result<WatchdogSpec> {
+        let mut spec = WatchdogSpec {
+            pids_max: None,
+            mem_max: None,
+            period: WatchdogSpec::DFL_PERIOD,
+        };
+        for (k, v) in Self::parse_propset(input).iter() {
+            match k.as_str() {
+                "pids" => spec.pids_max = Some(v.parse::<u64>()?),
+                "mem" => spec.mem_max = Some(parse_size(v)?),
+                "period" => spec.period = parse_duration(v)?,
+                k => bail!("unknown watchdog ceiling {:?}", k),
+            }
+        }
+        if spec.pids_max.is_none() && spec.mem_max.is_none() {
+            bail!("at least one of \"pids\" and \"mem\" must be specified");
+        }
+        Ok(spec)
+    }
+
+    fn parse_agent_slice(input: &str) -> Result<Slice> {
+        let slice = input.parse::<Slice>()?;
+        if !slice.name().ends_with(".slice") {
+            bail!("{:?} is not a slice and can't host rd-agent", slice.name());
+        }
+        Ok(slice)
+    }
+
+    fn parse_sysreqs(input: &str) -> Result<BTreeSet<SysReq>> {
+        let mut reqs = BTreeSet::new();
+        for tok in input.split(',').map(|x| x.trim()).filter(|x| x.len() > 0) {
+            match ALL_SYSREQS_SET
+                .iter()
+                .find(|req| format!("{:?}", req).eq_ignore_ascii_case(tok))
+            {
+                Some(req) => {
+                    reqs.insert(*req);
+                }
+                None => bail!("unknown sysreq {:?}", tok),
+            }
+        }
+        Ok(reqs)
+    }
+
     pub fn parse_propset(input: &str) -> BTreeMap<String, String> {
         let mut propset = BTreeMap::<String, String>::new();
         for tok in input.split(',') {
@@ -165,6 +331,10 @@ impl Args {
             props.push(propset);
         }
 
+        if let Some(id) = id.as_ref() {
+            JobSpec::validate_id(id)?;
+        }
+
         // Make sure there always is the first group.
         if props.len() == 0 {
             props.push(Default::default());
@@ -173,7 +343,10 @@ impl Args {
         Ok(JobSpec::new(kind, id.as_deref(), props))
     }
 
-    fn parse_job_specs(subm: &clap::ArgMatches) -> Result<Vec<JobSpec>> {
+    fn parse_job_specs(
+        subm: &clap::ArgMatches,
+        profiles: Option<&JobProfiles>,
+    ) -> Result<Vec<JobSpec>> {
         let mut jobsets = BTreeMap::<usize, Vec<JobSpec>>::new();
 
         match (subm.indices_of("spec"), subm.values_of("spec")) {
@@ -195,6 +368,13 @@ impl Args {
                 for (idx, fname) in idxs.zip(fnames) {
                     match Self::load(fname) {
                         Ok(v) => {
+                            for spec in v.job_specs.iter() {
+                                if let Some(id) = spec.id.as_ref() {
+                                    if let Err(e) = JobSpec::validate_id(id) {
+                                        bail!("file {:?}: {}: {}", fname, spec, &e);
+                                    }
+                                }
+                            }
                             jobsets.insert(idx, v.job_specs);
                         }
                         Err(e) => bail!("file {:?}: {}", fname, &e),
@@ -210,9 +390,25 @@ impl Args {
                 job_specs.append(jobset);
             }
         }
+        for spec in job_specs.iter_mut() {
+            if let Err(e) = spec.apply_profile(profiles) {
+                bail!("{}: {}", spec, &e);
+            }
+        }
         Ok(job_specs)
     }
 
+    fn parse_time_arg(subm: &clap::ArgMatches, key: &str) -> Option<u64> {
+        match subm.value_of(key).map(parse_unix_time) {
+            Some(Ok(v)) => Some(v),
+            Some(Err(e)) => {
+                error!("--{}: {}", key, &e);
+                exit(1);
+            }
+            None => None,
+        }
+    }
+
     fn process_subcommand(&mut self, mode: Mode, subm: &clap::ArgMatches) -> bool {
         let mut updated = false;
 
@@ -224,8 +420,11 @@ impl Args {
 
         match mode {
             Mode::Study => {
+                self.restudy = subm.is_present("restudy");
                 self.study_rep_d = match subm.value_of("reports") {
                     Some(v) => v.to_string(),
+                    // Restudying uses the reports left behind by the run.
+                    None if self.restudy => format!("{}/report.d", &self.dir),
                     None => format!(
                         "{}-report.d",
                         Path::new(&self.result)
@@ -239,7 +438,44 @@ impl Args {
             _ => {}
         }
 
-        match Self::parse_job_specs(subm) {
+        if let Mode::Format | Mode::Summary = mode {
+            self.decimal_size = subm.is_present("decimal-size");
+            self.width = match subm.value_of("width") {
+                Some(v) => match v.parse::<usize>() {
+                    Ok(w) if w > 0 => Some(w),
+                    _ => {
+                        error!("Invalid --width {:?}", v);
+                        exit(1);
+                    }
+                },
+                None => None,
+            };
+            self.format_version = match subm.value_of("format-version") {
+                Some(v) => match v.parse::<u32>() {
+                    Ok(ver) if ver > 0 => Some(ver),
+                    _ => {
+                        error!("Invalid --format-version {:?}", v);
+                        exit(1);
+                    }
+                },
+                None => None,
+            };
+            self.since = Self::parse_time_arg(subm, "since");
+            self.until = Self::parse_time_arg(subm, "until");
+        }
+
+        let profiles = match self.profiles.as_ref() {
+            Some(path) => match JobProfiles::load(path) {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    error!("Failed to load profiles {:?} ({:#})", path, &e);
+                    exit(1);
+                }
+            },
+            None => None,
+        };
+
+        match Self::parse_job_specs(subm, profiles.as_ref()) {
             Ok(job_specs) => {
                 if job_specs.len() > 0 {
                     self.job_specs = job_specs;
@@ -268,6 +504,25 @@ impl JsonArgs for Args {
             .takes_value(true)
             .number_of_values(1)
             .help("Benchmark job file");
+        let since_arg = clap::Arg::with_name("since")
+            .long("since")
+            .takes_value(true)
+            .help("Only results which started at or after DATE (e.g. \"2021-03-04 12:00\")");
+        let until_arg = clap::Arg::with_name("until")
+            .long("until")
+            .takes_value(true)
+            .help("Only results which ended at or before DATE");
+        let decimal_size_arg = clap::Arg::with_name("decimal-size")
+            .long("decimal-size")
+            .help("Format sizes in decimal units (kB, MB, GB) instead of binary");
+        let width_arg = clap::Arg::with_name("width")
+            .long("width")
+            .takes_value(true)
+            .help("Width of separators and wrapped lists (default: 90)");
+        let format_version_arg = clap::Arg::with_name("format-version")
+            .long("format-version")
+            .takes_value(true)
+            .help("Emit the text layout of an older version for diffing (default: latest)");
         let job_spec_arg = clap::Arg::with_name("spec")
             .multiple(true)
             .help("Benchmark job spec - \"BENCH_TYPE[:KEY=VAL...]\"");
@@ -295,6 +550,11 @@ impl JsonArgs for Args {
                             .takes_value(true)
                             .help("Study reports in the directory (default: RESULTFILE_BASENAME-report.d/)"),
                     )
+                    .arg(
+                        clap::Arg::with_name("restudy")
+                            .long("restudy")
+                            .help("Only study the recorded jobs whose study phase hasn't completed"),
+                    )
                     .arg(job_file_arg.clone())
                     .arg(job_spec_arg.clone()),
             )
@@ -310,6 +570,11 @@ impl JsonArgs for Args {
                                 "Report extra resource stats if available (repeat for even more)",
                             ),
                     )
+                    .arg(decimal_size_arg.clone())
+                    .arg(width_arg.clone())
+                    .arg(format_version_arg.clone())
+                    .arg(since_arg.clone())
+                    .arg(until_arg.clone())
                     .arg(job_file_arg.clone())
                     .arg(job_spec_arg.clone()),
             )
@@ -325,6 +590,11 @@ impl JsonArgs for Args {
                             .number_of_values(1)
                             .help("Benchmark format file"),
                     )
+                    .arg(decimal_size_arg.clone())
+                    .arg(width_arg.clone())
+                    .arg(format_version_arg.clone())
+                    .arg(since_arg.clone())
+                    .arg(until_arg.clone())
                     .arg(
                         clap::Arg::with_name("spec")
                             .multiple(true)
@@ -334,6 +604,23 @@ impl JsonArgs for Args {
             .subcommand(clap::SubCommand::with_name("pack").about(
                 "Create a tarball containing the result file and the associated report files",
             ))
+            .subcommand(
+                clap::SubCommand::with_name("merge")
+                    .about("Merge the results in the source files into the result file")
+                    .arg(
+                        clap::Arg::with_name("src")
+                            .multiple(true)
+                            .required(true)
+                            .help("Result files to merge"),
+                    ),
+            )
+            .subcommand(clap::SubCommand::with_name("doctor").about(
+                "Verify that cgroup resource control is enforced end to end",
+            ))
+            .subcommand(
+                clap::SubCommand::with_name("list")
+                    .about("List the available benchmarks and the properties they take"),
+            )
             .get_matches()
     }
 
@@ -455,6 +742,78 @@ impl JsonArgs for Args {
         self.iocost_from_sys = matches.is_present("iocost-from-sys");
         self.keep_reports = matches.is_present("keep-reports");
         self.clear_reports = matches.is_present("clear-reports");
+        self.raw_reports = matches.is_present("raw-reports");
+        self.fail_on_warn = matches.is_present("fail-on-warn");
+        self.rapl = matches.is_present("rapl");
+        if let Some(v) = matches.value_of("annotate") {
+            self.annotations = Self::parse_propset(v);
+        }
+        self.profiles = matches.value_of("profiles").map(|x| x.to_string());
+        self.baseline = matches.value_of("baseline").map(|x| x.to_string());
+        if let Some(v) = matches.value_of("baseline-thr") {
+            for (k, v) in Self::parse_propset(v).into_iter() {
+                let thr = parse_frac(&v)
+                    .with_context(|| format!("Parsing baseline threshold \"{}={}\"", &k, &v))
+                    .unwrap();
+                self.baseline_thrs.insert(k, thr);
+            }
+        }
+        self.exit_summary = matches.value_of("exit-summary").map(|x| x.to_string());
+        if let Some(v) = matches.value_of("nice") {
+            self.nice = match v.parse::<i32>() {
+                Ok(v) if v >= -20 && v <= 19 => Some(v),
+                _ => {
+                    error!("Invalid --nice {:?}", v);
+                    exit(1);
+                }
+            };
+        }
+        if let Some(v) = matches.value_of("ionice") {
+            match Self::parse_ionice(v) {
+                Ok(prio) => self.ionice = Some(prio),
+                Err(e) => {
+                    error!("--ionice: {:#}", &e);
+                    exit(1);
+                }
+            }
+        }
+        if let Some(v) = matches.value_of("minder-intv") {
+            self.minder_intv = match parse_duration(v) {
+                Ok(intv) if intv >= 0.05 && intv <= 60.0 => Some(intv),
+                _ => {
+                    error!("Invalid --minder-intv {:?}, must be in [50ms, 60s]", v);
+                    exit(1);
+                }
+            };
+        }
+        if let Some(v) = matches.value_of("watchdog") {
+            match Self::parse_watchdog(v) {
+                Ok(spec) => self.watchdog = Some(spec),
+                Err(e) => {
+                    error!("--watchdog: {:#}", &e);
+                    exit(1);
+                }
+            }
+        }
+        if let Some(v) = matches.value_of("agent-slice") {
+            match Self::parse_agent_slice(v) {
+                Ok(slice) => self.agent_slice = Some(slice),
+                Err(e) => {
+                    error!("--agent-slice: {:#}", &e);
+                    exit(1);
+                }
+            }
+        }
+        if let Some(v) = matches.value_of("strict-sysreqs") {
+            match Self::parse_sysreqs(v) {
+                Ok(allow) => self.strict_sysreqs = Some(allow),
+                Err(e) => {
+                    error!("--strict-sysreqs: {:#}", &e);
+                    exit(1);
+                }
+            }
+        }
+        self.validate = matches.is_present("validate");
         self.test = matches.is_present("test");
         self.verbosity = Self::verbosity(matches);
 
@@ -467,6 +826,23 @@ impl JsonArgs for Args {
                 self.mode = Mode::Pack;
                 false
             }
+            ("merge", Some(subm)) => {
+                self.mode = Mode::Merge;
+                self.merge_srcs = subm
+                    .values_of("src")
+                    .unwrap()
+                    .map(|x| x.to_string())
+                    .collect();
+                false
+            }
+            ("doctor", Some(_)) => {
+                self.mode = Mode::Doctor;
+                false
+            }
+            ("list", Some(_)) => {
+                self.mode = Mode::List;
+                false
+            }
             _ => false,
         };
 
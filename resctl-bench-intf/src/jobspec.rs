@@ -1,9 +1,18 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use util::*;
 
 pub type JobProps = Vec<BTreeMap<String, String>>;
 
+/// Named sets of default props, see `JobSpec::apply_profile()`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct JobProfiles(pub BTreeMap<String, JobProps>);
+
+impl JsonLoad for JobProfiles {}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct JobSpec {
     pub kind: String,
@@ -35,6 +44,65 @@ impl JobSpec {
         }
     }
 
+    fn is_id_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'
+    }
+
+    /// Ids may end up in file names. Only allow alphanumerics, '-', '_'
+    /// and '.', and don't allow ids which consist only of dots.
+    pub fn validate_id(id: &str) -> Result<()> {
+        if id.len() == 0 {
+            bail!("empty id");
+        }
+        if let Some(c) = id.chars().find(|c| !Self::is_id_char(*c)) {
+            bail!(
+                "id {:?} contains {:?}, only alphanumerics, '-', '_' and '.' are allowed",
+                id,
+                c
+            );
+        }
+        if id.chars().all(|c| c == '.') {
+            bail!("id {:?} isn't a valid name", id);
+        }
+        Ok(())
+    }
+
+    /// The id with characters unsafe for file names replaced with '_'.
+    pub fn sanitized_id(&self) -> Option<String> {
+        self.id.as_ref().map(|id| {
+            id.chars()
+                .map(|c| if Self::is_id_char(c) { c } else { '_' })
+                .collect()
+        })
+    }
+
+    /// If the first property group has "profile=NAME", remove it and fill
+    /// in the props of the named profile. Explicitly specified props take
+    /// precedence over the profile's.
+    pub fn apply_profile(&mut self, profiles: Option<&JobProfiles>) -> Result<()> {
+        let name = match self.props[0].remove("profile") {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        let profile = match profiles.and_then(|p| p.0.get(&name)) {
+            Some(v) => v,
+            None if profiles.is_none() => bail!("profile {:?} used without --profiles", &name),
+            None => bail!("unknown profile {:?}", &name),
+        };
+
+        for (idx, pprops) in profile.iter().enumerate() {
+            if idx >= self.props.len() {
+                self.props.push(Default::default());
+            }
+            for (k, v) in pprops.iter() {
+                if !self.props[idx].contains_key(k) {
+                    self.props[idx].insert(k.clone(), v.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn new(kind: &str, id: Option<&str>, props: JobProps) -> Self {
         assert!(props.len() > 0);
         Self {
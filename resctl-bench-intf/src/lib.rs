@@ -3,6 +3,6 @@ pub mod args;
 pub mod iocost;
 pub mod jobspec;
 
-pub use args::{Args, Mode};
+pub use args::{Args, Mode, WatchdogSpec};
 pub use iocost::IoCostQoSOvr;
-pub use jobspec::{JobProps, JobSpec};
+pub use jobspec::{JobProfiles, JobProps, JobSpec};
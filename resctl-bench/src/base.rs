@@ -18,6 +18,18 @@ pub struct MemInfo {
     pub target: usize,
 }
 
+impl MemInfo {
+    /// Memory offload factors are relative to the memory share but only
+    /// the target portion is usable. Scaling by target/share makes them
+    /// comparable across memory profiles.
+    pub fn mof_norm_factor(&self) -> f64 {
+        match self.share {
+            0 => 1.0,
+            share => self.target as f64 / share as f64,
+        }
+    }
+}
+
 pub struct Base<'a> {
     pub scr_devname: String,
     pub bench_knobs_path: String,
@@ -12,7 +12,7 @@ use std::time::Duration;
 
 use super::base::MemInfo;
 use super::iocost::{iocost_min_vrate, IoCostQoSCfg, IoCostQoSOvr};
-use super::job::{FormatOpts, Job, JobData};
+use super::job::{FormatOpts, Job, JobData, Metric};
 use super::parse_json_value_or_dump;
 use super::progress::BenchProgress;
 use super::run::{RunCtx, WorkloadMon};
@@ -49,6 +49,48 @@ pub struct HashdFakeCpuBench {
 }
 
 impl HashdFakeCpuBench {
+    // Sane bounds for the overridable parameters. log-bps is spread across
+    // requests as padding, keep it high enough to be visible at the
+    // default rps-max.
+    pub const HASH_SIZE_RANGE: (usize, usize) = (4 << 10, 1 << 30);
+    pub const RPS_MAX_RANGE: (u32, u32) = (1, 1 << 20);
+    pub const LOG_BPS_RANGE: (u64, u64) = (64 << 10, 4 << 30);
+
+    fn check_range<T: PartialOrd + std::fmt::Display>(
+        name: &str,
+        v: T,
+        range: (T, T),
+    ) -> Result<T> {
+        if v < range.0 || v > range.1 {
+            bail!("{} {} out of range [{}, {}]", name, v, range.0, range.1);
+        }
+        Ok(v)
+    }
+
+    /// Parse and validate a "hash-size" property override.
+    pub fn parse_hash_size(v: &str) -> Result<usize> {
+        Self::check_range("hash-size", v.parse::<usize>()?, Self::HASH_SIZE_RANGE)
+    }
+
+    /// Parse and validate a "rps-max" property override.
+    pub fn parse_rps_max(v: &str) -> Result<u32> {
+        Self::check_range("rps-max", v.parse::<u32>()?, Self::RPS_MAX_RANGE)
+    }
+
+    /// Parse and validate a "log-bps" property override.
+    pub fn parse_log_bps(v: &str) -> Result<u64> {
+        Self::check_range("log-bps", v.parse::<u64>()?, Self::LOG_BPS_RANGE)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        Self::check_range("hash-size", self.hash_size, Self::HASH_SIZE_RANGE)?;
+        Self::check_range("rps-max", self.rps_max, Self::RPS_MAX_RANGE)?;
+        if let Some(log_bps) = self.log_bps {
+            Self::check_range("log-bps", log_bps, Self::LOG_BPS_RANGE)?;
+        }
+        Ok(())
+    }
+
     pub fn base(rctx: &RunCtx) -> Self {
         let dfl_args = rd_hashd_intf::Args::with_mem_size(rctx.mem_info().share);
         let dfl_params = rd_hashd_intf::Params::default();
@@ -64,6 +106,7 @@ impl HashdFakeCpuBench {
     }
 
     pub fn start(&self, rctx: &mut RunCtx) -> Result<()> {
+        self.validate()?;
         rctx.start_hashd_bench(
             self.log_bps,
             // We should specify all the total_memory() dependent values in
@@ -98,6 +141,7 @@ pub fn find_bench(kind: &str) -> Result<Arc<Box<dyn Bench>>> {
 
 pub struct BenchDesc {
     pub kind: String,
+    pub about: String,
     pub takes_run_props: bool,
     pub takes_run_propsets: bool,
     pub takes_format_props: bool,
@@ -110,6 +154,7 @@ impl BenchDesc {
     pub fn new(kind: &str) -> Self {
         Self {
             kind: kind.into(),
+            about: "".into(),
             takes_run_props: false,
             takes_run_propsets: false,
             takes_format_props: false,
@@ -118,6 +163,11 @@ impl BenchDesc {
         }
     }
 
+    pub fn about(mut self, about: &str) -> Self {
+        self.about = about.into();
+        self
+    }
+
     pub fn takes_run_props(mut self) -> Self {
         self.takes_run_props = true;
         self
@@ -151,6 +201,11 @@ pub trait Bench: Send + Sync {
     fn parse(&self, spec: &JobSpec, prev_data: Option<&JobData>) -> Result<Box<dyn Job>>;
 }
 
+/// Descriptions of all registered benches in registration order.
+pub fn bench_descs() -> Vec<BenchDesc> {
+    BENCHS.lock().unwrap().iter().map(|b| b.desc()).collect()
+}
+
 fn register_bench(bench: Box<dyn Bench>) -> () {
     BENCHS.lock().unwrap().push(Arc::new(bench));
 }
@@ -159,6 +214,7 @@ mod hashd_params;
 mod iocost_params;
 mod iocost_qos;
 mod iocost_tune;
+mod mem_bw;
 mod protection;
 mod storage;
 
@@ -169,4 +225,5 @@ pub fn init_benchs() -> () {
     register_bench(Box::new(iocost_qos::IoCostQoSBench {}));
     register_bench(Box::new(iocost_tune::IoCostTuneBench {}));
     register_bench(Box::new(protection::ProtectionBench {}));
+    register_bench(Box::new(mem_bw::MemBwBench {}));
 }
@@ -2,6 +2,7 @@
 use super::*;
 use rd_agent_intf::HashdKnobs;
 use rd_agent_intf::{HASHD_BENCH_SVC_NAME, ROOT_SLICE};
+use std::collections::BTreeMap;
 
 struct HashdParamsJob {
     passive: bool,
@@ -10,6 +11,7 @@ struct HashdParamsJob {
     hash_size: Option<usize>,
     chunk_pages: Option<usize>,
     rps_max: Option<u32>,
+    warmup: Option<Duration>,
 }
 
 impl Default for HashdParamsJob {
@@ -22,6 +24,7 @@ impl Default for HashdParamsJob {
             hash_size: None,
             chunk_pages: None,
             rps_max: None,
+            warmup: None,
         }
     }
 }
@@ -30,7 +33,9 @@ pub struct HashdParamsBench {}
 
 impl Bench for HashdParamsBench {
     fn desc(&self) -> BenchDesc {
-        BenchDesc::new("hashd-params").takes_run_props()
+        BenchDesc::new("hashd-params")
+            .about("Determine rd-hashd parameters for the CPU and memory of the machine")
+            .takes_run_props()
     }
 
     fn parse(&self, spec: &JobSpec, _prev_data: Option<&JobData>) -> Result<Box<dyn Job>> {
@@ -39,11 +44,12 @@ impl Bench for HashdParamsBench {
         for (k, v) in spec.props[0].iter() {
             match k.as_str() {
                 "passive" => job.passive = v.len() == 0 || v.parse::<bool>()?,
-                "log-bps" => job.log_bps = v.parse::<u64>()?,
+                "log-bps" => job.log_bps = HashdFakeCpuBench::parse_log_bps(v)?,
                 "fake-cpu-load" => job.fake_cpu_load = v.len() == 0 || v.parse::<bool>()?,
-                "hash-size" => job.hash_size = Some(v.parse::<usize>()?),
+                "hash-size" => job.hash_size = Some(HashdFakeCpuBench::parse_hash_size(v)?),
                 "chunk-pages" => job.chunk_pages = Some(v.parse::<usize>()?),
-                "rps-max" => job.rps_max = Some(v.parse::<u32>()?),
+                "rps-max" => job.rps_max = Some(HashdFakeCpuBench::parse_rps_max(v)?),
+                "warmup" => job.warmup = Some(Duration::from_secs_f64(parse_duration(v)?)),
                 k => bail!("unknown property key {:?}", k),
             }
         }
@@ -57,10 +63,25 @@ impl Job for HashdParamsJob {
         HASHD_SYSREQS.clone()
     }
 
+    fn describe(&self) -> String {
+        format!(
+            "rd-hashd parameter estimation{}{}",
+            if self.fake_cpu_load {
+                ", fake CPU load"
+            } else {
+                ""
+            },
+            if self.passive { ", passive" } else { "" }
+        )
+    }
+
     fn run(&mut self, rctx: &mut RunCtx) -> Result<serde_json::Value> {
         if self.passive {
             rctx.set_passive_keep_crit_mem_prot();
         }
+        if let Some(warmup) = self.warmup {
+            rctx.set_hashd_bench_warmup(warmup);
+        }
         rctx.set_commit_bench().start_agent(vec![])?;
 
         info!("hashd-params: Estimating rd-hashd parameters");
@@ -120,19 +141,19 @@ impl Job for HashdParamsJob {
         &self,
         mut out: Box<dyn Write + 'a>,
         data: &JobData,
-        _opts: &FormatOpts,
+        opts: &FormatOpts,
         _props: &JobProps,
     ) -> Result<()> {
         let result: HashdKnobs = data.parse_record()?;
 
-        writeln!(out, "Params: log_bps={}", format_size(self.log_bps)).unwrap();
+        writeln!(out, "Params: log_bps={}", opts.format_size(self.log_bps)).unwrap();
 
         writeln!(
             out,
             "\nResult: hash_size={} rps_max={} mem_size={} mem_frac={:.3} chunk_pages={}",
-            format_size(result.hash_size),
+            opts.format_size(result.hash_size),
             result.rps_max,
-            format_size(result.mem_size),
+            opts.format_size(result.mem_size),
             result.mem_frac,
             result.chunk_pages
         )
@@ -140,4 +161,14 @@ impl Job for HashdParamsJob {
 
         Ok(())
     }
+
+    fn metrics(&self, data: &JobData) -> Result<BTreeMap<String, Metric>> {
+        let result: HashdKnobs = data.parse_record()?;
+        Ok(
+            [("rps_max".to_owned(), Metric::higher(result.rps_max as f64))]
+                .iter()
+                .cloned()
+                .collect(),
+        )
+    }
 }
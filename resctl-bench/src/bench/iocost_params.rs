@@ -2,6 +2,7 @@
 use super::*;
 use rd_agent_intf::IoCostKnobs;
 use rd_agent_intf::{IOCOST_BENCH_SVC_NAME, ROOT_SLICE};
+use std::collections::BTreeMap;
 
 struct IoCostParamsJob {}
 
@@ -10,6 +11,7 @@ pub struct IoCostParamsBench {}
 impl Bench for IoCostParamsBench {
     fn desc(&self) -> BenchDesc {
         BenchDesc::new("iocost-params")
+            .about("Determine the iocost model parameters of the scratch device")
     }
 
     fn parse(&self, _spec: &JobSpec, _prev_data: Option<&JobData>) -> Result<Box<dyn Job>> {
@@ -83,4 +85,19 @@ impl Job for IoCostParamsJob {
 
         Ok(())
     }
+
+    fn metrics(&self, data: &JobData) -> Result<BTreeMap<String, Metric>> {
+        let model = data.parse_record::<IoCostKnobs>()?.model;
+        Ok([
+            ("rbps", model.rbps),
+            ("rseqiops", model.rseqiops),
+            ("rrandiops", model.rrandiops),
+            ("wbps", model.wbps),
+            ("wseqiops", model.wseqiops),
+            ("wrandiops", model.wrandiops),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), Metric::higher(*v as f64)))
+        .collect())
+    }
 }
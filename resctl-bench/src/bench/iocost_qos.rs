@@ -39,6 +39,7 @@ pub struct IoCostQoSBench {}
 impl Bench for IoCostQoSBench {
     fn desc(&self) -> BenchDesc {
         BenchDesc::new("iocost-qos")
+            .about("Measure storage and protection behaviors across iocost QoS configurations")
             .takes_run_propsets()
             .takes_format_props()
             .incremental()
@@ -419,7 +420,10 @@ impl IoCostQoSJob {
             };
 
         // Study the vrate and IO latency distributions across all the runs.
-        let mut study_vrate = StudyMeanPcts::new(|arg| vec![arg.rep.iocost.vrate], None);
+        let mut study_vrate = StudyMeanPcts::new(
+            |arg| arg.rep.iocost.current_vrate().into_iter().collect(),
+            None,
+        );
         let mut study_read_lat_pcts = StudyIoLatPcts::new("read", None);
         let mut study_write_lat_pcts = StudyIoLatPcts::new("write", None);
         let nr_reports = Studies::new()
@@ -452,6 +456,14 @@ impl Job for IoCostQoSJob {
         StorageJob::default().sysreqs()
     }
 
+    fn describe(&self) -> String {
+        format!(
+            "iocost QoS sweep, {} runs, {} storage loops each",
+            self.runs.len(),
+            self.stor_loops
+        )
+    }
+
     fn run(&mut self, rctx: &mut RunCtx) -> Result<serde_json::Value> {
         // Make sure we have iocost parameters available.
         let mut bench_knobs = rctx.bench_knobs().clone();
@@ -575,7 +587,7 @@ impl Job for IoCostQoSJob {
                             }
                         }
                         prev_rec.inc_runs.push(recr.clone());
-                        rctx.update_incremental_record(serde_json::to_value(&prev_rec).unwrap());
+                        rctx.update_incremental_record(serde_json::to_value(&prev_rec).unwrap())?;
                         runs.push(Some(recr));
                         break;
                     }
@@ -650,7 +662,7 @@ impl Job for IoCostQoSJob {
         let base_stor_res = &res.runs[0].as_ref().unwrap().stor;
 
         self.stor_job
-            .format_header(&mut out, base_stor_rec, base_stor_res, false);
+            .format_header(&mut out, base_stor_rec, base_stor_res, false, opts);
 
         if opts.full {
             for (i, (recr, resr)) in rec.runs.iter().zip(res.runs.iter()).enumerate() {
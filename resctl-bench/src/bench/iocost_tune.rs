@@ -14,6 +14,9 @@ const DFL_IOCOST_QOS_VRATE_INTVS: u32 = 25;
 const DFL_GRAN: f64 = 0.1;
 const DFL_VRATE_MIN: f64 = 1.0;
 const DFL_VRATE_MAX: f64 = 100.0;
+const DFL_FIT_ERR_MAX: f64 = 0.1;
+const STUDY_MIN_POINTS: usize = 3;
+const DFL_SHOW_RESIDUALS: usize = 5;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum DataSel {
@@ -737,6 +740,7 @@ pub struct IoCostTuneBench {}
 impl Bench for IoCostTuneBench {
     fn desc(&self) -> BenchDesc {
         BenchDesc::new("iocost-tune")
+            .about("Study iocost-qos results and recommend QoS parameters")
             .takes_run_propsets()
             .takes_format_props()
             .incremental()
@@ -835,16 +839,33 @@ impl Bench for IoCostTuneBench {
     }
 }
 
-// (vrate, val)
+// (vrate, val) along with how long the point was measured for and the
+// number of reports backing it. The latter two are zero for fitted lines
+// and results from before they were recorded.
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
 struct DataPoint {
     x: f64,
     y: f64,
+    #[serde(default)]
+    dur: f64,
+    #[serde(default)]
+    nr_samples: u64,
 }
 
 impl DataPoint {
     fn new(x: f64, y: f64) -> Self {
-        Self { x, y }
+        Self {
+            x,
+            y,
+            dur: 0.0,
+            nr_samples: 0,
+        }
+    }
+
+    fn with_samples(mut self, dur: f64, nr_samples: u64) -> Self {
+        self.dur = dur;
+        self.nr_samples = nr_samples;
+        self
     }
 }
 
@@ -925,6 +946,9 @@ struct DataSeries {
     outliers: Vec<DataPoint>,
     lines: DataLines,
     error: f64,
+    // Observed minus fitted value for each of @points.
+    #[serde(default)]
+    residuals: Vec<f64>,
 }
 
 impl DataSeries {
@@ -1045,6 +1069,36 @@ impl DataSeries {
         }
     }
 
+    fn calc_residuals(&mut self) {
+        let lines = &self.lines;
+        self.residuals = self
+            .points
+            .iter()
+            .map(|point| point.y - lines.eval(point.x))
+            .collect();
+    }
+
+    // Up to @nr points with the largest absolute residuals, worst first.
+    // Results from before residuals were recorded are evaluated on the fly.
+    fn worst_residuals(&self, nr: usize) -> Vec<(DataPoint, f64)> {
+        let mut worst: Vec<(DataPoint, f64)> = match self.residuals.len() == self.points.len() {
+            true => self
+                .points
+                .iter()
+                .cloned()
+                .zip(self.residuals.iter().cloned())
+                .collect(),
+            false => self
+                .points
+                .iter()
+                .map(|point| (*point, point.y - self.lines.eval(point.x)))
+                .collect(),
+        };
+        worst.sort_by(|a, b| b.1.abs().partial_cmp(&a.1.abs()).unwrap());
+        worst.truncate(nr);
+        worst
+    }
+
     fn fit_lines(&mut self, gran: f64, dir: DataShape) -> Result<()> {
         if self.points.len() == 0 {
             return Ok(());
@@ -1230,6 +1284,92 @@ impl DataSeries {
     }
 }
 
+// Fitted line parameters in a stable form for downstream tools.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+struct DataSeriesSummary {
+    vrate_min: f64,
+    vrate_max: f64,
+    left_infl: Option<f64>,
+    right_infl: Option<f64>,
+    left_val: f64,
+    right_val: f64,
+    slope: f64,
+    error: f64,
+}
+
+// Reasons to distrust a fitted curve, collected during study. Fit errors
+// aren't included as they're checked against the fit-err-max format
+// property when formatting.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+enum StudyWarning {
+    TooFewPoints { nr: usize, min: usize },
+    DegenerateRange { vrate: f64 },
+}
+
+impl std::fmt::Display for StudyWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooFewPoints { nr, min } => {
+                write!(f, "only {} data points, at least {} needed", nr, min)
+            }
+            Self::DegenerateRange { vrate } => {
+                write!(f, "all data points at vrate {:.2}", vrate)
+            }
+        }
+    }
+}
+
+impl DataSeries {
+    fn study_warnings(&self) -> Vec<StudyWarning> {
+        let mut warns = vec![];
+        if self.points.len() < STUDY_MIN_POINTS {
+            warns.push(StudyWarning::TooFewPoints {
+                nr: self.points.len(),
+                min: STUDY_MIN_POINTS,
+            });
+        }
+        if let Some(first) = self.points.first() {
+            if self.points.len() > 1 && self.points.iter().all(|p| p.x == first.x) {
+                warns.push(StudyWarning::DegenerateRange { vrate: first.x });
+            }
+        }
+        warns
+    }
+
+    // Fit error relative to the magnitude of the fitted line so that
+    // selectors with different units can share a threshold.
+    fn rel_error(&self) -> f64 {
+        let mag = self.lines.left.y.abs().max(self.lines.right.y.abs());
+        if mag > 0.0 {
+            self.error / mag
+        } else {
+            0.0
+        }
+    }
+
+    fn summary(&self) -> DataSeriesSummary {
+        let lines = &self.lines;
+        DataSeriesSummary {
+            vrate_min: lines.range.0,
+            vrate_max: lines.range.1,
+            left_infl: if lines.left.x > lines.range.0 {
+                Some(lines.left.x)
+            } else {
+                None
+            },
+            right_infl: if lines.right.x < lines.range.1 {
+                Some(lines.right.x)
+            } else {
+                None
+            },
+            left_val: lines.left.y,
+            right_val: lines.right.y,
+            slope: lines.slope(),
+            error: self.error,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Default, Debug)]
 struct QoSSolution {
     target: QoSTarget,
@@ -1316,6 +1456,74 @@ pub struct IoCostTuneResult {
     isol_pct: String,
     data: BTreeMap<DataSel, DataSeries>,
     solutions: BTreeMap<String, QoSSolution>,
+    #[serde(default)]
+    summary: BTreeMap<DataSel, DataSeriesSummary>,
+    #[serde(default)]
+    warnings: BTreeMap<DataSel, Vec<StudyWarning>>,
+}
+
+impl IoCostTuneResult {
+    // Solutions to pick the operating point from, in order of preference.
+    const RECOMMEND_PREFS: &'static [&'static str] = &["protect", "bandwidth", "naive"];
+
+    /// Recommended io.cost.qos config along with the name of the solution
+    /// it's derived from. The operating point is the vrate chosen by the
+    /// first solved rule among "protect", "bandwidth" and "naive", falling
+    /// back to the first solved rule by name. "protect" picks the highest
+    /// vrate which doesn't hurt isolation, "bandwidth" the lowest vrate at
+    /// the maximum memory offloading factor and "naive" the default 75-100%
+    /// vrate range. If the solution doesn't specify read or
+    /// write latency targets, they're filled in with the fitted p99 mean
+    /// latencies at the operating point so that the config stays latency
+    /// sensitive. The min and max vrates are relative to the solution's
+    /// scaled model which should be applied together.
+    pub fn recommended_qos(&self) -> Option<(String, IoCostQoSParams)> {
+        let (name, sol) = Self::RECOMMEND_PREFS
+            .iter()
+            .filter_map(|name| self.solutions.get_key_value(*name))
+            .chain(self.solutions.iter())
+            .next()?;
+
+        let p99_mean = |lat: &TimePctsMap| {
+            lat.get("99")
+                .and_then(|x| x.get("mean"))
+                .map(|x| (x * 1_000_000.0).round() as u64)
+                .unwrap_or(0)
+        };
+
+        let mut qos = sol.qos.clone();
+        if qos.rpct == 0.0 && qos.rlat == 0 {
+            let rlat = p99_mean(&sol.rlat);
+            if rlat > 0 {
+                qos.rpct = 99.0;
+                qos.rlat = rlat;
+            }
+        }
+        if qos.wpct == 0.0 && qos.wlat == 0 {
+            let wlat = p99_mean(&sol.wlat);
+            if wlat > 0 {
+                qos.wpct = 99.0;
+                qos.wlat = wlat;
+            }
+        }
+        qos.sanitize();
+        Some((name.clone(), qos))
+    }
+
+    fn summary(&self) -> BTreeMap<DataSel, DataSeriesSummary> {
+        self.data
+            .iter()
+            .map(|(sel, series)| (sel.clone(), series.summary()))
+            .collect()
+    }
+
+    fn study_warnings(&self) -> BTreeMap<DataSel, Vec<StudyWarning>> {
+        self.data
+            .iter()
+            .map(|(sel, series)| (sel.clone(), series.study_warnings()))
+            .filter(|(_, warns)| warns.len() > 0)
+            .collect()
+    }
 }
 
 impl IoCostTuneJob {
@@ -1350,7 +1558,10 @@ impl IoCostTuneJob {
                 _ => continue,
             };
             if let Some(val) = sel.select(qrecr, qresr, &isol_pct) {
-                series.points.push(DataPoint::new(vrate, val));
+                series.points.push(DataPoint::new(vrate, val).with_samples(
+                    qrecr.period.1.saturating_sub(qrecr.period.0) as f64,
+                    qresr.nr_reports.0,
+                ));
             }
         }
         series.points.sort_by(|a, b| a.partial_cmp(b).unwrap());
@@ -1406,6 +1617,7 @@ impl IoCostTuneJob {
             series.points.iter().chain(series.outliers.iter()),
             &series.lines,
         );
+        series.calc_residuals();
 
         data.insert(sel.clone(), series);
 
@@ -1574,14 +1786,20 @@ impl Job for IoCostTuneJob {
             }
         }
 
-        Ok(serde_json::to_value(IoCostTuneResult {
+        let mut result = IoCostTuneResult {
             base_model,
             base_qos,
             mem_profile: qrec.mem_profile,
             isol_pct,
             data,
             solutions,
-        })?)
+            summary: Default::default(),
+            warnings: Default::default(),
+        };
+        result.summary = result.summary();
+        result.warnings = result.study_warnings();
+
+        Ok(serde_json::to_value(result)?)
     }
 
     fn format<'a>(
@@ -1592,6 +1810,18 @@ impl Job for IoCostTuneJob {
         props: &JobProps,
     ) -> Result<()> {
         let mut graph_prefix = None;
+        let mut clean_graphs = false;
+        let mut provenance = false;
+        let mut confidence_markers = false;
+        let mut graph_font = None;
+        let mut graph_only = false;
+        let mut graph_json = false;
+        let mut graph_overlay_lat = false;
+        let mut graph_max_points = None;
+        let mut fit_err_max = DFL_FIT_ERR_MAX;
+        let mut show_residuals = None;
+        let mut graph_size = graph::DFL_SVG_SIZE;
+        let mut graph_dpi = graph::DFL_DPI;
         for (k, v) in props[0].iter() {
             match k.as_ref() {
                 "graph" => {
@@ -1599,13 +1829,50 @@ impl Job for IoCostTuneJob {
                         graph_prefix = Some(v.to_owned());
                     }
                 }
+                "clean-graphs" => clean_graphs = v.len() == 0 || v.parse::<bool>()?,
+                "keep-graphs" => clean_graphs = !(v.len() == 0 || v.parse::<bool>()?),
+                "fit-err-max" => fit_err_max = parse_frac(v)?,
+                "provenance" => provenance = v.len() == 0 || v.parse::<bool>()?,
+                "graph-font" => {
+                    graph_font = match v.len() {
+                        0 => Some("none".to_owned()),
+                        _ => Some(v.to_owned()),
+                    }
+                }
+                "confidence-markers" => confidence_markers = v.len() == 0 || v.parse::<bool>()?,
+                "graph-only" => graph_only = v.len() == 0 || v.parse::<bool>()?,
+                "graph-json" => graph_json = v.len() == 0 || v.parse::<bool>()?,
+                "graph-overlay-lat" => graph_overlay_lat = v.len() == 0 || v.parse::<bool>()?,
+                "graph-max-points" => graph_max_points = Some(v.parse::<usize>()?),
+                "graph-size" => {
+                    let dims: Vec<Option<u32>> =
+                        v.splitn(2, 'x').map(|x| x.parse::<u32>().ok()).collect();
+                    graph_size = match dims.as_slice() {
+                        [Some(w), Some(h)] if *w > 0 && *h > 0 => (*w, *h),
+                        _ => bail!("invalid graph-size {:?}, should be WIDTHxHEIGHT", v),
+                    }
+                }
+                "graph-dpi" => {
+                    graph_dpi = v.parse::<u32>()?;
+                    if graph_dpi == 0 {
+                        bail!("graph-dpi must be positive");
+                    }
+                }
+                "show-residuals" => {
+                    show_residuals = match v.len() {
+                        0 => Some(DFL_SHOW_RESIDUALS),
+                        _ => Some(v.parse::<usize>()?),
+                    }
+                }
                 k => bail!("unknown format parameter {:?}", k),
             }
         }
 
         let res: IoCostTuneResult = data.parse_result()?;
 
-        if opts.full {
+        // Graphs are plotted purely from the stored result, so "graph-only"
+        // can be used to iterate on them without re-running or re-studying.
+        if opts.full || graph_only {
             write!(
                 out,
                 "{}\n",
@@ -1622,7 +1889,73 @@ impl Job for IoCostTuneJob {
                     (ds.lines.range.0.min(acc.0), ds.lines.range.1.max(acc.1))
                 });
             let mut grapher = graph::Grapher::new(&mut out, graph_prefix.as_deref(), vrate_range);
-            grapher.plot(data, &res)?;
+            grapher
+                .set_clean_svgs(clean_graphs)
+                .set_provenance(provenance, opts.result_hash)
+                .set_confidence_markers(confidence_markers)
+                .set_json_views(graph_json)
+                .set_overlay_lat(graph_overlay_lat)
+                .set_max_points(graph_max_points)
+                .set_svg_size(graph_size)
+                .set_dpi(graph_dpi)
+                .set_font(graph_font.as_deref())
+                .plot(data, &res)?;
+        }
+
+        if graph_only {
+            return Ok(());
+        }
+
+        write!(out, "{}\n", &double_underline("Fit errors")).unwrap();
+        let mut nr_poor = 0;
+        for (sel, series) in res.data.iter() {
+            let rel_err = series.rel_error();
+            let mark = if rel_err > fit_err_max {
+                nr_poor += 1;
+                "*"
+            } else {
+                " "
+            };
+            writeln!(
+                out,
+                "{}{:<16} err={:.3} ({:>5}%)",
+                mark,
+                format!("{}", sel),
+                series.error,
+                format_pct(rel_err)
+            )
+            .unwrap();
+            for warn in res.warnings.get(sel).into_iter().flatten() {
+                writeln!(out, "{:18}{}", "", warn).unwrap();
+            }
+        }
+        if nr_poor > 0 {
+            writeln!(
+                out,
+                "\n* Fit error exceeds {}% of the fitted value, the curve may be unreliable",
+                format_pct(fit_err_max)
+            )
+            .unwrap();
+        }
+        writeln!(out, "").unwrap();
+
+        if let Some(nr) = show_residuals {
+            write!(out, "{}\n", &double_underline("Worst-fitting points")).unwrap();
+            for (sel, series) in res.data.iter() {
+                for (point, resid) in series.worst_residuals(nr).iter() {
+                    writeln!(
+                        out,
+                        "{:<16} vrate={:>6.2} observed={:.3} fitted={:.3} residual={:+.3}",
+                        format!("{}", sel),
+                        point.x,
+                        point.y,
+                        point.y - resid,
+                        resid
+                    )
+                    .unwrap();
+                }
+            }
+            writeln!(out, "").unwrap();
         }
 
         if self.rules.len() > 0 {
@@ -1637,6 +1970,26 @@ impl Job for IoCostTuneJob {
             }
         }
 
+        if let Some((name, qos)) = res.recommended_qos() {
+            write!(out, "{}\n", &double_underline("Recommendation")).unwrap();
+            writeln!(
+                out,
+                "io.cost.qos derived from {:?}, use together with its model:\n",
+                &name
+            )
+            .unwrap();
+            let devnr = match data.sysinfo.sysreqs_report.as_ref() {
+                Some(rep) => format!("{}:{}", rep.scr_devnr.0, rep.scr_devnr.1),
+                None => "MAJ:MIN".to_owned(),
+            };
+            writeln!(
+                out,
+                "  {} enable=1 ctrl=user rpct={:.2} rlat={} wpct={:.2} wlat={} min={:.2} max={:.2}\n",
+                devnr, qos.rpct, qos.rlat, qos.wpct, qos.wlat, qos.min, qos.max
+            )
+            .unwrap();
+        }
+
         Ok(())
     }
 }
@@ -9,6 +9,112 @@ pub struct Grapher<'a, 'b> {
     out: &'a mut Box<dyn Write + 'b>,
     file_prefix: Option<String>,
     vrate_range: (f64, f64),
+    clean_svgs: bool,
+    provenance: bool,
+    result_hash: Option<u64>,
+    confidence_markers: bool,
+    json_views: bool,
+    overlay_lat: bool,
+    max_points: Option<usize>,
+    font: Option<String>,
+    svg_size: (u32, u32),
+    dpi: u32,
+}
+
+pub const DFL_SVG_SIZE: (u32, u32) = (576, 468);
+pub const DFL_DPI: u32 = 150;
+// Axis ticks at DFL_SVG_SIZE, scaled with the SVG dimensions.
+const DFL_MAX_TICKS: usize = 10;
+
+// Latency percentiles overlaid on the combined latency views and their colours.
+const OVERLAY_LAT_PCTS: &[(&str, &str)] =
+    &[("50", "#37c0e6"), ("90", "#e6a237"), ("99", "#e63737")];
+
+#[derive(Serialize)]
+struct GraphLabels {
+    x: String,
+    y: String,
+}
+
+// What plot_one_svg() draws for a sel, in plotted (scaled) coordinates.
+#[derive(Serialize)]
+struct GraphView {
+    x_range: (f64, f64),
+    y_range: (f64, f64),
+    labels: GraphLabels,
+    points: Vec<(f64, f64)>,
+    outliers: Vec<(f64, f64)>,
+    line_segments: Vec<(f64, f64)>,
+}
+
+// Tried in order when the font isn't specified.
+const FONT_CANDIDATES: &[&str] = &["cantarell", "DejaVu Sans", "Liberation Sans", "sans"];
+
+// Find the first of FONT_CANDIDATES known to fontconfig.
+fn probe_font() -> Option<String> {
+    for font in FONT_CANDIDATES.iter() {
+        match Command::new("fc-list").args(&["-q", font]).status() {
+            Ok(rc) if rc.success() => return Some(font.to_string()),
+            Ok(_) => {}
+            // fontconfig isn't available, let montage pick.
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+fn xml_escape(input: &str) -> String {
+    let mut buf = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => buf.push_str("&amp;"),
+            '<' => buf.push_str("&lt;"),
+            '>' => buf.push_str("&gt;"),
+            '"' => buf.push_str("&quot;"),
+            c => buf.push(c),
+        }
+    }
+    buf
+}
+
+/// Insert `meta` as the `<metadata>` element right after the opening
+/// `<svg>` tag of the SVG file at `path`.
+pub fn inject_svg_metadata(path: &str, meta: &str) -> Result<()> {
+    let svg = std::fs::read_to_string(path)?;
+    let pos = match svg.find("<svg") {
+        Some(start) => svg[start..].find('>').map(|end| start + end + 1),
+        None => None,
+    }
+    .ok_or(anyhow!("<svg> element not found"))?;
+
+    let mut buf = String::with_capacity(svg.len() + meta.len() + 32);
+    buf.push_str(&svg[..pos]);
+    write!(buf, "\n<metadata>{}</metadata>", xml_escape(meta)).unwrap();
+    buf.push_str(&svg[pos..]);
+    std::fs::write(path, buf)?;
+    Ok(())
+}
+
+/// Multiply all `font-size` attributes of the SVG file at `path` by `scale`.
+/// plotlib hard-codes the font sizes regardless of the page dimensions.
+pub fn scale_svg_fonts(path: &str, scale: f64) -> Result<()> {
+    const KEY: &str = "font-size=\"";
+    let svg = std::fs::read_to_string(path)?;
+    let mut buf = String::with_capacity(svg.len());
+    let mut rest = svg.as_str();
+    while let Some(pos) = rest.find(KEY) {
+        let start = pos + KEY.len();
+        let len = rest[start..]
+            .find('"')
+            .ok_or(anyhow!("unterminated font-size attribute"))?;
+        let size = rest[start..start + len].parse::<f64>()?;
+        buf.push_str(&rest[..start]);
+        write!(buf, "{:.1}", size * scale).unwrap();
+        rest = &rest[start + len..];
+    }
+    buf.push_str(rest);
+    std::fs::write(path, buf)?;
+    Ok(())
 }
 
 impl<'a, 'b> Grapher<'a, 'b> {
@@ -21,9 +127,179 @@ impl<'a, 'b> Grapher<'a, 'b> {
             out,
             file_prefix: file_prefix.map(|x| x.to_owned()),
             vrate_range,
+            clean_svgs: false,
+            provenance: false,
+            result_hash: None,
+            confidence_markers: false,
+            json_views: false,
+            overlay_lat: false,
+            max_points: None,
+            font: None,
+            svg_size: DFL_SVG_SIZE,
+            dpi: DFL_DPI,
         }
     }
 
+    /// Dimensions of the SVGs in pixels. The number of axis ticks and the
+    /// font sizes scale with them so that the labels don't crowd smaller
+    /// graphs or get lost in larger ones.
+    pub fn set_svg_size(&mut self, size: (u32, u32)) -> &mut Self {
+        self.svg_size = size;
+        self
+    }
+
+    /// Density the SVGs are rasterized at when assembled into the PDF.
+    pub fn set_dpi(&mut self, dpi: u32) -> &mut Self {
+        self.dpi = dpi;
+        self
+    }
+
+    // Fonts scale with the shorter side relative to DFL_SVG_SIZE so that
+    // the labels still fit.
+    fn font_scale(&self) -> f64 {
+        (self.svg_size.0 as f64 / DFL_SVG_SIZE.0 as f64)
+            .min(self.svg_size.1 as f64 / DFL_SVG_SIZE.1 as f64)
+    }
+
+    // Save `view` as a `svg_size` SVG at `path` with the fonts scaled.
+    fn save_svg(&self, view: &ContinuousView, path: &str) -> Result<()> {
+        if let Err(e) = Page::single(view)
+            .dimensions(self.svg_size.0, self.svg_size.1)
+            .save(path)
+        {
+            bail!("{}", &e);
+        }
+        if self.svg_size != DFL_SVG_SIZE {
+            scale_svg_fonts(path, self.font_scale())?;
+        }
+        Ok(())
+    }
+
+    fn max_ticks(&self) -> (usize, usize) {
+        let scale = |len: u32, dfl: u32| {
+            ((DFL_MAX_TICKS as f64 * len as f64 / dfl as f64).round() as usize).clamp(2, 20)
+        };
+        (
+            scale(self.svg_size.0, DFL_SVG_SIZE.0),
+            scale(self.svg_size.1, DFL_SVG_SIZE.1),
+        )
+    }
+
+    /// Embed the scratch device model, kernel version, memory profile and
+    /// `result_hash` into the generated SVGs and PDF. `result_hash` should
+    /// be the fnv1a64 of the result file's bytes so that a graph can be
+    /// matched against the file it was plotted from. It's left out if
+    /// None.
+    pub fn set_provenance(&mut self, provenance: bool, result_hash: Option<u64>) -> &mut Self {
+        self.provenance = provenance;
+        self.result_hash = result_hash;
+        self
+    }
+
+    fn provenance_string(
+        data: &JobData,
+        res: &IoCostTuneResult,
+        result_hash: Option<u64>,
+    ) -> String {
+        let (model, kernel) = match data.sysinfo.sysreqs_report.as_ref() {
+            Some(sr) => (sr.scr_dev_model.trim(), sr.kernel_version.as_str()),
+            None => ("", ""),
+        };
+        let mut buf = format!(
+            "device={:?} kernel={:?} mem_profile={}",
+            model, kernel, res.mem_profile
+        );
+        if let Some(hash) = result_hash {
+            write!(buf, " result_hash={:016x}", hash).unwrap();
+        }
+        buf
+    }
+
+    /// Scale the point markers in the SVGs by the number of reports backing
+    /// each point. The plotted positions don't change.
+    pub fn set_confidence_markers(&mut self, confidence_markers: bool) -> &mut Self {
+        self.confidence_markers = confidence_markers;
+        self
+    }
+
+    /// Also write what's drawn in each SVG - ranges, labels, points and
+    /// line segments - to `<prefix>-<sel>.json` for external renderers.
+    pub fn set_json_views(&mut self, json_views: bool) -> &mut Self {
+        self.json_views = json_views;
+        self
+    }
+
+    /// Add views overlaying the p50/p90/p99 latencies of the same IO
+    /// direction and time percentile. The per-percentile views are kept.
+    pub fn set_overlay_lat(&mut self, overlay_lat: bool) -> &mut Self {
+        self.overlay_lat = overlay_lat;
+        self
+    }
+
+    /// Thin the non-outlier points of each SVG down to about `max_points`
+    /// by averaging them in equal-width vrate bins. Outliers and the fitted
+    /// lines are always drawn in full.
+    pub fn set_max_points(&mut self, max_points: Option<usize>) -> &mut Self {
+        self.max_points = max_points;
+        self
+    }
+
+    // The non-outlier points as drawn, downsampled if max_points is set.
+    fn sampled_points(&self, series: &DataSeries) -> Vec<DataPoint> {
+        match self.max_points {
+            Some(max) => Self::downsample_points(&series.points, max),
+            None => series.points.clone(),
+        }
+    }
+
+    // Average `points` into `nr_bins` vrate bins. Empty bins are skipped.
+    fn downsample_points(points: &[DataPoint], nr_bins: usize) -> Vec<DataPoint> {
+        if nr_bins == 0 || points.len() <= nr_bins {
+            return points.to_vec();
+        }
+        let (min, max) = points
+            .iter()
+            .fold((std::f64::MAX, std::f64::MIN), |acc, p| {
+                (acc.0.min(p.x), acc.1.max(p.x))
+            });
+        let width = (max - min) / nr_bins as f64;
+
+        let mut bins = vec![(DataPoint::default(), 0usize); nr_bins];
+        for p in points.iter() {
+            let idx = match width > 0.0 {
+                true => (((p.x - min) / width) as usize).min(nr_bins - 1),
+                false => 0,
+            };
+            let (acc, cnt) = &mut bins[idx];
+            acc.x += p.x;
+            acc.y += p.y;
+            acc.dur += p.dur;
+            acc.nr_samples += p.nr_samples;
+            *cnt += 1;
+        }
+
+        bins.into_iter()
+            .filter(|(_, cnt)| *cnt > 0)
+            .map(|(acc, cnt)| {
+                DataPoint::new(acc.x / cnt as f64, acc.y / cnt as f64)
+                    .with_samples(acc.dur, acc.nr_samples)
+            })
+            .collect()
+    }
+
+    /// Font for the PDF montage. "none" uses montage's default. If not
+    /// set, the first available of a few common sans fonts is used.
+    pub fn set_font(&mut self, font: Option<&str>) -> &mut Self {
+        self.font = font.map(|x| x.to_owned());
+        self
+    }
+
+    /// Delete the per-sel SVGs once the PDF is successfully produced.
+    pub fn set_clean_svgs(&mut self, clean: bool) -> &mut Self {
+        self.clean_svgs = clean;
+        self
+    }
+
     fn setup_view(
         vrate_range: (f64, f64),
         sel: &DataSel,
@@ -32,6 +308,24 @@ impl<'a, 'b> Grapher<'a, 'b> {
         isol_pct: &str,
         extra_info: Option<&str>,
     ) -> (ContinuousView, f64) {
+        let (gv, yscale) =
+            Self::setup_graph_view(vrate_range, sel, series, mem_profile, isol_pct, extra_info);
+        let view = ContinuousView::new()
+            .x_range(gv.x_range.0, gv.x_range.1)
+            .y_range(gv.y_range.0, gv.y_range.1)
+            .x_label(gv.labels.x)
+            .y_label(gv.labels.y);
+        (view, yscale)
+    }
+
+    fn setup_graph_view(
+        vrate_range: (f64, f64),
+        sel: &DataSel,
+        series: &DataSeries,
+        mem_profile: u32,
+        isol_pct: &str,
+        extra_info: Option<&str>,
+    ) -> (GraphView, f64) {
         let (val_min, val_max) = series
             .points
             .iter()
@@ -100,13 +394,37 @@ impl<'a, 'b> Grapher<'a, 'b> {
             ylabel += &format!(" ({})", extra_info.as_ref().unwrap());
         }
 
-        let view = ContinuousView::new()
-            .x_range(0.0, (vrate_range.1 * 1.1).max(0.000001))
-            .y_range(ymin * yscale, ymax * yscale)
-            .x_label(xlabel)
-            .y_label(ylabel);
+        let gv = GraphView {
+            x_range: (0.0, (vrate_range.1 * 1.1).max(0.000001)),
+            y_range: (ymin * yscale, ymax * yscale),
+            labels: GraphLabels {
+                x: xlabel,
+                y: ylabel,
+            },
+            points: series.points.iter().map(|p| (p.x, p.y * yscale)).collect(),
+            outliers: series
+                .outliers
+                .iter()
+                .map(|p| (p.x, p.y * yscale))
+                .collect(),
+            line_segments: Self::line_segments(series, yscale),
+        };
 
-        (view, yscale)
+        (gv, yscale)
+    }
+
+    fn line_segments(series: &DataSeries, yscale: f64) -> Vec<(f64, f64)> {
+        let lines = &series.lines;
+        let mut segments = vec![];
+        if series.lines.range.0 < lines.left.x {
+            segments.push((series.lines.range.0, lines.left.y * yscale));
+        }
+        segments.push((lines.left.x, lines.left.y * yscale));
+        segments.push((lines.right.x, lines.right.y * yscale));
+        if series.lines.range.1 > lines.right.x {
+            segments.push((series.lines.range.1, lines.right.y * yscale));
+        }
+        segments
     }
 
     fn plot_one_text(
@@ -159,7 +477,6 @@ impl<'a, 'b> Grapher<'a, 'b> {
         isol_pct: &str,
         extra_info: &str,
     ) -> Result<()> {
-        const SIZE: (u32, u32) = (576, 468);
         let (view, yscale) = Self::setup_view(
             self.vrate_range,
             sel,
@@ -182,67 +499,219 @@ impl<'a, 'b> Grapher<'a, 'b> {
             ),
         );
 
-        let points = series.points.iter().map(|p| (p.x, p.y * yscale)).collect();
-        let view = view.add(
-            Plot::new(points).point_style(
-                PointStyle::new()
-                    .marker(PointMarker::Circle)
-                    .colour("#37c0e6"),
-            ),
-        );
+        let sampled = self.sampled_points(series);
+        let max_samples = sampled.iter().map(|p| p.nr_samples).max().unwrap_or(0);
+        let view = if self.confidence_markers && max_samples > 0 {
+            // One plot per point as the marker size is per-plot.
+            sampled.iter().fold(view, |view, p| {
+                let size = 2.0 + 4.0 * p.nr_samples as f32 / max_samples as f32;
+                view.add(
+                    Plot::new(vec![(p.x, p.y * yscale)]).point_style(
+                        PointStyle::new()
+                            .marker(PointMarker::Circle)
+                            .colour("#37c0e6")
+                            .size(size),
+                    ),
+                )
+            })
+        } else {
+            let points = sampled.iter().map(|p| (p.x, p.y * yscale)).collect();
+            view.add(
+                Plot::new(points).point_style(
+                    PointStyle::new()
+                        .marker(PointMarker::Circle)
+                        .colour("#37c0e6"),
+                ),
+            )
+        };
 
-        let lines = &series.lines;
-        let mut segments = vec![];
-        if series.lines.range.0 < lines.left.x {
-            segments.push((series.lines.range.0, lines.left.y * yscale));
+        let segments = Self::line_segments(series, yscale);
+        let view = view.add(Plot::new(segments).line_style(LineStyle::new().colour("#3749e6")));
+
+        let (x_ticks, y_ticks) = self.max_ticks();
+        let view = view.x_max_ticks(x_ticks).y_max_ticks(y_ticks);
+        self.save_svg(&view, &self.plot_filename(sel))
+    }
+
+    fn overlay_filename(&self, first: &DataSel) -> String {
+        let (dir, time_pct) = match first {
+            DataSel::RLat(_, time_pct) => ("rlat", time_pct),
+            DataSel::WLat(_, time_pct) => ("wlat", time_pct),
+            _ => panic!("{} isn't a latency sel", first),
+        };
+        format!(
+            "{}-{}-overlay-{}.svg",
+            self.file_prefix.as_ref().unwrap(),
+            dir,
+            time_pct
+        )
+    }
+
+    // Latency groups with more than one of OVERLAY_LAT_PCTS, filtered down
+    // to those percentiles.
+    fn overlay_groups(sels: &[DataSel]) -> Vec<Vec<DataSel>> {
+        DataSel::group(sels.to_vec())
+            .into_iter()
+            .map(|grp| {
+                grp.into_iter()
+                    .filter(|sel| match sel {
+                        DataSel::RLat(lat_pct, _) | DataSel::WLat(lat_pct, _) => {
+                            OVERLAY_LAT_PCTS.iter().any(|(pct, _)| pct == lat_pct)
+                        }
+                        _ => false,
+                    })
+                    .collect::<Vec<DataSel>>()
+            })
+            .filter(|grp| grp.len() > 1)
+            .collect()
+    }
+
+    fn plot_one_overlay(
+        &self,
+        grp: &[DataSel],
+        res: &IoCostTuneResult,
+        extra_info: &str,
+    ) -> Result<()> {
+        let mut views = vec![];
+        for sel in grp.iter() {
+            let series = &res.data[sel];
+            let (gv, _) = Self::setup_graph_view(
+                self.vrate_range,
+                sel,
+                series,
+                res.mem_profile,
+                &res.isol_pct,
+                Some(extra_info),
+            );
+            views.push((sel, gv));
         }
-        segments.push((lines.left.x, lines.left.y * yscale));
-        segments.push((lines.right.x, lines.right.y * yscale));
-        if series.lines.range.1 > lines.right.x {
-            segments.push((series.lines.range.1, lines.right.y * yscale));
+
+        let y_max = views
+            .iter()
+            .fold(0.0_f64, |acc, (_, gv)| acc.max(gv.y_range.1));
+        let (dir, time_pct) = match grp[0] {
+            DataSel::RLat(_, ref time_pct) => ("rlat", time_pct),
+            DataSel::WLat(_, ref time_pct) => ("wlat", time_pct),
+            _ => panic!("{} isn't a latency sel", &grp[0]),
+        };
+        let mut view = ContinuousView::new()
+            .x_range(views[0].1.x_range.0, views[0].1.x_range.1)
+            .y_range(0.0, y_max.max(0.000001))
+            .x_label("vrate")
+            .y_label(format!("{}-{} ({})", dir, time_pct, extra_info));
+
+        for (sel, gv) in views.into_iter() {
+            let lat_pct = match sel {
+                DataSel::RLat(lat_pct, _) | DataSel::WLat(lat_pct, _) => lat_pct,
+                _ => unreachable!(),
+            };
+            let colour = OVERLAY_LAT_PCTS
+                .iter()
+                .find(|(pct, _)| pct == lat_pct)
+                .unwrap()
+                .1;
+            view = view
+                .add(
+                    Plot::new(gv.points)
+                        .point_style(PointStyle::new().marker(PointMarker::Circle).colour(colour)),
+                )
+                .add(
+                    Plot::new(gv.line_segments)
+                        .line_style(LineStyle::new().colour(colour))
+                        .legend(format!("p{}", lat_pct)),
+                );
         }
 
-        let view = view.add(Plot::new(segments).line_style(LineStyle::new().colour("#3749e6")));
+        let (x_ticks, y_ticks) = self.max_ticks();
+        let view = view.x_max_ticks(x_ticks).y_max_ticks(y_ticks);
+        self.save_svg(&view, &self.overlay_filename(&grp[0]))
+    }
 
-        let view = view.x_max_ticks(10).y_max_ticks(10);
+    fn json_filename(&self, sel: &DataSel) -> String {
+        format!("{}-{}.json", self.file_prefix.as_ref().unwrap(), sel)
+    }
 
-        if let Err(e) = Page::single(&view)
-            .dimensions(SIZE.0, SIZE.1)
-            .save(self.plot_filename(sel))
-        {
-            bail!("{}", &e);
-        }
+    fn write_one_json(
+        &self,
+        sel: &DataSel,
+        series: &DataSeries,
+        mem_profile: u32,
+        isol_pct: &str,
+        extra_info: &str,
+    ) -> Result<()> {
+        let (mut gv, yscale) = Self::setup_graph_view(
+            self.vrate_range,
+            sel,
+            series,
+            mem_profile,
+            isol_pct,
+            Some(extra_info),
+        );
+        gv.points = self
+            .sampled_points(series)
+            .iter()
+            .map(|p| (p.x, p.y * yscale))
+            .collect();
+        std::fs::write(self.json_filename(sel), serde_json::to_string_pretty(&gv)?)?;
         Ok(())
     }
 
-    fn collect_svgs(&self, sels: Vec<DataSel>, dst: &str) -> Result<()> {
+    fn collect_svgs(
+        &self,
+        sels: Vec<DataSel>,
+        overlays: &[String],
+        dst: &str,
+        meta: Option<&str>,
+    ) -> Result<()> {
         const NR_PER_PAGE: usize = 6;
 
         let groups = DataSel::align_and_merge_groups(DataSel::group(sels), NR_PER_PAGE);
         let mut srcs: Vec<String> = vec![];
-        for grp in groups.iter() {
-            srcs.extend(grp.iter().map(|sel| self.plot_filename(sel)));
-            let pad = NR_PER_PAGE - (grp.len() % NR_PER_PAGE);
+        let pad_page = |srcs: &mut Vec<String>, len: usize| {
+            let pad = NR_PER_PAGE - (len % NR_PER_PAGE);
             if pad < NR_PER_PAGE {
                 srcs.extend(std::iter::repeat("null:".to_owned()).take(pad));
             }
+        };
+        for grp in groups.iter() {
+            srcs.extend(grp.iter().map(|sel| self.plot_filename(sel)));
+            pad_page(&mut srcs, grp.len());
         }
+        // Overlays go on their own pages at the end.
+        srcs.extend(overlays.iter().cloned());
+        pad_page(&mut srcs, overlays.len());
+
+        let (font, emsg) = match self.font.as_deref() {
+            Some("none") => (None, "is imagemagick available?".to_owned()),
+            Some(font) => (
+                Some(font.to_owned()),
+                format!("are imagemagick and font {:?} available?", font),
+            ),
+            None => match probe_font() {
+                Some(font) => (Some(font), "is imagemagick available?".to_owned()),
+                None => (
+                    None,
+                    "is imagemagick available? No usable font was found, try installing cantarell"
+                        .to_owned(),
+                ),
+            },
+        };
 
         run_command(
             Command::new("montage")
-                .args(&[
-                    "-font",
-                    "cantarell",
-                    "-density",
-                    "150",
-                    "-tile",
-                    "2x3",
-                    "-geometry",
-                    "+0+0",
-                ])
+                .args(
+                    font.map(|font| vec!["-font".to_owned(), font])
+                        .unwrap_or_default(),
+                )
+                .args(&["-density", &format!("{}", self.dpi)])
+                .args(&["-tile", "2x3", "-geometry", "+0+0"])
+                .args(
+                    meta.map(|meta| vec!["-define".to_owned(), format!("pdf:Subject={}", meta)])
+                        .unwrap_or_default(),
+                )
                 .args(srcs)
                 .arg(dst),
-            "are imagemagick and cantarell font available?",
+            &emsg,
         )
     }
 
@@ -254,6 +723,11 @@ impl<'a, 'b> Grapher<'a, 'b> {
             return Ok(());
         }
 
+        let meta = match self.provenance {
+            true => Some(Self::provenance_string(data, res, self.result_hash)),
+            false => None,
+        };
+
         for (sel, series) in res.data.iter() {
             let sr = data.sysinfo.sysreqs_report.as_ref().unwrap();
             if let Err(e) = self.plot_one_svg(
@@ -269,11 +743,120 @@ impl<'a, 'b> Grapher<'a, 'b> {
                     &e
                 );
             }
+            if self.json_views {
+                let path = self.json_filename(sel);
+                self.write_one_json(
+                    sel,
+                    series,
+                    res.mem_profile,
+                    &res.isol_pct,
+                    &format!("{}", sr.scr_dev_model.trim()),
+                )
+                .map_err(|e| anyhow!("Failed to write graph view into {:?} ({})", &path, &e))?;
+            }
+            if let Some(meta) = meta.as_ref() {
+                let path = self.plot_filename(sel);
+                inject_svg_metadata(&path, meta)
+                    .map_err(|e| anyhow!("Failed to add metadata to {:?} ({})", &path, &e))?;
+            }
+        }
+
+        let sels: Vec<DataSel> = res.data.iter().map(|(sel, _)| sel).cloned().collect();
+
+        let mut overlays = vec![];
+        if self.overlay_lat {
+            let sr = data.sysinfo.sysreqs_report.as_ref().unwrap();
+            for grp in Self::overlay_groups(&sels).iter() {
+                let path = self.overlay_filename(&grp[0]);
+                self.plot_one_overlay(grp, res, sr.scr_dev_model.trim())
+                    .map_err(|e| anyhow!("Failed to plot graph into {:?} ({})", &path, &e))?;
+                if let Some(meta) = meta.as_ref() {
+                    inject_svg_metadata(&path, meta)
+                        .map_err(|e| anyhow!("Failed to add metadata to {:?} ({})", &path, &e))?;
+                }
+                overlays.push(path);
+            }
         }
 
-        let sels = res.data.iter().map(|(sel, _)| sel).cloned().collect();
         let dst = format!("{}.pdf", self.file_prefix.as_ref().unwrap());
-        self.collect_svgs(sels, &dst)
-            .map_err(|e| anyhow!("Failed to collect graphs into {:?} ({})", &dst, &e))
+        self.collect_svgs(sels.clone(), &overlays, &dst, meta.as_deref())
+            .map_err(|e| anyhow!("Failed to collect graphs into {:?} ({})", &dst, &e))?;
+
+        if self.clean_svgs {
+            let paths = sels
+                .iter()
+                .map(|sel| self.plot_filename(sel))
+                .chain(overlays.into_iter());
+            for path in paths {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    warn!("iocost-tune: Failed to remove {:?} ({})", &path, &e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_bench_iocost_tune_graph_downsample() {
+        use super::{DataPoint, Grapher};
+
+        let points: Vec<DataPoint> = (0..100)
+            .map(|i| DataPoint::new(i as f64, 1.0).with_samples(1.0, 1))
+            .collect();
+        assert_eq!(Grapher::downsample_points(&points, 200).len(), 100);
+
+        let sampled = Grapher::downsample_points(&points, 10);
+        assert_eq!(sampled.len(), 10);
+        assert_eq!(sampled[0].x, 4.5);
+        assert!(sampled.iter().all(|p| p.y == 1.0 && p.nr_samples == 10));
+    }
+
+    #[test]
+    fn test_bench_iocost_tune_graph_svg_metadata() {
+        let path = std::env::temp_dir()
+            .join(format!("resctl-bench-test-{}.svg", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_owned();
+        std::fs::write(
+            &path,
+            "<?xml version=\"1.0\"?>\n<svg width=\"10\" height=\"10\"><g/></svg>\n",
+        )
+        .unwrap();
+
+        let meta = "device=\"Disk <X>\" mem_profile=16";
+        super::inject_svg_metadata(&path, meta).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(svg.contains(
+            "<svg width=\"10\" height=\"10\">\n<metadata>device=&quot;Disk &lt;X&gt;&quot; mem_profile=16</metadata><g/>"
+        ));
+    }
+
+    #[test]
+    fn test_bench_iocost_tune_graph_svg_font_scale() {
+        let path = std::env::temp_dir()
+            .join(format!("resctl-bench-test-font-{}.svg", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_owned();
+        std::fs::write(
+            &path,
+            "<svg><text font-size=\"12\">a</text><text font-size=\"9\">b</text></svg>",
+        )
+        .unwrap();
+
+        super::scale_svg_fonts(&path, 1.5).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            svg,
+            "<svg><text font-size=\"18.0\">a</text><text font-size=\"13.5\">b</text></svg>"
+        );
     }
 }
@@ -0,0 +1,196 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+use super::*;
+use std::collections::BTreeMap;
+use std::hint::black_box;
+use std::time::Instant;
+
+struct MemBwJob {
+    size: usize,
+    loops: u32,
+}
+
+impl Default for MemBwJob {
+    fn default() -> Self {
+        Self {
+            size: 1 << 30,
+            loops: 5,
+        }
+    }
+}
+
+pub struct MemBwBench {}
+
+impl Bench for MemBwBench {
+    fn desc(&self) -> BenchDesc {
+        BenchDesc::new("mem-bw")
+            .about("Measure streaming memory read and write bandwidth as a baseline")
+            .takes_run_props()
+    }
+
+    fn parse(&self, spec: &JobSpec, _prev_data: Option<&JobData>) -> Result<Box<dyn Job>> {
+        let mut job = MemBwJob::default();
+
+        for (k, v) in spec.props[0].iter() {
+            match k.as_str() {
+                "size" => job.size = parse_size(v)? as usize,
+                "loops" => job.loops = v.parse::<u32>()?,
+                k => bail!("unknown property key {:?}", k),
+            }
+        }
+
+        if job.size < (1 << 20) {
+            bail!("size must be at least 1M");
+        }
+        if job.loops == 0 {
+            bail!("loops must be positive");
+        }
+
+        Ok(Box::new(job))
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct MemBwRecord {
+    size: usize,
+    // (read, write) bytes per second of each loop.
+    bps: Vec<(f64, f64)>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct MemBwResult {
+    read_bps: f64,
+    read_bps_stdev: f64,
+    write_bps: f64,
+    write_bps_stdev: f64,
+}
+
+impl MemBwJob {
+    // Stream through `buf` once writing and once reading. Returns the
+    // (read, write) bandwidths in bytes per second.
+    fn measure_one(buf: &mut [u64], seed: u64) -> (f64, f64) {
+        let bytes = (buf.len() * std::mem::size_of::<u64>()) as f64;
+
+        let started_at = Instant::now();
+        for (i, v) in buf.iter_mut().enumerate() {
+            *v = seed.wrapping_add(i as u64);
+        }
+        black_box(&mut *buf);
+        let write_dur = started_at.elapsed().as_secs_f64();
+
+        let started_at = Instant::now();
+        let sum = buf.iter().fold(0u64, |acc, v| acc.wrapping_add(*v));
+        black_box(sum);
+        let read_dur = started_at.elapsed().as_secs_f64();
+
+        (bytes / read_dur.max(1e-9), bytes / write_dur.max(1e-9))
+    }
+}
+
+impl Job for MemBwJob {
+    fn sysreqs(&self) -> BTreeSet<SysReq> {
+        MIN_SYSREQS.clone()
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "Streaming memory bandwidth, {} buffer, {} loops",
+            format_size(self.size),
+            self.loops
+        )
+    }
+
+    fn run(&mut self, rctx: &mut RunCtx) -> Result<serde_json::Value> {
+        rctx.skip_mem_profile().start_agent(vec![])?;
+
+        info!(
+            "mem-bw: Measuring memory bandwidth with {} buffer, {} loops",
+            format_size(self.size),
+            self.loops
+        );
+
+        // vec! hands out untouched zero pages, fault them all in up front so
+        // that the first loop doesn't measure page faults.
+        let mut buf = vec![0u64; self.size / std::mem::size_of::<u64>()];
+        buf.fill(u64::MAX);
+        black_box(&mut buf);
+        let mut rec = MemBwRecord {
+            size: self.size,
+            bps: vec![],
+        };
+        for i in 0..self.loops {
+            if prog_exiting() {
+                bail!("Program exiting");
+            }
+            let (rbps, wbps) = Self::measure_one(&mut buf, i as u64);
+            info!(
+                "mem-bw: [{}/{}] read={}/s write={}/s",
+                i + 1,
+                self.loops,
+                format_size(rbps),
+                format_size(wbps)
+            );
+            rec.bps.push((rbps, wbps));
+        }
+
+        Ok(serde_json::to_value(&rec).unwrap())
+    }
+
+    fn study(&self, _rctx: &mut RunCtx, rec_json: serde_json::Value) -> Result<serde_json::Value> {
+        let rec: MemBwRecord = parse_json_value_or_dump(rec_json)?;
+        let rbps: Vec<f64> = rec.bps.iter().map(|x| x.0).collect();
+        let wbps: Vec<f64> = rec.bps.iter().map(|x| x.1).collect();
+
+        let stdev = |vals: &[f64]| match vals.len() {
+            0 | 1 => 0.0,
+            _ => statistical::standard_deviation(vals, None),
+        };
+
+        let result = MemBwResult {
+            read_bps: statistical::mean(&rbps),
+            read_bps_stdev: stdev(&rbps),
+            write_bps: statistical::mean(&wbps),
+            write_bps_stdev: stdev(&wbps),
+        };
+
+        Ok(serde_json::to_value(&result).unwrap())
+    }
+
+    fn format<'a>(
+        &self,
+        mut out: Box<dyn Write + 'a>,
+        data: &JobData,
+        opts: &FormatOpts,
+        _props: &JobProps,
+    ) -> Result<()> {
+        let rec: MemBwRecord = data.parse_record()?;
+        let res: MemBwResult = data.parse_result()?;
+
+        writeln!(
+            out,
+            "Params: size={} loops={}\n",
+            opts.format_size(rec.size),
+            rec.bps.len()
+        )
+        .unwrap();
+
+        writeln!(
+            out,
+            "Result: read={:.2}GB/s (stdev {:.2}) write={:.2}GB/s (stdev {:.2})",
+            res.read_bps / 1_000_000_000.0,
+            res.read_bps_stdev / 1_000_000_000.0,
+            res.write_bps / 1_000_000_000.0,
+            res.write_bps_stdev / 1_000_000_000.0,
+        )
+        .unwrap();
+
+        Ok(())
+    }
+
+    fn metrics(&self, data: &JobData) -> Result<BTreeMap<String, Metric>> {
+        let res: MemBwResult = data.parse_result()?;
+        Ok([("read_bps", res.read_bps), ("write_bps", res.write_bps)]
+            .iter()
+            .map(|(k, v)| (k.to_string(), Metric::higher(*v)))
+            .collect())
+    }
+}
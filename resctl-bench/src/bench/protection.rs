@@ -214,7 +214,9 @@ pub struct ProtectionBench {}
 
 impl Bench for ProtectionBench {
     fn desc(&self) -> BenchDesc {
-        BenchDesc::new("protection").takes_run_propsets()
+        BenchDesc::new("protection")
+            .about("Measure how well the workload is protected against memory hogs")
+            .takes_run_propsets()
     }
 
     fn parse(&self, spec: &JobSpec, _prev_data: Option<&JobData>) -> Result<Box<dyn Job>> {
@@ -336,7 +338,7 @@ impl ProtectionJob {
                     ScenarioResult::MemHogTune(res),
                 ) => {
                     print_header(&mut out, idx, "Memory Hog Tuning");
-                    scn.format_params(&mut out);
+                    scn.format_params(&mut out, opts);
                     writeln!(out, "").unwrap();
                     scn.format_result(&mut out, rec, res, opts);
                 }
@@ -349,7 +349,7 @@ impl MemHog {
                     // absolute model parameters. As vrate is sampled, if it
                     // fluctuates at high frequency, this can introduce
                     // significant errors.
-                    let vrate = arg.rep.iocost.vrate / 100.0;
+                    let vrate = arg.rep.iocost.current_vrate().unwrap_or(100.0) / 100.0;
                     io_usage += (root - last_root).max(0.0) * vrate;
                     io_unused += (arg.dur - (root - last_root)).max(0.0) * vrate;
                     hog_io_usage += (hog - last_hog).max(0.0) * vrate;
@@ -403,7 +403,13 @@ impl MemHog {
 
         // The followings are captured over the entire period. vrate mean
         // isn't used in the process but report to help visibility.
-        let mut study_vrate_mean = StudyMean::new(|arg| [arg.rep.iocost.vrate].repeat(arg.cnt));
+        let mut study_vrate_mean = StudyMean::new(|arg| {
+            arg.rep
+                .iocost
+                .current_vrate()
+                .map(|vrate| [vrate].repeat(arg.cnt))
+                .unwrap_or_default()
+        });
         let mut study_read_lat_pcts = StudyIoLatPcts::new("read", None);
         let mut study_write_lat_pcts = StudyIoLatPcts::new("write", None);
 
@@ -633,7 +639,7 @@ impl MemHog {
         .unwrap();
     }
 
-    fn format_info<'a>(out: &mut Box<dyn Write + 'a>, result: &MemHogResult) {
+    fn format_info<'a>(out: &mut Box<dyn Write + 'a>, result: &MemHogResult, opts: &FormatOpts) {
         writeln!(
             out,
             "Info: baseline_rps={:.2} baseline_lat={}:{} vrate={:.2}:{:.2}",
@@ -653,8 +659,8 @@ impl MemHog {
         writeln!(
             out,
             "      hog_bytes={} hog_lost_bytes={}\n",
-            format_size(result.hog_bytes),
-            format_size(result.hog_lost_bytes)
+            opts.format_size(result.hog_bytes),
+            opts.format_size(result.hog_lost_bytes)
         )
         .unwrap();
     }
@@ -665,7 +671,7 @@ impl MemHog {
         opts: &FormatOpts,
     ) {
         if opts.full {
-            Self::format_info(out, result);
+            Self::format_info(out, result, opts);
         }
 
         StudyIoLatPcts::format_rw(out, result.iolat.as_ref(), opts, None);
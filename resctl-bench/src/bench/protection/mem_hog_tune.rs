@@ -192,14 +192,14 @@ impl MemHogTune {
         }
     }
 
-    pub fn format_params<'a>(&self, out: &mut Box<dyn Write + 'a>) {
+    pub fn format_params<'a>(&self, out: &mut Box<dyn Write + 'a>, opts: &FormatOpts) {
         writeln!(
             out,
             "Params: load={} speed={} size={}-{} intvs={}",
             self.load,
             self.speed,
-            format_size(self.size_range.0),
-            format_size(self.size_range.1),
+            opts.format_size(self.size_range.0),
+            opts.format_size(self.size_range.1),
             self.intvs,
         )
         .unwrap();
@@ -234,8 +234,8 @@ impl MemHogTune {
                 writeln!(
                     out,
                     "        hashd memory size {}/{} can be protected at isol-{} <= {}%",
-                    format_size(final_size),
-                    format_size(self.size_range.1),
+                    opts.format_size(final_size),
+                    opts.format_size(self.size_range.1),
                     self.isol_pct,
                     format_pct(self.isol_thr),
                 )
@@ -246,8 +246,8 @@ impl MemHogTune {
                 "        Failed to find size to keep isol-{} above {}% in [{}, {}]",
                 self.isol_pct,
                 format_pct(self.isol_thr),
-                format_size(self.size_range.0),
-                format_size(self.size_range.1),
+                opts.format_size(self.size_range.0),
+                opts.format_size(self.size_range.1),
             )
             .unwrap(),
         }
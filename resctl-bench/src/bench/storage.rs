@@ -47,7 +47,9 @@ pub struct StorageBench {}
 
 impl Bench for StorageBench {
     fn desc(&self) -> BenchDesc {
-        BenchDesc::new("storage").takes_run_props()
+        BenchDesc::new("storage")
+            .about("Measure how much memory the storage device can offload")
+            .takes_run_props()
     }
 
     fn parse(&self, spec: &JobSpec, _prev_data: Option<&JobData>) -> Result<Box<dyn Job>> {
@@ -83,10 +85,10 @@ impl StorageJob {
 
         for (k, v) in spec.props[0].iter() {
             match k.as_str() {
-                "hash-size" => job.hash_size = v.parse::<usize>()?,
+                "hash-size" => job.hash_size = HashdFakeCpuBench::parse_hash_size(v)?,
                 "chunk-pages" => job.chunk_pages = v.parse::<usize>()?,
-                "rps-max" => job.rps_max = v.parse::<u32>()?,
-                "log-bps" => job.log_bps = v.parse::<u64>()?,
+                "rps-max" => job.rps_max = HashdFakeCpuBench::parse_rps_max(v)?,
+                "log-bps" => job.log_bps = HashdFakeCpuBench::parse_log_bps(v)?,
                 "loops" => job.loops = v.parse::<u32>()?,
                 "mem-avail-err-max" => job.mem_avail_err_max = v.parse::<f64>()?,
                 "mem-avail-inner-retries" => job.mem_avail_inner_retries = v.parse::<u32>()?,
@@ -235,13 +237,14 @@ impl StorageJob {
         _rec: &StorageRecord,
         _res: &StorageResult,
         include_loops: bool,
+        opts: &FormatOpts,
     ) {
         write!(
             out,
             "Params: hash_size={} rps_max={} log_bps={}",
-            format_size(self.hash_size),
+            opts.format_size(self.hash_size),
             self.rps_max,
-            format_size(self.log_bps)
+            opts.format_size(self.log_bps)
         )
         .unwrap();
 
@@ -269,10 +272,10 @@ impl StorageJob {
         writeln!(
             out,
             "IO BPS: read_final={} write_final={} read_all={} write_all={}",
-            format_size(res.final_rstat.io_bps.0["mean"]),
-            format_size(res.final_rstat.io_bps.1["mean"]),
-            format_size(res.all_rstat.io_bps.0["mean"]),
-            format_size(res.all_rstat.io_bps.1["mean"])
+            opts.format_size(res.final_rstat.io_bps.0["mean"]),
+            opts.format_size(res.final_rstat.io_bps.1["mean"]),
+            opts.format_size(res.all_rstat.io_bps.0["mean"]),
+            opts.format_size(res.all_rstat.io_bps.1["mean"])
         )
         .unwrap();
     }
@@ -282,6 +285,7 @@ impl StorageJob {
         out: &mut Box<dyn Write + 'a>,
         rec: &StorageRecord,
         res: &StorageResult,
+        opts: &FormatOpts,
     ) {
         write!(
             out,
@@ -293,10 +297,10 @@ impl StorageJob {
             writeln!(
                 out,
                 "usage/stdev={}/{} size/stdev={}/{} missing={}%",
-                format_size(res.mem_usage),
-                format_size(res.mem_usage_stdev),
-                format_size(res.mem_size),
-                format_size(res.mem_size_stdev),
+                opts.format_size(res.mem_usage),
+                opts.format_size(res.mem_usage_stdev),
+                opts.format_size(res.mem_size),
+                opts.format_size(res.mem_size_stdev),
                 format_pct(Studies::reports_missing(res.nr_reports)),
             )
             .unwrap();
@@ -304,8 +308,8 @@ impl StorageJob {
             writeln!(
                 out,
                 "usage={} size={} missing={}%",
-                format_size(res.mem_usage),
-                format_size(res.mem_size),
+                opts.format_size(res.mem_usage),
+                opts.format_size(res.mem_size),
                 format_pct(Studies::reports_missing(res.nr_reports)),
             )
             .unwrap();
@@ -321,7 +325,7 @@ impl StorageJob {
         opts: &FormatOpts,
     ) {
         if header {
-            self.format_header(out, rec, res, true);
+            self.format_header(out, rec, res, true, opts);
             writeln!(out, "").unwrap();
         }
         StudyIoLatPcts::format_rw(out, &res.iolat, opts, None);
@@ -330,7 +334,7 @@ impl StorageJob {
         self.format_rstat(out, rec, res, opts);
 
         writeln!(out, "").unwrap();
-        self.format_mem_summary(out, rec, res);
+        self.format_mem_summary(out, rec, res, opts);
     }
 }
 
@@ -339,6 +343,14 @@ impl Job for StorageJob {
         HASHD_SYSREQS.clone()
     }
 
+    fn describe(&self) -> String {
+        format!(
+            "Storage memory offloading capacity, {} loops, log_bps={}",
+            self.loops,
+            format_size(self.log_bps)
+        )
+    }
+
     fn run(&mut self, rctx: &mut RunCtx) -> Result<serde_json::Value> {
         if !self.active {
             rctx.set_passive_keep_crit_mem_prot();
@@ -486,4 +498,21 @@ impl Job for StorageJob {
         self.format_result(&mut out, &rec, &res, true, opts);
         Ok(())
     }
+
+    fn metrics(&self, data: &JobData) -> Result<BTreeMap<String, Metric>> {
+        let res: StorageResult = data.parse_result()?;
+        let mut metrics: BTreeMap<String, Metric> = [(
+            "mem_offload_factor".to_owned(),
+            Metric::higher(res.mem_offload_factor).mof(),
+        )]
+        .iter()
+        .cloned()
+        .collect();
+        for (rw, iolat) in ["read", "write"].iter().zip(res.iolat.iter()) {
+            if let Some(lat) = iolat.get("99").and_then(|pcts| pcts.get("mean")) {
+                metrics.insert(format!("{}_lat_p99_mean", rw), Metric::lower(*lat));
+            }
+        }
+        Ok(metrics)
+    }
 }
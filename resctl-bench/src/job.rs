@@ -1,12 +1,14 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
 use anyhow::{anyhow, bail, Context, Result};
 use chrono::{DateTime, Local};
-use log::{debug, warn};
+use log::{debug, error, warn};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Write;
 use std::fs;
 use std::io::{Read, Write as IoWrite};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, UNIX_EPOCH};
@@ -14,14 +16,171 @@ use util::*;
 
 use super::base::MemInfo;
 use super::parse_json_value_or_dump;
+use super::rapl::RaplSampler;
 use super::run::RunCtx;
-use rd_agent_intf::{SysReq, SysReqsReport};
+use rd_agent_intf::{IoStatReport, PsiReport, Slice, SysReq, SysReqsReport, ROOT_SLICE};
 use resctl_bench_intf::{JobProps, JobSpec};
 
+// memory.events:high rate of the workload slice above which results are
+// flagged as memory.high throttled.
+const MEM_HIGH_EVENTS_WARN_RATE: f64 = 1.0;
+
 #[derive(Debug, Clone)]
 pub struct FormatOpts {
     pub full: bool,
     pub rstat: u32,
+    pub decimal_size: bool,
+    pub width: Option<usize>,
+    pub format_version: u32,
+    // fnv1a64 of the result file's contents as loaded, if known.
+    pub result_hash: Option<u64>,
+}
+
+impl FormatOpts {
+    pub const DFL_WIDTH: usize = 90;
+    /// Latest text layout version. Older versions are kept selectable so
+    /// that saved reports can still be diffed against new runs.
+    pub const FORMAT_VERSION: u32 = 2;
+
+    /// Width of separators and wrapped lists.
+    pub fn width(&self) -> usize {
+        self.width.unwrap_or(Self::DFL_WIDTH)
+    }
+
+    /// Join `items` with ", " wrapping at `width()`. Continuation lines are
+    /// indented by `indent` spaces.
+    pub fn wrap_list(&self, first: &str, items: &[String], indent: usize) -> String {
+        let mut buf = first.to_string();
+        let mut line_len = first.len();
+        for (i, item) in items.iter().enumerate() {
+            let sep = if i + 1 < items.len() { "," } else { "" };
+            if i > 0 {
+                if line_len + 1 + item.len() + sep.len() > self.width() {
+                    buf += "\n";
+                    buf += &" ".repeat(indent);
+                    line_len = indent;
+                } else {
+                    buf += " ";
+                    line_len += 1;
+                }
+            }
+            buf += item;
+            buf += sep;
+            line_len += item.len() + sep.len();
+        }
+        buf
+    }
+
+    /// Binary units by default, decimal with two fractional digits if
+    /// `decimal_size` is set. "-" if `size` is NaN or negative.
+    pub fn format_size<T>(&self, size: T) -> String
+    where
+        T: num_traits::ToPrimitive,
+    {
+        let size = match size.to_u64() {
+            Some(v) => v,
+            None => match size.to_f64() {
+                Some(v) if v.is_infinite() && v > 0.0 => std::u64::MAX,
+                _ => return "-".into(),
+            },
+        };
+        match self.decimal_size {
+            false => format_size(size),
+            true => format_size_prec(size, SizeBase::Decimal, 2),
+        }
+    }
+}
+
+/// A scalar result used to detect regressions against a baseline.
+#[derive(Debug, Clone, Copy)]
+pub struct Metric {
+    pub val: f64,
+    pub higher_is_better: bool,
+    // Memory offload factor which should be normalized when comparing
+    // across different memory profiles.
+    pub mof: bool,
+}
+
+impl Metric {
+    pub fn higher(val: f64) -> Self {
+        Self {
+            val,
+            higher_is_better: true,
+            mof: false,
+        }
+    }
+
+    pub fn lower(val: f64) -> Self {
+        Self {
+            val,
+            higher_is_better: false,
+            mof: false,
+        }
+    }
+
+    pub fn mof(mut self) -> Self {
+        self.mof = true;
+        self
+    }
+
+    /// Scale the MOF metrics by `MemInfo::mof_norm_factor()`. Returns the
+    /// number of metrics normalized.
+    pub fn normalize_mofs(metrics: &mut BTreeMap<String, Metric>, mem: &MemInfo) -> usize {
+        let factor = mem.mof_norm_factor();
+        let mut nr = 0;
+        for metric in metrics.values_mut().filter(|m| m.mof) {
+            metric.val *= factor;
+            nr += 1;
+        }
+        nr
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricRegression {
+    pub name: String,
+    pub base: f64,
+    pub new: f64,
+    // Relative change in the worse direction.
+    pub change: f64,
+    pub thr: f64,
+}
+
+impl MetricRegression {
+    /// Compare `new` metrics against `base`. `thrs` can override the
+    /// default threshold `dfl_thr` per metric name.
+    pub fn find(
+        base: &BTreeMap<String, Metric>,
+        new: &BTreeMap<String, Metric>,
+        thrs: &BTreeMap<String, f64>,
+        dfl_thr: f64,
+    ) -> Vec<Self> {
+        let mut regs = vec![];
+        for (name, nm) in new.iter() {
+            let bm = match base.get(name) {
+                Some(v) => v,
+                None => continue,
+            };
+            if bm.val == 0.0 {
+                continue;
+            }
+            let mut change = (nm.val - bm.val) / bm.val.abs();
+            if nm.higher_is_better {
+                change = -change;
+            }
+            let thr = *thrs.get(name).unwrap_or(&dfl_thr);
+            if change > thr {
+                regs.push(Self {
+                    name: name.clone(),
+                    base: bm.val,
+                    new: nm.val,
+                    change,
+                    thr,
+                });
+            }
+        }
+        regs
+    }
 }
 
 pub trait Job {
@@ -40,6 +199,15 @@ pub trait Job {
         opts: &FormatOpts,
         props: &JobProps,
     ) -> Result<()>;
+    /// Human summary of what's measured and how, printed right after the
+    /// result header. Empty to skip.
+    fn describe(&self) -> String {
+        String::new()
+    }
+    /// Key scalar results for baseline comparisons.
+    fn metrics(&self, _data: &JobData) -> Result<BTreeMap<String, Metric>> {
+        Ok(Default::default())
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
@@ -50,6 +218,157 @@ pub struct SysInfo {
     pub iocost: rd_agent_intf::IoCostReport,
     pub mem: MemInfo,
     pub swappiness: u32,
+    #[serde(default)]
+    pub psi: BTreeMap<String, PsiReport>,
+    #[serde(default)]
+    pub swap: Option<SwapStat>,
+    #[serde(default)]
+    pub agent_args: Vec<String>,
+    // Per-slice scratch device io.stat deltas over the run period.
+    #[serde(default)]
+    pub slice_io: BTreeMap<String, IoStatReport>,
+    // Per-CPU utilizations over the run period.
+    #[serde(default)]
+    pub cpu_util: Vec<f64>,
+    #[serde(default)]
+    pub faults: FaultStat,
+    // memory.events:high increase of workload.slice over the run period.
+    #[serde(default)]
+    pub work_mem_high_events: u64,
+    // Number of cgroup values rd-agent had to correct over the run period
+    // keyed by SLICE/FIELD.
+    #[serde(default)]
+    pub slice_fixes: BTreeMap<String, u64>,
+    // Joules consumed by the CPU packages if --rapl and the seconds they
+    // were measured over. The latter is shorter than the period if it was
+    // extended with --incremental as only the last run is measured.
+    #[serde(default)]
+    pub energy: Option<f64>,
+    #[serde(default)]
+    pub energy_dur: f64,
+    // Overridden scheduling and io priorities of resctl-bench itself.
+    #[serde(default)]
+    pub self_nice: Option<i32>,
+    #[serde(default)]
+    pub self_ionice: Option<String>,
+    // Per-CPU cpufreq governors from before they were pinned for the run,
+    // empty if they weren't. sysreqs_report.cpufreq shows the pinned ones.
+    #[serde(default)]
+    pub cpu_governor_orig: BTreeMap<usize, String>,
+    // Seconds of hashd bench warmup excluded from the start of the period.
+    #[serde(default)]
+    pub warmup: Option<f64>,
+}
+
+// Context switches and major faults over a job's run period. Zero if the
+// counters weren't available. The kernel doesn't count involuntary context
+// switches system-wide, nr_ctxsw_all is all switches from /proc/stat.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FaultStat {
+    #[serde(alias = "nr_ctxsw")]
+    pub nr_ctxsw_all: u64,
+    pub nr_majfaults: u64,
+    pub slice_majfaults: BTreeMap<String, u64>,
+}
+
+impl FaultStat {
+    fn from_reports(rctx: &RunCtx, period: (u64, u64)) -> Self {
+        let (first, last) = match rctx.first_last_reports(period) {
+            Some(v) => v,
+            None => return Default::default(),
+        };
+        let delta = |end: Option<&f64>, start: Option<&f64>| match (end, start) {
+            (Some(end), Some(start)) => (end - start).max(0.0) as u64,
+            _ => 0,
+        };
+        Self {
+            nr_ctxsw_all: last.nr_ctxsw_all.saturating_sub(first.nr_ctxsw_all),
+            nr_majfaults: delta(
+                last.vmstat.get("pgmajfault"),
+                first.vmstat.get("pgmajfault"),
+            ),
+            slice_majfaults: last
+                .mem_stat
+                .iter()
+                .filter(|(slice, _)| *slice != ROOT_SLICE)
+                .map(|(slice, end)| {
+                    let start = first.mem_stat.get(slice).and_then(|x| x.get("pgmajfault"));
+                    (slice.clone(), delta(end.get("pgmajfault"), start))
+                })
+                .collect(),
+        }
+    }
+}
+
+// Swap usage and swap-in/out rates over a job's run period.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SwapStat {
+    pub usage_avg: u64,
+    pub usage_peak: u64,
+    pub in_bps_avg: f64,
+    pub in_bps_peak: f64,
+    pub out_bps_avg: f64,
+    pub out_bps_peak: f64,
+}
+
+impl SwapStat {
+    fn from_reports(rctx: &RunCtx, period: (u64, u64)) -> Option<Self> {
+        let page_size = *PAGE_SIZE as f64;
+        let mut stat = SwapStat::default();
+        let mut usage_sum = 0;
+        let mut nr_reps = 0;
+        let mut first: Option<(u64, f64, f64)> = None;
+        let mut last: Option<(u64, f64, f64)> = None;
+
+        for (rep, at) in rctx.report_iter(period) {
+            let rep = match rep {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            let usage = rep
+                .usages
+                .get(ROOT_SLICE)
+                .map(|u| u.swap_bytes)
+                .unwrap_or(0);
+            stat.usage_peak = stat.usage_peak.max(usage);
+            usage_sum += usage;
+            nr_reps += 1;
+
+            let cur = (
+                at,
+                rep.vmstat.get("pswpin").cloned().unwrap_or(0.0),
+                rep.vmstat.get("pswpout").cloned().unwrap_or(0.0),
+            );
+            if let Some(prev) = last {
+                let dur = (cur.0 - prev.0) as f64;
+                if dur > 0.0 {
+                    stat.in_bps_peak = stat
+                        .in_bps_peak
+                        .max((cur.1 - prev.1).max(0.0) * page_size / dur);
+                    stat.out_bps_peak = stat
+                        .out_bps_peak
+                        .max((cur.2 - prev.2).max(0.0) * page_size / dur);
+                }
+            }
+            if first.is_none() {
+                first = Some(cur);
+            }
+            last = Some(cur);
+        }
+
+        if nr_reps == 0 {
+            return None;
+        }
+        stat.usage_avg = usage_sum / nr_reps;
+        if let (Some(first), Some(last)) = (first, last) {
+            let dur = (last.0 - first.0) as f64;
+            if dur > 0.0 {
+                stat.in_bps_avg = (last.1 - first.1).max(0.0) * page_size / dur;
+                stat.out_bps_avg = (last.2 - first.2).max(0.0) * page_size / dur;
+            }
+        }
+        Some(stat)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -59,6 +378,35 @@ pub struct JobData {
     pub sysinfo: SysInfo,
     pub record: Option<serde_json::Value>,
     pub result: Option<serde_json::Value>,
+    // Set to the recording period if the result was produced by studying
+    // previously recorded data rather than a new run.
+    #[serde(default)]
+    pub studied_from: Option<(u64, u64)>,
+    // Free-form user notes. Not considered when matching jobs.
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+    // Content id derived from kind, id and the start of the first run.
+    // Assigned once and, unlike JobCtx::uid, stays the same across
+    // reloads, merges and incremental runs so it can be referenced from
+    // outside.
+    #[serde(default)]
+    pub cid: String,
+}
+
+/// Key of the record which --raw-reports attaches the base64-encoded
+/// gzipped JSON array of (timestamp, report) pairs over the period to.
+pub const RAW_REPORTS_KEY: &str = "raw_reports";
+
+/// Per-second reports attached to `record` with --raw-reports, oldest
+/// first. Empty if they weren't captured.
+pub fn raw_reports(record: &serde_json::Value) -> Result<Vec<(u64, rd_agent_intf::Report)>> {
+    let b64 = match record.get(RAW_REPORTS_KEY).and_then(|v| v.as_str()) {
+        Some(v) => v,
+        None => return Ok(vec![]),
+    };
+    let buf = base64_decode(b64).context("Decoding raw reports")?;
+    let dec = libflate::gzip::Decoder::new(&buf[..]).context("Decompressing raw reports")?;
+    Ok(serde_json::from_reader(dec).context("Parsing raw reports")?)
 }
 
 // This part gets stored in the result file.
@@ -70,6 +418,33 @@ impl JobData {
             sysinfo: Default::default(),
             record: None,
             result: None,
+            studied_from: None,
+            annotations: Default::default(),
+            cid: String::new(),
+        }
+    }
+
+    fn encode_raw_reports(reports: &[(u64, rd_agent_intf::Report)]) -> Result<String> {
+        let mut enc = libflate::gzip::Encoder::new(Vec::new())?;
+        serde_json::to_writer(&mut enc, reports)?;
+        Ok(base64_encode(&enc.finish().into_result()?))
+    }
+
+    pub fn content_id(&self) -> String {
+        let key = format!(
+            "{}\0{}\0{}",
+            &self.spec.kind,
+            self.spec.id.as_deref().unwrap_or(""),
+            self.period.0,
+        );
+        format!("{:016x}", fnv1a64(key.as_bytes()))
+    }
+
+    /// Assign the content id if not already assigned. Must be called after
+    /// period.0 is set.
+    pub fn init_cid(&mut self) {
+        if self.cid.len() == 0 {
+            self.cid = self.content_id();
         }
     }
 
@@ -142,13 +517,25 @@ impl JobCtx {
 
     pub fn parse_job_spec(&mut self, prev_data: Option<&JobData>) -> Result<()> {
         let spec = &self.data.spec;
+        // Specs loaded from result files skip the command line checks and
+        // may predate them. Don't refuse to load them.
+        if let Some(id) = spec.id.as_ref() {
+            if let Err(e) = JobSpec::validate_id(id) {
+                warn!("job: Loading {} with an invalid id ({:#})", spec, &e);
+            }
+        }
         let bench = super::bench::find_bench(&spec.kind)?;
         let desc = bench.desc();
         if !desc.takes_run_props && spec.props[0].len() > 0 {
             bail!("unknown properties");
         }
         if !desc.takes_run_propsets && spec.props.len() > 1 {
-            bail!("multiple property sets not supported");
+            bail!(
+                "{} takes at most one property set but {} were specified, \
+                 remove the extra ':'-separated groups",
+                &spec.kind,
+                spec.props.len()
+            );
         }
         self.incremental = desc.incremental;
 
@@ -190,6 +577,99 @@ impl JobCtx {
             si.swappiness = rep.swappiness;
         }
         si.mem = rctx.mem_info().clone();
+        si.agent_args = rctx.agent_args();
+        let (nice, ionice) = rctx.self_prio();
+        si.self_nice = nice;
+        si.self_ionice = ionice;
+        si.cpu_governor_orig = rctx.cpu_governor_orig();
+    }
+
+    fn slice_io_deltas(rctx: &RunCtx, period: (u64, u64)) -> BTreeMap<String, IoStatReport> {
+        let (first, last) = match rctx.first_last_reports(period) {
+            Some(v) => v,
+            None => return Default::default(),
+        };
+        last.slice_io
+            .iter()
+            .map(|(slice, end)| {
+                let delta = match first.slice_io.get(slice) {
+                    Some(start) => end.delta(start),
+                    None => end.clone(),
+                };
+                (slice.clone(), delta)
+            })
+            .collect()
+    }
+
+    fn slice_psi_pressures(rctx: &RunCtx, period: (u64, u64)) -> BTreeMap<String, PsiReport> {
+        let (first, last) = match rctx.first_last_reports(period) {
+            Some(v) => v,
+            None => return Default::default(),
+        };
+        let dur = (last.timestamp - first.timestamp).num_milliseconds() as f64 / 1000.0;
+        last.psi
+            .iter()
+            .filter_map(|(slice, end)| {
+                first
+                    .psi
+                    .get(slice)
+                    .map(|start| (slice.clone(), end.pressure_since(start, dur)))
+            })
+            .collect()
+    }
+
+    fn slice_fix_deltas(rctx: &RunCtx, period: (u64, u64)) -> BTreeMap<String, u64> {
+        let (first, last) = match rctx.first_last_reports(period) {
+            Some(v) => v,
+            None => return Default::default(),
+        };
+        last.resctl
+            .fixes
+            .iter()
+            .filter_map(|(key, end)| {
+                // The counts restart if the agent was restarted in between.
+                let start = match first.resctl.fixes.get(key) {
+                    Some(start) if start <= end && first.seq == last.seq => *start,
+                    _ => 0,
+                };
+                match end - start {
+                    0 => None,
+                    delta => Some((key.clone(), delta)),
+                }
+            })
+            .collect()
+    }
+
+    fn work_mem_high_events(rctx: &RunCtx, period: (u64, u64)) -> u64 {
+        let (first, last) = match rctx.first_last_reports(period) {
+            Some(v) => v,
+            None => return 0,
+        };
+        let high = |rep: &rd_agent_intf::Report| {
+            rep.mem_events
+                .get(Slice::Work.name())
+                .and_then(|ev| ev.get("high"))
+                .cloned()
+        };
+        match (high(&first), high(&last)) {
+            (Some(start), Some(end)) => (end - start).max(0.0) as u64,
+            _ => 0,
+        }
+    }
+
+    fn cpu_utils(rctx: &RunCtx, period: (u64, u64)) -> Vec<f64> {
+        let (first, last) = match rctx.first_last_reports(period) {
+            Some(v) => v,
+            None => return vec![],
+        };
+        if first.cpu_stat.len() != last.cpu_stat.len() {
+            return vec![];
+        }
+        last.cpu_stat
+            .iter()
+            .zip(first.cpu_stat.iter())
+            .map(|(end, start)| end.util_since(start))
+            .collect()
     }
 
     pub fn run(&mut self, rctx: &mut RunCtx) -> Result<()> {
@@ -206,6 +686,7 @@ impl JobCtx {
                 "--study specified but {} isn't complete",
                 &self.data.spec
             ))?;
+            self.data.studied_from = Some(self.data.period);
         } else {
             let job = self.job.as_mut().unwrap();
             let data = &mut self.data;
@@ -216,10 +697,36 @@ impl JobCtx {
             if self.incremental {
                 if let Some(pdata) = pdata.as_ref() {
                     data.period.0 = pdata.period.0.min(data.period.0);
+                    data.cid = pdata.cid.clone();
                 }
             }
-            let record = job.run(rctx)?;
+            let rapl = match rctx.rapl() {
+                true => match RaplSampler::start() {
+                    Ok(v) => Some(v),
+                    Err(e) => {
+                        warn!("job: RAPL energy measurement unavailable ({:#})", &e);
+                        None
+                    }
+                },
+                false => None,
+            };
+            // Only the bench itself is subject to --fail-on-warn.
+            let capture = match rctx.fail_on_warn() {
+                true => Some(LogCapture::start()),
+                false => None,
+            };
+            let mut record = job.run(rctx)?;
+            let warnings = capture.map(|c| c.finish()).unwrap_or_default();
+            let warmup = match (rctx.warmed_up(), self.incremental && pdata.is_some()) {
+                (Some((at, dur)), false) => {
+                    data.period.0 = data.period.0.max(at);
+                    Some(dur)
+                }
+                _ => None,
+            };
             data.period.1 = unix_now();
+            let energy = rapl.map(|rapl| rapl.stop());
+            data.init_cid();
 
             if rctx.sysreqs_report().is_some() {
                 Self::fill_sysinfo_from_rctx(&mut data.sysinfo, rctx);
@@ -240,14 +747,63 @@ impl JobCtx {
                     "job: No sysreqs available for {:?} after completion, cycling rd_agent...",
                     &data.spec
                 );
-                rctx.start_agent(vec![])?;
+                if let Err(e) = rctx.start_agent(vec![]) {
+                    // Don't lose the record of the run which already happened.
+                    data.record = Some(record);
+                    data.studied_from = None;
+                    if let Err(e) = rctx.update_incremental_jctx(&self) {
+                        error!("job: Failed to save the record ({:#})", &e);
+                    }
+                    return Err(e);
+                }
                 rctx.stop_agent();
                 Self::fill_sysinfo_from_rctx(&mut data.sysinfo, rctx);
             }
 
+            if let Some((joules, dur)) = energy {
+                data.sysinfo.energy = Some(joules);
+                data.sysinfo.energy_dur = dur;
+            }
+            data.sysinfo.warmup = warmup;
+            data.sysinfo.swap = SwapStat::from_reports(rctx, data.period);
+            data.sysinfo.slice_io = Self::slice_io_deltas(rctx, data.period);
+            data.sysinfo.psi = Self::slice_psi_pressures(rctx, data.period);
+            data.sysinfo.cpu_util = Self::cpu_utils(rctx, data.period);
+            data.sysinfo.faults = FaultStat::from_reports(rctx, data.period);
+            data.sysinfo.work_mem_high_events = Self::work_mem_high_events(rctx, data.period);
+            data.sysinfo.slice_fixes = Self::slice_fix_deltas(rctx, data.period);
+            if rctx.raw_reports() {
+                let reports: Vec<(u64, rd_agent_intf::Report)> = rctx
+                    .report_iter(data.period)
+                    .filter_map(|(rep, at)| rep.ok().map(|rep| (at, rep)))
+                    .collect();
+                match (
+                    JobData::encode_raw_reports(&reports),
+                    record.as_object_mut(),
+                ) {
+                    (Ok(b64), Some(obj)) => {
+                        obj.insert(RAW_REPORTS_KEY.into(), serde_json::Value::String(b64));
+                    }
+                    (Ok(_), None) => warn!("job: Can't attach raw reports to a non-map record"),
+                    (Err(e), _) => warn!("job: Failed to capture raw reports ({:#})", &e),
+                }
+            }
             data.record = Some(record);
+            data.studied_from = None;
+
+            // Keep the record around for diagnosis but fail the job.
+            if warnings.len() > 0 {
+                rctx.update_incremental_jctx(&self)?;
+                bail!(
+                    "--fail-on-warn: {} warnings or errors logged while running:\n  {}",
+                    warnings.len(),
+                    warnings.join("\n  ")
+                );
+            }
         }
 
+        self.data.annotations.extend(rctx.annotations().clone());
+
         let res = match self
             .job
             .as_ref()
@@ -263,12 +819,21 @@ impl JobCtx {
 
         // We still wanna save what came out of the run phase even if the
         // study phase failed.
-        rctx.update_incremental_jctx(&self);
+        rctx.update_incremental_jctx(&self)?;
 
         res
     }
 
     pub fn format(&self, opts: &FormatOpts, props: &JobProps) -> Result<String> {
+        match opts.format_version {
+            1 => self.format_v1(opts, props),
+            _ => self.format_v2(opts, props),
+        }
+    }
+
+    // Format version 1, the layout before per-run system stats were added.
+    // Kept verbatim so that old text reports can be diffed against.
+    fn format_v1(&self, opts: &FormatOpts, props: &JobProps) -> Result<String> {
         let mut buf = String::new();
         let data = &self.data;
         write!(buf, "[{} result] ", data.spec.kind).unwrap();
@@ -395,15 +960,483 @@ impl JobCtx {
         Ok(buf)
     }
 
+    fn format_v2(&self, opts: &FormatOpts, props: &JobProps) -> Result<String> {
+        let mut buf = String::new();
+        let data = &self.data;
+        write!(buf, "[{} result] ", data.spec.kind).unwrap();
+        if let Some(id) = data.spec.id.as_ref() {
+            write!(buf, "\"{}\" ", id).unwrap();
+        }
+        writeln!(
+            buf,
+            "{} - {}\n",
+            DateTime::<Local>::from(UNIX_EPOCH + Duration::from_secs(data.period.0))
+                .format("%Y-%m-%d %T"),
+            DateTime::<Local>::from(UNIX_EPOCH + Duration::from_secs(data.period.1)).format("%T")
+        )
+        .unwrap();
+
+        if let Some(job) = self.job.as_ref() {
+            let desc = job.describe();
+            if desc.len() > 0 {
+                writeln!(buf, "{}\n", desc).unwrap();
+            }
+        }
+
+        if let Some(per) = data.studied_from {
+            writeln!(
+                buf,
+                "Studied from data recorded during {}, not a new measurement\n",
+                format_period(per)
+            )
+            .unwrap();
+        }
+
+        if let Some(warmup) = data.sysinfo.warmup {
+            writeln!(
+                buf,
+                "Excludes {} of hashd bench warmup at the start\n",
+                format_duration(warmup)
+            )
+            .unwrap();
+        }
+
+        let raw_b64 = data
+            .record
+            .as_ref()
+            .and_then(|rec| rec.get(RAW_REPORTS_KEY))
+            .and_then(|v| v.as_str());
+        if let Some(b64) = raw_b64 {
+            match raw_reports(data.record.as_ref().unwrap()) {
+                Ok(reports) => writeln!(
+                    buf,
+                    "Raw reports: {} reports ({} compressed)\n",
+                    reports.len(),
+                    opts.format_size(b64.len() * 3 / 4)
+                )
+                .unwrap(),
+                Err(e) => writeln!(buf, "Raw reports: corrupt ({:#})\n", &e).unwrap(),
+            }
+        }
+
+        if data.annotations.len() > 0 {
+            writeln!(buf, "Annotations:").unwrap();
+            for (k, v) in data.annotations.iter() {
+                writeln!(buf, "  {}: {}", k, v).unwrap();
+            }
+            writeln!(buf, "").unwrap();
+        }
+
+        let si = &data.sysinfo;
+        if si.sysreqs_report.is_some() {
+            let rep = data.sysinfo.sysreqs_report.as_ref().unwrap();
+            writeln!(buf, "System info: kernel={:?}", &rep.kernel_version).unwrap();
+            writeln!(
+                buf,
+                "             nr_cpus={} memory={} swap={} swappiness={}",
+                rep.nr_cpus,
+                opts.format_size(rep.total_memory),
+                opts.format_size(rep.total_swap),
+                si.swappiness
+            )
+            .unwrap();
+            if rep.cpufreq.len() > 0 {
+                let summarize_govs = |govs: &mut dyn Iterator<Item = &String>| {
+                    let mut nrs = BTreeMap::<&str, usize>::new();
+                    for gov in govs {
+                        *nrs.entry(gov).or_insert(0) += 1;
+                    }
+                    nrs.iter()
+                        .map(|(gov, nr)| format!("{}x{}", gov, nr))
+                        .collect::<Vec<String>>()
+                        .join(",")
+                };
+                let (mut min, mut max) = (std::u64::MAX, 0);
+                for info in rep.cpufreq.values() {
+                    min = min.min(info.min_freq);
+                    max = max.max(info.max_freq);
+                }
+                writeln!(
+                    buf,
+                    "             cpufreq={} min={:.2}GHz max={:.2}GHz",
+                    summarize_govs(&mut rep.cpufreq.values().map(|info| &info.governor)),
+                    min as f64 / 1_000_000.0,
+                    max as f64 / 1_000_000.0
+                )
+                .unwrap();
+                if si.cpu_governor_orig.len() > 0 {
+                    writeln!(
+                        buf,
+                        "             cpufreq pinned, original={}",
+                        summarize_govs(&mut si.cpu_governor_orig.values())
+                    )
+                    .unwrap();
+                }
+            }
+            if si.mem.profile > 0 {
+                writeln!(
+                    buf,
+                    "             mem_profile={} (avail={} share={} target={})",
+                    si.mem.profile,
+                    opts.format_size(si.mem.avail),
+                    opts.format_size(si.mem.share),
+                    opts.format_size(si.mem.target)
+                )
+                .unwrap();
+            }
+            writeln!(buf, "").unwrap();
+
+            writeln!(
+                buf,
+                "IO info: dev={}({}:{}) model=\"{}\" size={}",
+                &rep.scr_dev,
+                rep.scr_devnr.0,
+                rep.scr_devnr.1,
+                &rep.scr_dev_model,
+                opts.format_size(rep.scr_dev_size)
+            )
+            .unwrap();
+
+            let mut devinfo = String::new();
+            if rep.scr_dev_fwrev.len() > 0 {
+                write!(devinfo, " fwrev=\"{}\"", &rep.scr_dev_fwrev).unwrap();
+            }
+            if let Some(rot) = rep.scr_dev_rotational {
+                write!(devinfo, " rotational={}", rot).unwrap();
+            }
+            if let (Some(lbs), Some(pbs)) = (rep.scr_dev_lbs, rep.scr_dev_pbs) {
+                write!(devinfo, " lbs={} pbs={}", lbs, pbs).unwrap();
+            }
+            if rep.scr_fstype.len() > 0 {
+                write!(devinfo, " fs={}", &rep.scr_fstype).unwrap();
+            }
+            if devinfo.len() > 0 {
+                writeln!(buf, "        {}", &devinfo).unwrap();
+            }
+
+            writeln!(
+                buf,
+                "         iosched={} wbt={} iocost={} other={}",
+                &rep.scr_dev_iosched,
+                match si.sysreqs_missed.contains(&SysReq::NoWbt) {
+                    true => "on",
+                    false => "off",
+                },
+                match si.iocost.qos.enable > 0 {
+                    true => "on",
+                    false => "off",
+                },
+                match si.sysreqs_missed.contains(&SysReq::NoOtherIoControllers) {
+                    true => "on",
+                    false => "off",
+                },
+            )
+            .unwrap();
+
+            let iocost = &data.sysinfo.iocost;
+            if iocost.qos.enable > 0 {
+                let model = &iocost.model;
+                let qos = &iocost.qos;
+                writeln!(
+                    buf,
+                    "         iocost model: rbps={} rseqiops={} rrandiops={}",
+                    model.knobs.rbps, model.knobs.rseqiops, model.knobs.rrandiops
+                )
+                .unwrap();
+                writeln!(
+                    buf,
+                    "                       wbps={} wseqiops={} wrandiops={}",
+                    model.knobs.wbps, model.knobs.wseqiops, model.knobs.wrandiops
+                )
+                .unwrap();
+                writeln!(
+                buf,
+                "         iocost QoS: rpct={:.2} rlat={} wpct={:.2} wlat={} min={:.2} max={:.2}",
+                qos.knobs.rpct,
+                qos.knobs.rlat,
+                qos.knobs.wpct,
+                qos.knobs.wlat,
+                qos.knobs.min,
+                qos.knobs.max
+            )
+                .unwrap();
+            }
+            writeln!(buf, "").unwrap();
+
+            if opts.full && si.psi.len() > 0 {
+                let fmt_psi = |psi: &Option<(f64, f64)>| match psi {
+                    Some((some, full)) => {
+                        format!("{:>5}/{:>5}", format_pct(*some), format_pct(*full))
+                    }
+                    None => format!("{:>5}/{:>5}", "-", "-"),
+                };
+                writeln!(buf, "PSI (some/full %, over the run period):").unwrap();
+                for (slice, psi) in si.psi.iter() {
+                    writeln!(
+                        buf,
+                        "  {:<20} cpu={} mem={} io={}",
+                        slice,
+                        fmt_psi(&psi.cpu),
+                        fmt_psi(&psi.mem),
+                        fmt_psi(&psi.io)
+                    )
+                    .unwrap();
+                }
+                writeln!(buf, "").unwrap();
+            }
+
+            if si.slice_io.len() > 0 {
+                writeln!(buf, "IO stat (scratch device, over the run period):").unwrap();
+                for (slice, st) in si.slice_io.iter() {
+                    writeln!(
+                        buf,
+                        "  {:<20} rbytes={:>5} wbytes={:>5} rios={:>8} wios={:>8}",
+                        slice,
+                        opts.format_size(st.rbytes),
+                        opts.format_size(st.wbytes),
+                        st.rios,
+                        st.wios
+                    )
+                    .unwrap();
+                }
+                writeln!(buf, "").unwrap();
+            }
+
+            if opts.full && si.cpu_util.len() > 0 {
+                let mut utils = si.cpu_util.clone();
+                utils.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                writeln!(
+                    buf,
+                    "CPU util% (min/median/max over {} CPUs): {}/{}/{}\n",
+                    utils.len(),
+                    format_pct(utils[0]),
+                    format_pct(utils[utils.len() / 2]),
+                    format_pct(utils[utils.len() - 1]),
+                )
+                .unwrap();
+            }
+
+            if opts.full {
+                let faults = &si.faults;
+                let mut slices = String::new();
+                for (slice, nr) in faults.slice_majfaults.iter() {
+                    write!(slices, " {}={}", slice, nr).unwrap();
+                }
+                writeln!(
+                    buf,
+                    "Faults: ctxsw-all={} majfault={}{}\n",
+                    faults.nr_ctxsw_all, faults.nr_majfaults, &slices
+                )
+                .unwrap();
+            }
+
+            if opts.full && si.slice_fixes.len() > 0 {
+                writeln!(
+                    buf,
+                    "Drift: rd-agent corrected {} cgroup values over the run period",
+                    si.slice_fixes.values().sum::<u64>()
+                )
+                .unwrap();
+                for (key, nr) in si.slice_fixes.iter() {
+                    writeln!(buf, "  {:<40} {:>6}", key, nr).unwrap();
+                }
+                writeln!(buf, "").unwrap();
+            }
+
+            if let Some(energy) = si.energy {
+                self.format_energy(&mut buf, energy, si.energy_dur);
+            }
+
+            if let (true, Some(swap)) = (opts.full, si.swap.as_ref()) {
+                writeln!(
+                    buf,
+                    "Swap (avg/peak): usage={}/{} in={}/{} out={}/{}\n",
+                    opts.format_size(swap.usage_avg),
+                    opts.format_size(swap.usage_peak),
+                    opts.format_size(swap.in_bps_avg),
+                    opts.format_size(swap.in_bps_peak),
+                    opts.format_size(swap.out_bps_avg),
+                    opts.format_size(swap.out_bps_peak)
+                )
+                .unwrap();
+            }
+
+            self.format_sysinfo_tail(&mut buf, opts);
+        }
+
+        self.job
+            .as_ref()
+            .unwrap()
+            .format(Box::new(&mut buf), data, opts, props)?;
+
+        Ok(buf)
+    }
+
+    fn format_energy(&self, buf: &mut String, energy: f64, dur: f64) {
+        if dur <= 0.0 {
+            writeln!(buf, "Energy: {:.1}J\n", energy).unwrap();
+            return;
+        }
+        let watts = energy / dur;
+        writeln!(
+            buf,
+            "Energy: {:.1}J over {} (avg {:.1}W)",
+            energy,
+            format_duration(dur),
+            watts
+        )
+        .unwrap();
+
+        // Energy per op at the measured throughput is the average power
+        // divided by the rate. Bandwidth metrics are reported per byte.
+        let rate_unit = |name: &str| {
+            for tok in name.split('_') {
+                if tok.ends_with("rps") || tok.ends_with("iops") {
+                    return Some("op");
+                } else if tok.ends_with("bps") {
+                    return Some("B");
+                }
+            }
+            None
+        };
+        let metrics = self.metrics().unwrap_or_default();
+        for (name, metric) in metrics.iter() {
+            let per = match rate_unit(name) {
+                Some(v) if metric.val > 0.0 => v,
+                _ => continue,
+            };
+            let per_op = watts / metric.val;
+            let (val, unit) = match per_op {
+                v if v >= 1.0 => (v, "J"),
+                v if v >= 1e-3 => (v * 1e3, "mJ"),
+                v if v >= 1e-6 => (v * 1e6, "uJ"),
+                v => (v * 1e9, "nJ"),
+            };
+            writeln!(buf, "  {:<20} {:.3}{}/{}", name, val, unit, per).unwrap();
+        }
+        writeln!(buf, "").unwrap();
+    }
+
+    // Warnings and missed requirements at the end of the system info.
+    fn format_sysinfo_tail(&self, buf: &mut String, opts: &FormatOpts) {
+        let si = &self.data.sysinfo;
+        let dur = self.data.period.1.saturating_sub(self.data.period.0) as f64;
+        let high_rate = match dur > 0.0 {
+            true => si.work_mem_high_events as f64 / dur,
+            false => 0.0,
+        };
+        if high_rate >= MEM_HIGH_EVENTS_WARN_RATE {
+            writeln!(
+                buf,
+                "WARNING: {} was throttled by memory.high {} times ({:.1}/s), \
+                 results may be skewed\n",
+                Slice::Work.name(),
+                si.work_mem_high_events,
+                high_rate
+            )
+            .unwrap();
+        }
+
+        if si.sysreqs_missed.len() > 0 {
+            writeln!(buf, "Missed requirements:").unwrap();
+            for ((sev, cat), reqs) in SysReq::classify(&si.sysreqs_missed) {
+                let first = format!("  {:<16} ", format!("{:?}/{:?}:", sev, cat));
+                let names = reqs
+                    .iter()
+                    .map(|x| format!("{:?}", x))
+                    .collect::<Vec<String>>();
+                writeln!(buf, "{}", opts.wrap_list(&first, &names, first.len())).unwrap();
+            }
+            writeln!(buf, "").unwrap();
+        }
+    }
+
+    pub fn metrics(&self) -> Result<BTreeMap<String, Metric>> {
+        self.job.as_ref().unwrap().metrics(&self.data)
+    }
+
     pub fn print(&self, opts: &FormatOpts, props: &JobProps) -> Result<()> {
         // Format only the completed jobs.
         if self.data.result.is_some() {
-            println!("{}\n\n{}", "=".repeat(90), &self.format(opts, props)?);
+            println!(
+                "{}\n\n{}",
+                "=".repeat(opts.width()),
+                &self.format(opts, props)?
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Compact per-invocation job outcome summary for CI consumption.
+#[derive(Serialize, Debug, Default)]
+pub struct ExitSummary {
+    pub nr_jobs: usize,
+    pub nr_ran: usize,
+    pub nr_passed: usize,
+    pub nr_failed: usize,
+    pub nr_skipped: usize,
+    pub failures: Vec<ExitFailure>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExitFailure {
+    pub spec: String,
+    pub error: String,
+}
+
+impl ExitSummary {
+    pub fn new(nr_jobs: usize) -> Self {
+        Self {
+            nr_jobs,
+            nr_skipped: nr_jobs,
+            ..Default::default()
+        }
+    }
+
+    pub fn record(&mut self, spec: &JobSpec, res: &Result<()>) {
+        self.nr_ran += 1;
+        self.nr_skipped -= 1;
+        match res {
+            Ok(()) => self.nr_passed += 1,
+            Err(e) => {
+                self.nr_failed += 1;
+                self.failures.push(ExitFailure {
+                    spec: format!("{}", spec),
+                    error: format!("{:#}", e),
+                });
+            }
+        }
+    }
+
+    /// Write to `path`, or stdout if `path` is "-".
+    pub fn save(&self, path: &str) -> Result<()> {
+        let out = serde_json::to_string_pretty(self)? + "\n";
+        if path == "-" {
+            print!("{}", &out);
+        } else {
+            fs::write(path, out)?;
         }
         Ok(())
     }
 }
 
+/// Exclusive lock on a result file, see JobCtxs::lock_results(). The lock
+/// file is removed on release.
+pub struct ResultsLock {
+    path: String,
+    _file: fs::File,
+}
+
+impl Drop for ResultsLock {
+    fn drop(&mut self) {
+        // Unlink while still holding the lock. Waiters notice and retry.
+        if let Err(e) = fs::remove_file(&self.path) {
+            debug!("job: Failed to remove {:?} ({})", &self.path, &e);
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct JobCtxs {
     pub vec: Vec<JobCtx>,
@@ -419,6 +1452,10 @@ impl JobCtxs {
         None
     }
 
+    pub fn by_cid<'a>(&'a self, cid: &str) -> Option<&'a JobCtx> {
+        self.vec.iter().find(|jctx| jctx.data.cid == cid)
+    }
+
     pub fn by_uid_mut<'a>(&'a mut self, uid: u64) -> Option<&'a mut JobCtx> {
         for jctx in self.vec.iter_mut() {
             if jctx.uid == uid {
@@ -488,15 +1525,118 @@ impl JobCtxs {
         }
     }
 
-    pub fn load_results(path: &str) -> Result<Self> {
+    /// Merge the jobs in `other` in. Jobs are matched by kind and id and
+    /// the one which finished later wins. Merged jobs are assigned new uids.
+    /// Returns the numbers of added and replaced jobs.
+    pub fn merge(&mut self, other: JobCtxs) -> (usize, usize) {
+        let (mut nr_added, mut nr_replaced) = (0, 0);
+        for mut jctx in other.vec.into_iter() {
+            if let Some(cur) = self.by_cid(&jctx.data.cid) {
+                if cur.data.period == jctx.data.period {
+                    debug!("merge: Skipping identical {}", &jctx.data.spec);
+                    continue;
+                }
+            }
+            jctx.uid = JobCtx::new_uid();
+            jctx.update_seq = std::u64::MAX;
+            match self.find_matching_jctx_idx(&jctx.data.spec) {
+                Some(idx) => {
+                    if jctx.data.period.1 > self.vec[idx].data.period.1 {
+                        debug!("merge: Replacing {} with newer result", &jctx.data.spec);
+                        self.vec[idx] = jctx;
+                        nr_replaced += 1;
+                    } else {
+                        debug!("merge: Keeping existing {}", &jctx.data.spec);
+                    }
+                }
+                None => {
+                    self.vec.push(jctx);
+                    nr_added += 1;
+                }
+            }
+        }
+        (nr_added, nr_replaced)
+    }
+
+    /// Result files may be shared between resctl-bench instances. An
+    /// instance which updates the result file should hold this lock from
+    /// loading the file until it's done saving so that concurrent instances
+    /// block on each other rather than overwrite each other's jobs. Readers
+    /// don't need it as save_results() replaces the file atomically.
+    pub fn lock_results(path: &str) -> Result<ResultsLock> {
+        let lock_path = format!("{}.lock", path);
+        loop {
+            let file = fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .open(&lock_path)
+                .with_context(|| format!("Opening {:?}", &lock_path))?;
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } < 0 {
+                bail!(
+                    "Failed to lock {:?} ({})",
+                    &lock_path,
+                    std::io::Error::last_os_error()
+                );
+            }
+            // The previous holder unlinks the lock file on release. Retry
+            // if we ended up locking an unlinked one.
+            let held = file.metadata()?;
+            match fs::metadata(&lock_path) {
+                Ok(cur) if cur.dev() == held.dev() && cur.ino() == held.ino() => {
+                    return Ok(ResultsLock {
+                        path: lock_path,
+                        _file: file,
+                    });
+                }
+                _ => debug!("job: {:?} was removed while locking, retrying", &lock_path),
+            }
+        }
+    }
+
+    fn read_results(path: &str) -> Result<Vec<JobCtx>> {
         let mut f = fs::OpenOptions::new().read(true).open(path)?;
+        if Self::is_msgpack(path) {
+            let vec: Vec<JobCtx> = rmp_serde::from_read(std::io::BufReader::new(f))?;
+            return Ok(Self::reset_loaded(vec));
+        }
         let mut buf = String::new();
         f.read_to_string(&mut buf)?;
 
-        let mut vec: Vec<JobCtx> = serde_json::from_str(&buf)?;
+        // Result files are always written as strict JSON. If parsing fails,
+        // retry with comments and trailing commas stripped so that
+        // hand-curated baselines can be annotated.
+        let vec: Vec<JobCtx> = match serde_json::from_str(&buf) {
+            Ok(v) => v,
+            Err(e) => match serde_json::from_str(&strip_json_comments(&buf)) {
+                Ok(v) => {
+                    debug!("job: Parsed {:?} after stripping comments", path);
+                    v
+                }
+                Err(_) => return Err(e.into()),
+            },
+        };
+        Ok(Self::reset_loaded(vec))
+    }
+
+    fn reset_loaded(mut vec: Vec<JobCtx>) -> Vec<JobCtx> {
         for jctx in vec.iter_mut() {
             jctx.uid = JobCtx::new_uid();
             jctx.update_seq = std::u64::MAX;
+            // Results from before cids were recorded.
+            jctx.data.init_cid();
+        }
+        vec
+    }
+
+    /// Result files ending in `.msgpack` are stored as MessagePack instead
+    /// of JSON. Handy for long-running jobs with large records.
+    fn is_msgpack(path: &str) -> bool {
+        path.ends_with(".msgpack")
+    }
+
+    pub fn load_results(path: &str) -> Result<Self> {
+        let mut vec = Self::read_results(path)?;
+        for jctx in vec.iter_mut() {
             if let Err(e) = jctx.parse_job_spec(None) {
                 bail!("Failed to parse {} ({:#})", &jctx.data.spec, &e);
             }
@@ -505,17 +1645,44 @@ impl JobCtxs {
         Ok(Self { vec })
     }
 
-    pub fn save_results(&self, path: &str) {
-        let serialized =
-            serde_json::to_string_pretty(&self.vec).expect("Failed to serialize output");
+    /// Parse each entry of the result file without failing on the first
+    /// error. Returns the per-job spec and parse result.
+    pub fn validate_results(path: &str) -> Result<Vec<(JobSpec, Result<()>)>> {
+        Ok(Self::read_results(path)?
+            .into_iter()
+            .map(|mut jctx| {
+                let res = jctx.parse_job_spec(None);
+                (jctx.data.spec, res)
+            })
+            .collect())
+    }
+
+    /// The results are written to a temporary file which is then renamed
+    /// over `path` so that a crash mid-write can't destroy the previous
+    /// results. See lock_results() for concurrent instances.
+    pub fn save_results(&self, path: &str) -> Result<()> {
+        let serialized: Vec<u8> = if Self::is_msgpack(path) {
+            rmp_serde::to_vec_named(&self.vec).context("Serializing results")?
+        } else {
+            serde_json::to_string_pretty(&self.vec)
+                .context("Serializing results")?
+                .into()
+        };
+
+        let tmp_path = format!("{}.tmp.{}", path, std::process::id());
         let mut f = fs::OpenOptions::new()
             .write(true)
             .create(true)
             .truncate(true)
-            .open(path)
-            .expect("Failed to open output file");
+            .open(&tmp_path)
+            .with_context(|| format!("Opening {:?}", &tmp_path))?;
         f.write_all(serialized.as_ref())
-            .expect("Failed to write output file");
+            .with_context(|| format!("Writing {:?}", &tmp_path))?;
+        f.sync_all()
+            .with_context(|| format!("Syncing {:?}", &tmp_path))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Renaming {:?} to {:?}", &tmp_path, path))?;
+        Ok(())
     }
 
     pub fn format_ids(&self) -> String {
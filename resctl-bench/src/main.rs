@@ -8,17 +8,18 @@ use std::process::{exit, Command};
 use std::sync::{Arc, Mutex};
 use util::*;
 
-use resctl_bench_intf::{Args, Mode};
+use resctl_bench_intf::{Args, JobSpec, Mode};
 
 mod base;
 mod bench;
 mod iocost;
 mod job;
 mod progress;
+mod rapl;
 mod run;
 mod study;
 
-use job::{FormatOpts, JobCtxs};
+use job::{ExitSummary, FormatOpts, JobCtxs, Metric, MetricRegression};
 use run::RunCtx;
 
 lazy_static::lazy_static! {
@@ -118,6 +119,31 @@ impl Program {
         }
     }
 
+    // Jobs which have the run phase recorded but no result because the
+    // study phase failed. If `job_specs` isn't empty, only those matching.
+    fn restudy_specs(jobs: &JobCtxs, job_specs: &Vec<JobSpec>) -> Vec<JobSpec> {
+        let mut specs = vec![];
+        for jctx in jobs.vec.iter() {
+            let spec = &jctx.data.spec;
+            if jctx.data.record.is_none() || jctx.data.result.is_some() {
+                continue;
+            }
+            if job_specs.len() > 0
+                && !job_specs
+                    .iter()
+                    .any(|js| js.kind == spec.kind && js.id == spec.id)
+            {
+                continue;
+            }
+            info!("Restudying {}", spec);
+            specs.push(spec.clone());
+        }
+        if specs.len() == 0 {
+            info!("No job to restudy");
+        }
+        specs
+    }
+
     fn do_run(&mut self) {
         let mut base = match self.args_file.data.mode {
             Mode::Study => base::Base::dummy(&self.args_file.data),
@@ -128,7 +154,11 @@ impl Program {
         let mut jobs = self.jobs.lock().unwrap();
         let mut pending = JobCtxs::default();
         let args = &self.args_file.data;
-        for spec in args.job_specs.iter() {
+        let specs = match args.restudy {
+            true => Self::restudy_specs(&jobs, &args.job_specs),
+            false => args.job_specs.clone(),
+        };
+        for spec in specs.iter() {
             match jobs.parse_job_spec_and_link(spec) {
                 Ok(new) => pending.vec.push(new),
                 Err(e) => {
@@ -155,14 +185,108 @@ impl Program {
 
         // Run the benches and print out the results.
         drop(jobs);
+        let mut summary = ExitSummary::new(pending.vec.len());
+        let mut failed = false;
+        let mut ran_uids = vec![];
         for jctx in pending.vec.into_iter() {
             let mut rctx = RunCtx::new(&args, &mut base, self.jobs.clone());
-            let name = format!("{}", &jctx.data.spec);
-            if let Err(e) = rctx.run_jctx(jctx) {
-                error!("{}: {:?}", &name, &e);
-                panic!();
+            let spec = jctx.data.spec.clone();
+            ran_uids.push(jctx.uid);
+            let res = rctx.run_jctx(jctx);
+            summary.record(&spec, &res);
+            if let Err(e) = res {
+                error!("{}: {:?}", &spec, &e);
+                failed = true;
+                break;
+            }
+        }
+
+        if let Some(path) = args.exit_summary.as_ref() {
+            if let Err(e) = summary.save(path) {
+                error!("Failed to write exit summary to {:?} ({:#})", path, &e);
+            }
+        }
+        if failed {
+            panic!();
+        }
+
+        if let Some(path) = args.baseline.as_ref() {
+            match self.check_baseline(path, &ran_uids) {
+                Ok(true) => exit(1),
+                Ok(false) => {}
+                Err(e) => {
+                    error!("Failed to compare against baseline {:?} ({:#})", path, &e);
+                    exit(1);
+                }
+            }
+        }
+    }
+
+    // Returns whether any metric regressed.
+    fn check_baseline(&self, path: &str, uids: &[u64]) -> Result<bool> {
+        let args = &self.args_file.data;
+        let mut baseline = JobCtxs::load_results(path)?;
+        let jobs = self.jobs.lock().unwrap();
+        let mut regressed = false;
+
+        for uid in uids.iter() {
+            let mut jctx = jobs.by_uid(*uid).unwrap().weak_clone();
+            jctx.parse_job_spec(None)?;
+            let spec = &jctx.data.spec;
+            let base = match baseline.pop_matching_jctx(spec) {
+                Some(v) => v,
+                None => {
+                    warn!("{}: No matching result in baseline", spec);
+                    continue;
+                }
+            };
+
+            let mut base_metrics = base.metrics()?;
+            let mut new_metrics = jctx.metrics()?;
+            let (base_mem, new_mem) = (&base.data.sysinfo.mem, &jctx.data.sysinfo.mem);
+            let mut normalized = false;
+            if base_mem.profile != new_mem.profile {
+                normalized = Metric::normalize_mofs(&mut base_metrics, base_mem) > 0;
+                Metric::normalize_mofs(&mut new_metrics, new_mem);
+                if normalized {
+                    info!(
+                        "{}: mem_profile differs ({} vs. {}), comparing normalized MOFs",
+                        spec, base_mem.profile, new_mem.profile
+                    );
+                }
             }
+
+            let regs = MetricRegression::find(
+                &base_metrics,
+                &new_metrics,
+                &args.baseline_thrs,
+                *args
+                    .baseline_thrs
+                    .get("default")
+                    .unwrap_or(&Args::DFL_BASELINE_THR),
+            );
+            for reg in regs.iter() {
+                let norm = match normalized && base_metrics[&reg.name].mof {
+                    true => " (normalized)",
+                    false => "",
+                };
+                error!(
+                    "{}: {}{} regressed by {}% ({} -> {}, threshold {}%)",
+                    spec,
+                    &reg.name,
+                    norm,
+                    format_pct(reg.change),
+                    reg.base,
+                    reg.new,
+                    format_pct(reg.thr)
+                );
+            }
+            if regs.len() == 0 {
+                info!("{}: No regression against baseline", spec);
+            }
+            regressed |= regs.len() > 0;
         }
+        Ok(regressed)
     }
 
     fn do_format(&mut self, opts: &FormatOpts) {
@@ -194,8 +318,9 @@ impl Program {
                 }
                 if !desc.takes_format_propsets && spec.props.len() > 1 {
                     error!(
-                        "Multiple property sets not supported for formatting {}",
-                        &jctx.data.spec
+                        "{} takes at most one property set for formatting but {} were specified",
+                        &jctx.data.spec,
+                        spec.props.len()
                     );
                     exit(1);
                 }
@@ -203,6 +328,16 @@ impl Program {
             }
         }
 
+        let args = &self.args_file.data;
+        if args.since.is_some() || args.until.is_some() {
+            let since = args.since.unwrap_or(0);
+            let until = args.until.unwrap_or(std::u64::MAX);
+            to_format.retain(|(jctx, _)| {
+                let per = jctx.data.period;
+                per != (0, 0) && per.0 >= since && per.1 <= until
+            });
+        }
+
         for (jctx, props) in to_format.iter() {
             if let Err(e) = jctx.print(opts, props) {
                 error!("Failed to format {}: {:#}", &jctx.data.spec, &e);
@@ -213,6 +348,23 @@ impl Program {
         self.commit_args();
     }
 
+    fn do_merge(&mut self) -> Result<()> {
+        let args = &self.args_file.data;
+        let mut jobs = self.jobs.lock().unwrap();
+        for src in args.merge_srcs.iter() {
+            let other = JobCtxs::load_results(src)
+                .with_context(|| format!("Loading result file {:?}", src))?;
+            let nr_jobs = other.vec.len();
+            let (nr_added, nr_replaced) = jobs.merge(other);
+            info!(
+                "Merged {:?}: {} jobs, {} added, {} replaced",
+                src, nr_jobs, nr_added, nr_replaced
+            );
+        }
+        jobs.save_results(&args.result)?;
+        Ok(())
+    }
+
     fn do_pack(&mut self) -> Result<()> {
         let args = &self.args_file.data;
         let res_path = Path::new(&args.result);
@@ -310,9 +462,104 @@ impl Program {
         Ok(())
     }
 
+    fn do_doctor(&mut self) -> Result<()> {
+        let args = &self.args_file.data;
+
+        let mut cmd = Command::new(&*AGENT_BIN);
+        cmd.args(&Program::rd_agent_base_args(
+            &args.dir,
+            args.systemd_timeout,
+            args.dev.as_deref(),
+        )?)
+        .args(&["--linux-tar", "__SKIP__"])
+        .arg("--doctor");
+        if args.verbosity > 0 {
+            cmd.arg("-".to_string() + &"v".repeat(args.verbosity as usize));
+        }
+
+        let status = cmd.status()?;
+        if !status.success() {
+            bail!("cgroup enforcement self-test failed ({})", &status);
+        }
+
+        info!("cgroup enforcement self-test passed");
+        Ok(())
+    }
+
+    fn do_validate(&self) -> Result<()> {
+        let args = &self.args_file.data;
+        let results = JobCtxs::validate_results(&args.result)
+            .with_context(|| format!("Failed to read result file {:?}", &args.result))?;
+
+        let mut nr_failed = 0;
+        for (spec, res) in results.iter() {
+            match res {
+                Ok(()) => info!("{}: OK", spec),
+                Err(e) => {
+                    error!("{}: {:#}", spec, e);
+                    nr_failed += 1;
+                }
+            }
+        }
+        if nr_failed > 0 {
+            bail!("{}/{} jobs failed to parse", nr_failed, results.len());
+        }
+        info!("All {} jobs parsed successfully", results.len());
+        Ok(())
+    }
+
+    fn do_list(&self) {
+        for desc in bench::bench_descs().iter() {
+            let mut flags = vec![];
+            if desc.takes_run_propsets {
+                flags.push("run-propsets");
+            } else if desc.takes_run_props {
+                flags.push("run-props");
+            }
+            if desc.takes_format_propsets {
+                flags.push("format-propsets");
+            } else if desc.takes_format_props {
+                flags.push("format-props");
+            }
+            if desc.incremental {
+                flags.push("incremental");
+            }
+            println!("{:<16} {}", &desc.kind, &desc.about);
+            if flags.len() > 0 {
+                println!("{:<16} [{}]", "", flags.join(", "));
+            }
+        }
+    }
+
     fn main(mut self) {
         let args = &self.args_file.data;
 
+        if args.mode == Mode::List {
+            self.do_list();
+            return;
+        }
+
+        if args.validate {
+            if let Err(e) = self.do_validate() {
+                error!("{:#}", &e);
+                exit(1);
+            }
+            return;
+        }
+
+        // Modes which update the result file hold its lock until exit so
+        // that concurrent instances don't overwrite each other's jobs.
+        let _results_lock = match args.mode {
+            Mode::Run | Mode::Study | Mode::Merge => match JobCtxs::lock_results(&args.result) {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    error!("Failed to lock result file {:?} ({:#})", &args.result, &e);
+                    exit(1);
+                }
+            },
+            _ => None,
+        };
+
         // Load existing result file into job_ctxs.
         if Path::new(&args.result).exists() {
             let mut jobs = self.jobs.lock().unwrap();
@@ -332,18 +579,91 @@ impl Program {
         }
 
         let rstat = args.rstat;
+        let decimal_size = args.decimal_size;
+        let width = args.width;
+        let format_version = args.format_version.unwrap_or(FormatOpts::FORMAT_VERSION);
+        let result_hash = match args.mode {
+            Mode::Format | Mode::Summary => std::fs::read(&args.result).ok().map(|v| fnv1a64(&v)),
+            _ => None,
+        };
+        if format_version > FormatOpts::FORMAT_VERSION {
+            error!(
+                "Invalid --format-version {}, the latest is {}",
+                format_version,
+                FormatOpts::FORMAT_VERSION
+            );
+            exit(1);
+        }
         match args.mode {
             Mode::Run | Mode::Study => self.do_run(),
-            Mode::Format => self.do_format(&FormatOpts { full: true, rstat }),
+            Mode::Format => self.do_format(&FormatOpts {
+                full: true,
+                rstat,
+                decimal_size,
+                width,
+                format_version,
+                result_hash,
+            }),
             Mode::Summary => self.do_format(&FormatOpts {
                 full: false,
                 rstat: 0,
+                decimal_size,
+                width,
+                format_version,
+                result_hash,
             }),
             Mode::Pack => self.do_pack().unwrap(),
+            Mode::Doctor => {
+                if let Err(e) = self.do_doctor() {
+                    error!("{:#}", &e);
+                    exit(1);
+                }
+            }
+            Mode::Merge => {
+                if let Err(e) = self.do_merge() {
+                    error!("{:#}", &e);
+                    exit(1);
+                }
+            }
+            Mode::List => {}
         }
     }
 }
 
+// Keep resctl-bench itself from competing with the workloads it measures.
+fn set_self_prio(args: &Args) -> Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: u32 = 13;
+
+    if let Some(nice) = args.nice {
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } < 0 {
+            bail!(
+                "Failed to set nice to {} ({})",
+                nice,
+                std::io::Error::last_os_error()
+            );
+        }
+        info!("Set nice to {}", nice);
+    }
+    if let Some((class, level)) = args.ionice {
+        let prio = (class << IOPRIO_CLASS_SHIFT) | level;
+        if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, prio) } < 0 {
+            bail!(
+                "Failed to set io priority to {}:{} ({})",
+                Args::IOPRIO_CLASS_NAMES[class as usize],
+                level,
+                std::io::Error::last_os_error()
+            );
+        }
+        info!(
+            "Set io priority to {}:{}",
+            Args::IOPRIO_CLASS_NAMES[class as usize],
+            level
+        );
+    }
+    Ok(())
+}
+
 fn main() {
     setup_prog_state();
     bench::init_benchs();
@@ -359,6 +679,11 @@ fn main() {
 
     systemd::set_systemd_timeout(args_file.data.systemd_timeout);
 
+    if let Err(e) = set_self_prio(&args_file.data) {
+        error!("{:#}", &e);
+        exit(1);
+    }
+
     Program {
         args_file,
         args_updated,
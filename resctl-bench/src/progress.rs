@@ -11,6 +11,7 @@ pub struct BenchProgress {
     main_jh: Option<JoinHandle<()>>,
     term_width: usize,
     intv_cnt: u32,
+    label: Option<String>,
 }
 
 impl BenchProgress {
@@ -30,9 +31,21 @@ impl BenchProgress {
             main_jh: None,
             term_width: term_size::dimensions_stderr().unwrap_or((80, 0)).0,
             intv_cnt: 0,
+            label: None,
         }
     }
 
+    /// Describe what's being waited on. Shown in the status output and
+    /// included in wait timeout errors.
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_owned());
+        self
+    }
+
+    pub fn get_label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
     pub fn monitor_systemd_unit(mut self, unit: &str) -> Self {
         if !console::user_attended_stderr() {
             return self;
@@ -60,6 +73,15 @@ impl BenchProgress {
     }
 
     pub fn set_status(&mut self, status: &str) {
+        let labeled;
+        let status = match self.label.as_ref() {
+            Some(label) => {
+                labeled = format!("{}: {}", label, status);
+                &labeled
+            }
+            None => status,
+        };
+
         if let Some(main) = self.main.take() {
             self.main_jh = Some(spawn(move || {
                 main.join_and_clear().unwrap();
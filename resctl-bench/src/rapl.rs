@@ -0,0 +1,131 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//! CPU package energy consumption from the RAPL powercap interface.
+use anyhow::{bail, Result};
+use log::{debug, warn};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant};
+use util::*;
+
+const POWERCAP_PATH: &str = "/sys/class/powercap";
+// The counters wrap around every few tens of minutes under load. Sample
+// often enough to never miss a wraparound.
+const SAMPLE_INTV: Duration = Duration::from_secs(10);
+
+struct RaplDomain {
+    path: String,
+    max_uj: u64,
+    last_uj: u64,
+}
+
+impl RaplDomain {
+    fn read_uj(path: &str, knob: &str) -> Result<u64> {
+        Ok(read_one_line(&format!("{}/{}", path, knob))?
+            .trim()
+            .parse::<u64>()?)
+    }
+
+    fn new(path: String) -> Result<Self> {
+        let max_uj = Self::read_uj(&path, "max_energy_range_uj")?;
+        let last_uj = Self::read_uj(&path, "energy_uj")?;
+        Ok(Self {
+            path,
+            max_uj,
+            last_uj,
+        })
+    }
+
+    // Returns the energy consumed since the last sample in microjoules.
+    fn sample(&mut self) -> Result<u64> {
+        let cur = Self::read_uj(&self.path, "energy_uj")?;
+        let delta = match cur >= self.last_uj {
+            true => cur - self.last_uj,
+            false => cur + self.max_uj.saturating_sub(self.last_uj),
+        };
+        self.last_uj = cur;
+        Ok(delta)
+    }
+}
+
+/// Accumulates the energy consumed by all CPU packages.
+pub struct RaplMeter {
+    domains: Vec<RaplDomain>,
+    total_uj: u64,
+}
+
+impl RaplMeter {
+    /// Fails if RAPL isn't available. Only the top-level package domains
+    /// are used as the subdomains are included in them.
+    pub fn new() -> Result<Self> {
+        let mut domains = vec![];
+        for entry in std::fs::read_dir(POWERCAP_PATH)?.filter_map(|x| x.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            match name.strip_prefix("intel-rapl:") {
+                Some(idx) if idx.parse::<u32>().is_ok() => {}
+                _ => continue,
+            }
+            let path = format!("{}/{}", POWERCAP_PATH, &name);
+            match RaplDomain::new(path.clone()) {
+                Ok(domain) => domains.push(domain),
+                Err(e) => debug!("rapl: Failed to open {:?} ({:#})", &path, &e),
+            }
+        }
+        if domains.is_empty() {
+            bail!("no readable RAPL package domain under {:?}", POWERCAP_PATH);
+        }
+        Ok(Self {
+            domains,
+            total_uj: 0,
+        })
+    }
+
+    pub fn sample(&mut self) {
+        for domain in self.domains.iter_mut() {
+            match domain.sample() {
+                Ok(uj) => self.total_uj += uj,
+                Err(e) => warn!("rapl: Failed to read {:?} ({:#})", &domain.path, &e),
+            }
+        }
+    }
+
+    pub fn joules(&self) -> f64 {
+        self.total_uj as f64 / 1_000_000.0
+    }
+}
+
+/// Samples a RaplMeter in the background until stopped.
+pub struct RaplSampler {
+    stop_tx: Sender<()>,
+    jh: JoinHandle<RaplMeter>,
+    started_at: Instant,
+}
+
+impl RaplSampler {
+    pub fn start() -> Result<Self> {
+        let mut meter = RaplMeter::new()?;
+        let (stop_tx, stop_rx) = channel::<()>();
+        let jh = spawn(move || {
+            loop {
+                match stop_rx.recv_timeout(SAMPLE_INTV) {
+                    Err(RecvTimeoutError::Timeout) => meter.sample(),
+                    _ => break,
+                }
+            }
+            meter.sample();
+            meter
+        });
+        Ok(Self {
+            stop_tx,
+            jh,
+            started_at: Instant::now(),
+        })
+    }
+
+    /// Stop sampling and return the joules consumed and the seconds
+    /// elapsed since start().
+    pub fn stop(self) -> (f64, f64) {
+        let _ = self.stop_tx.send(());
+        let joules = self.jh.join().unwrap().joules();
+        (joules, self.started_at.elapsed().as_secs_f64())
+    }
+}
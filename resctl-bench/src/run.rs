@@ -2,13 +2,15 @@
 #![allow(dead_code)]
 use anyhow::{anyhow, bail, Context, Result};
 use log::{debug, error, info, warn};
-use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 use std::fmt::Write;
-use std::process::Command;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread::{spawn, JoinHandle};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime};
 use thiserror::Error;
 use util::*;
 
@@ -21,20 +23,52 @@ use rd_agent_intf::{
     AGENT_SVC_NAME, HASHD_A_SVC_NAME, HASHD_BENCH_SVC_NAME, HASHD_B_SVC_NAME,
     IOCOST_BENCH_SVC_NAME, SIDELOAD_SVC_PREFIX, SYSLOAD_SVC_PREFIX,
 };
-use resctl_bench_intf::{JobSpec, Mode};
+use resctl_bench_intf::{JobSpec, Mode, WatchdogSpec};
 
 const MINDER_AGENT_TIMEOUT: Duration = Duration::from_secs(120);
+const MINDER_DFL_RETRIES: u32 = 3;
+const MINDER_DFL_RETRY_BACKOFF: Duration = Duration::from_millis(250);
+const MINDER_DFL_INTV: Duration = Duration::from_secs(1);
 const CMD_TIMEOUT: Duration = Duration::from_secs(120);
 const REP_RECORD_CADENCE: u64 = 10;
 const REP_RECORD_RETENTION: usize = 3;
 const HASHD_SLOPER_SLOTS: usize = 15;
+const NR_HASHDS: usize = 2;
+const AGENT_START_TRIES: u32 = 5;
+const AGENT_START_BACKOFF: Duration = Duration::from_secs(1);
+const TESTFILES_PREP_TIMEOUT: Duration = Duration::from_secs(3600);
+const TESTFILES_PREP_STDERR_TAIL: usize = 10;
 
 static AGENT_WAS_ACTIVE: AtomicBool = AtomicBool::new(false);
 
+fn check_hashd_idx(idx: usize) -> Result<()> {
+    if idx >= NR_HASHDS {
+        bail!(
+            "hashd instance index {} out of range, only {} instances",
+            idx,
+            NR_HASHDS
+        );
+    }
+    Ok(())
+}
+
+fn hashd_svc_name(idx: usize) -> &'static str {
+    match idx {
+        0 => HASHD_A_SVC_NAME,
+        1 => HASHD_B_SVC_NAME,
+        _ => panic!("invalid hashd instance index {}", idx),
+    }
+}
+
+// Per-instance dir name under the top and scratch dirs, e.g. "hashd-A".
+fn hashd_inst_dir(idx: usize) -> String {
+    format!("hashd-{}", (b'A' + idx as u8) as char)
+}
+
 #[derive(Error, Debug)]
 pub enum RunCtxErr {
-    #[error("wait_cond didn't finish in {timeout:?}")]
-    WaitCondTimeout { timeout: Duration },
+    #[error("timeout waiting for {what} after {timeout:?}")]
+    WaitCondTimeout { what: String, timeout: Duration },
     #[error("Hashd stabilization didn't finish in {timeout:?}")]
     HashdStabilizationTimeout { timeout: Duration },
 }
@@ -45,6 +79,20 @@ pub enum MinderState {
     AgentTimeout,
     AgentNotRunning(systemd::UnitState),
     ReportTimeout,
+    WorkloadRunaway,
+}
+
+impl MinderState {
+    /// Short form written to the status file.
+    pub fn status_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::AgentTimeout => "agent-timeout",
+            Self::AgentNotRunning(_) => "agent-not-running",
+            Self::ReportTimeout => "report-timeout",
+            Self::WorkloadRunaway => "workload-runaway",
+        }
+    }
 }
 
 fn run_nested_job_spec_int(
@@ -121,6 +169,8 @@ impl Sloper {
 
 struct RunCtxInner {
     dir: String,
+    scratch: Option<String>,
+    scr_devname: String,
     systemd_timeout: f64,
     dev: Option<String>,
     linux_tar: Option<String>,
@@ -128,15 +178,21 @@ struct RunCtxInner {
     sysreqs: BTreeSet<SysReq>,
     missed_sysreqs: BTreeSet<SysReq>,
     need_linux_tar: bool,
-    prep_testfiles: bool,
+    prep_testfiles: BTreeSet<usize>,
     bypass: bool,
     passive_all: bool,
     passive_keep_crit_mem_prot: bool,
+    agent_slice: Slice,
 
     agent_files: AgentFiles,
     agent_svc: Option<TransientService>,
     minder_state: MinderState,
     minder_jh: Option<JoinHandle<()>>,
+    minder_retries: u32,
+    minder_retry_backoff: Duration,
+    minder_intv: Duration,
+    watchdog: Option<WatchdogSpec>,
+    agent_args: Vec<String>,
 
     sysreqs_rep: Option<Arc<rd_agent_intf::SysReqsReport>>,
 
@@ -145,7 +201,52 @@ struct RunCtxInner {
 }
 
 impl RunCtxInner {
-    fn start_agent_svc(&self, mut extra_args: Vec<String>) -> Result<TransientService> {
+    fn status_path(&self) -> String {
+        self.dir.clone() + "/resctl-bench-status"
+    }
+
+    // Let external watchdogs poll the minder state without dbus access.
+    // Written to a tmp file and renamed into place so that readers never
+    // see a partial or stale line.
+    fn update_status_file(&self) {
+        let path = self.status_path();
+        let tmp_path = format!("{}.tmp", &path);
+        if let Err(e) = std::fs::write(&tmp_path, format!("{}\n", self.minder_state.status_str()))
+            .and_then(|_| std::fs::rename(&tmp_path, &path))
+        {
+            warn!("minder: Failed to update {:?} ({:#})", &path, &e);
+        }
+    }
+
+    fn scratch_path(&self) -> String {
+        match self.scratch.as_ref() {
+            Some(scr) => scr.clone(),
+            None => self.dir.clone() + "/scratch",
+        }
+    }
+
+    fn verify_scratch(&self) -> Result<()> {
+        let scr = match self.scratch.as_ref() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        if !Path::new(scr).is_dir() {
+            bail!("scratch dir {:?} doesn't exist", scr);
+        }
+        let devname = path_to_devname(scr)
+            .with_context(|| format!("Resolving device for scratch dir {:?}", scr))?;
+        if devname.to_str() != Some(self.scr_devname.as_str()) {
+            bail!(
+                "scratch dir {:?} is on {:?}, not the scratch device {:?}",
+                scr,
+                &devname,
+                &self.scr_devname
+            );
+        }
+        Ok(())
+    }
+
+    fn start_agent_svc(&mut self, mut extra_args: Vec<String>) -> Result<TransientService> {
         let mut args = vec![AGENT_BIN.clone()];
         args.append(&mut Program::rd_agent_base_args(
             &self.dir,
@@ -155,6 +256,11 @@ impl RunCtxInner {
         args.push("--reset".into());
         args.push("--keep-reports".into());
 
+        if let Some(scr) = self.scratch.as_ref() {
+            args.push("--scratch".into());
+            args.push(scr.into());
+        }
+
         if self.need_linux_tar {
             if self.linux_tar.is_some() {
                 args.push("--linux-tar".into());
@@ -181,14 +287,96 @@ impl RunCtxInner {
 
         args.append(&mut extra_args);
 
+        debug!("agent: args: {:?}", &args);
+        self.agent_args = args.clone();
+
         let mut svc =
             TransientService::new_sys(AGENT_SVC_NAME.into(), args, Vec::new(), Some(0o002))?;
-        svc.set_slice(Slice::Host.name()).set_quiet();
+        svc.set_slice(self.agent_slice.name()).set_quiet();
         svc.start()?;
 
         Ok(svc)
     }
 
+    // Run rd-hashd --prepare for instance @idx. Progress is read from
+    // rd-hashd's report file and the last lines of stderr are included in
+    // the error on failure.
+    fn prep_testfiles_inst(&self, idx: usize) -> Result<()> {
+        let hashd_bin =
+            find_bin("rd-hashd", exe_dir().ok()).ok_or(anyhow!("can't find rd-hashd"))?;
+        let testfiles_path = format!("{}/{}/testfiles", self.scratch_path(), hashd_inst_dir(idx));
+        let report_path = format!("{}/{}-testfiles-prep.json", &self.dir, hashd_inst_dir(idx));
+
+        let mut child = Command::new(&hashd_bin)
+            .arg("--testfiles")
+            .arg(&testfiles_path)
+            .arg("--report")
+            .arg(&report_path)
+            .arg("--keep-cache")
+            .arg("--prepare")
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Starting {:?}", &hashd_bin))?;
+
+        let stderr = child.stderr.take().unwrap();
+        let tail_jh = spawn(move || {
+            let mut tail = VecDeque::new();
+            for line in BufReader::new(stderr).lines().filter_map(|x| x.ok()) {
+                debug!("rd-hashd: {}", &line);
+                tail.push_back(line);
+                if tail.len() > TESTFILES_PREP_STDERR_TAIL {
+                    tail.pop_front();
+                }
+            }
+            tail
+        });
+
+        let mut progress =
+            BenchProgress::new().label(&format!("Preparing {} testfiles", hashd_inst_dir(idx)));
+        let started_at = Instant::now();
+        let res = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break Ok(status),
+                Ok(None) => {}
+                Err(e) => break Err(anyhow!("Failed to wait for rd-hashd ({})", &e)),
+            }
+            if started_at.elapsed() >= TESTFILES_PREP_TIMEOUT {
+                break Err(anyhow!(
+                    "Testfiles preparation didn't finish in {:?}",
+                    TESTFILES_PREP_TIMEOUT
+                ));
+            }
+            if wait_prog_state(Duration::from_secs(1)) == ProgState::Exiting {
+                break Err(anyhow!("Program exiting"));
+            }
+            if let Ok(rep) = rd_hashd_intf::Report::load(&report_path) {
+                progress.set_status(&format!("{}% done", format_pct(rep.testfiles_progress)));
+            }
+        };
+        drop(progress);
+
+        if res.is_err() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        let tail = tail_jh.join().unwrap();
+        let _ = std::fs::remove_file(&report_path);
+
+        let status = res?;
+        if !status.success() {
+            let mut msg = format!("Failed to prepare testfiles ({})", &status);
+            if tail.len() > 0 {
+                msg += ", rd-hashd stderr:";
+                for line in tail.iter() {
+                    msg += "\n  ";
+                    msg += line;
+                }
+            }
+            bail!("{}", msg);
+        }
+        Ok(())
+    }
+
     fn start_agent(&mut self, extra_args: Vec<String>) -> Result<()> {
         if prog_exiting() {
             bail!("Program exiting");
@@ -197,25 +385,44 @@ impl RunCtxInner {
             bail!("Already running");
         }
 
+        self.verify_scratch()?;
+
         // Prepare testfiles synchronously for better progress report.
-        if self.prep_testfiles {
-            let hashd_bin =
-                find_bin("rd-hashd", exe_dir().ok()).ok_or(anyhow!("can't find rd-hashd"))?;
-            let testfiles_path = self.dir.clone() + "/scratch/hashd-A/testfiles";
-
-            let status = Command::new(&hashd_bin)
-                .arg("--testfiles")
-                .arg(testfiles_path)
-                .arg("--keep-cache")
-                .arg("--prepare")
-                .status()?;
-            if !status.success() {
-                bail!("Failed to prepare testfiles ({})", &status);
-            }
+        for idx in self.prep_testfiles.iter() {
+            self.prep_testfiles_inst(*idx)?;
         }
 
-        // Start agent.
-        let svc = self.start_agent_svc(extra_args)?;
+        // Start agent. Transient dbus failures on a busy systemd are
+        // retried with exponential backoff. A name conflict won't go away
+        // by retrying.
+        let mut backoff = AGENT_START_BACKOFF;
+        let mut tries = 0;
+        let svc = loop {
+            tries += 1;
+            match self.start_agent_svc(extra_args.clone()) {
+                Ok(svc) => break svc,
+                Err(e) => {
+                    let msg = format!("{:#}", &e);
+                    if msg.contains("org.freedesktop.systemd1.UnitExists") {
+                        bail!("{} already exists ({})", AGENT_SVC_NAME, &msg);
+                    }
+                    if tries >= AGENT_START_TRIES || prog_exiting() {
+                        bail!(
+                            "Failed to start {} after {} tries ({})",
+                            AGENT_SVC_NAME,
+                            tries,
+                            &msg
+                        );
+                    }
+                    warn!(
+                        "Failed to start {}, retrying in {:?} ({}/{}) ({})",
+                        AGENT_SVC_NAME, backoff, tries, AGENT_START_TRIES, &msg
+                    );
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                }
+            }
+        };
         self.agent_svc.replace(svc);
 
         Ok(())
@@ -253,6 +460,12 @@ pub struct RunCtx<'a, 'b> {
     args: &'a resctl_bench_intf::Args,
     extra_args: Vec<String>,
     svcs: HashSet<String>,
+    keep_agent: bool,
+    hashd_bench_warmup: Option<Duration>,
+    warmed_up: Option<(u64, f64)>,
+    cpu_governor: Option<String>,
+    cpu_governor_saved: Vec<(usize, String)>,
+    cpu_governor_orig: BTreeMap<usize, String>,
 }
 
 impl<'a, 'b> RunCtx<'a, 'b> {
@@ -261,9 +474,11 @@ impl<'a, 'b> RunCtx<'a, 'b> {
         base: &'a mut Base<'b>,
         jobs: Arc<Mutex<JobCtxs>>,
     ) -> Self {
-        Self {
+        let mut rctx = Self {
             inner: Arc::new(Mutex::new(RunCtxInner {
                 dir: args.dir.clone(),
+                scratch: None,
+                scr_devname: base.scr_devname.clone(),
                 systemd_timeout: args.systemd_timeout,
                 dev: args.dev.clone(),
                 linux_tar: args.linux_tar.clone(),
@@ -271,14 +486,20 @@ impl<'a, 'b> RunCtx<'a, 'b> {
                 sysreqs: Default::default(),
                 missed_sysreqs: Default::default(),
                 need_linux_tar: false,
-                prep_testfiles: false,
+                prep_testfiles: Default::default(),
                 bypass: false,
                 passive_all: false,
                 passive_keep_crit_mem_prot: false,
+                agent_slice: Slice::Host,
                 agent_files: AgentFiles::new(&args.dir),
                 agent_svc: None,
                 minder_state: MinderState::Ok,
                 minder_jh: None,
+                minder_retries: MINDER_DFL_RETRIES,
+                minder_retry_backoff: MINDER_DFL_RETRY_BACKOFF,
+                minder_intv: MINDER_DFL_INTV,
+                watchdog: None,
+                agent_args: vec![],
                 sysreqs_rep: None,
                 reports: VecDeque::new(),
                 report_sample: None,
@@ -296,7 +517,23 @@ impl<'a, 'b> RunCtx<'a, 'b> {
             args,
             extra_args: vec![],
             svcs: Default::default(),
+            keep_agent: false,
+            hashd_bench_warmup: None,
+            warmed_up: None,
+            cpu_governor: None,
+            cpu_governor_saved: vec![],
+            cpu_governor_orig: Default::default(),
+        };
+        if let Some(slice) = args.agent_slice {
+            rctx.set_agent_slice(slice);
+        }
+        if let Some(intv) = args.minder_intv {
+            rctx.set_minder_intv(Duration::from_secs_f64(intv));
         }
+        if let Some(spec) = args.watchdog.as_ref() {
+            rctx.set_watchdog(Some(spec.clone()));
+        }
+        rctx
     }
 
     pub fn add_sysreqs(&mut self, sysreqs: BTreeSet<SysReq>) -> &mut Self {
@@ -321,11 +558,25 @@ impl<'a, 'b> RunCtx<'a, 'b> {
         self
     }
 
+    /// Relocate the scratch root from the default $TOPDIR/scratch. The
+    /// directory must exist and be on the scratch device.
+    pub fn set_scratch(&mut self, path: &str) -> &mut Self {
+        self.inner.lock().unwrap().scratch = Some(path.to_owned());
+        self
+    }
+
     pub fn set_prep_testfiles(&mut self) -> &mut Self {
-        self.inner.lock().unwrap().prep_testfiles = true;
+        self.inner.lock().unwrap().prep_testfiles.insert(0);
         self
     }
 
+    /// Like `set_prep_testfiles` but for the hashd instance `idx`.
+    pub fn set_prep_testfiles_inst(&mut self, idx: usize) -> Result<&mut Self> {
+        check_hashd_idx(idx)?;
+        self.inner.lock().unwrap().prep_testfiles.insert(idx);
+        Ok(self)
+    }
+
     pub fn set_bypass(&mut self) -> &mut Self {
         self.inner.lock().unwrap().bypass = true;
         self
@@ -351,26 +602,188 @@ impl<'a, 'b> RunCtx<'a, 'b> {
         self
     }
 
+    /// Once the hashd bench is up, keep it running for `warmup` before
+    /// returning from `start_hashd_bench()`. The job's period starts after
+    /// the dwell so that warmup transients don't skew what's studied over it.
+    pub fn set_hashd_bench_warmup(&mut self, warmup: Duration) -> &mut Self {
+        self.hashd_bench_warmup = Some(warmup);
+        self
+    }
+
+    /// Re-verify the agent status up to `retries` times before declaring
+    /// that it's not running. The wait before each retry starts at
+    /// `backoff`, doubles on each attempt and is jittered by up to 50%.
+    pub fn set_minder_retries(&mut self, retries: u32, backoff: Duration) -> &mut Self {
+        let mut inner = self.inner.lock().unwrap();
+        inner.minder_retries = retries;
+        inner.minder_retry_backoff = backoff;
+        drop(inner);
+        self
+    }
+
+    /// Run rd-agent in `slice` instead of hostcritical.slice. The agent's
+    /// own CPU, memory and IO usage is then charged to and controlled as a
+    /// part of `slice`. Putting it in workload.slice or sideload.slice makes
+    /// it compete with what's being measured and its reports may lag under
+    /// contention. Takes effect on the next agent start.
+    pub fn set_agent_slice(&mut self, slice: Slice) -> &mut Self {
+        if slice == Slice::Work || slice == Slice::Side {
+            warn!(
+                "Running rd-agent in {}, its usage will be accounted to the workload",
+                slice.name()
+            );
+        }
+        self.inner.lock().unwrap().agent_slice = slice;
+        self
+    }
+
+    /// How often the minder checks the agent status, refreshes the agent
+    /// files and wakes up waiters. Sub-second intervals give finer-grained
+    /// captures at the cost of more overhead.
+    pub fn set_minder_intv(&mut self, intv: Duration) -> &mut Self {
+        self.inner.lock().unwrap().minder_intv = intv;
+        self
+    }
+
+    /// Abort the run if workload.slice's pids.current or memory.current
+    /// stays above the ceilings for longer than the period, e.g. when a
+    /// workload leaks processes which escape the usual limits.
+    pub fn set_watchdog(&mut self, spec: Option<WatchdogSpec>) -> &mut Self {
+        self.inner.lock().unwrap().watchdog = spec;
+        self
+    }
+
+    /// Leave rd-agent and the services it started running when the bench
+    /// is done so that the system state can be inspected.
+    pub fn set_keep_agent(&mut self) -> &mut Self {
+        self.keep_agent = true;
+        self
+    }
+
+    /// Switch all CPUs to the cpufreq `governor` while rd-agent is
+    /// running. The original governors are restored when the agent is
+    /// stopped. Ignored if cpufreq isn't available.
+    pub fn set_cpu_governor(&mut self, governor: &str) -> &mut Self {
+        self.cpu_governor = Some(governor.to_string());
+        self
+    }
+
+    fn apply_cpu_governor(&mut self) -> Result<()> {
+        let governor = match self.cpu_governor.as_ref() {
+            Some(v) if self.cpu_governor_saved.len() == 0 => v.clone(),
+            _ => return Ok(()),
+        };
+        let cpufreq = read_cpufreq();
+        if cpufreq.len() == 0 {
+            warn!(
+                "cpufreq not available, can't set governor to {:?}",
+                &governor
+            );
+            return Ok(());
+        }
+        for (cpu, orig, _, _) in cpufreq.into_iter() {
+            self.cpu_governor_orig
+                .entry(cpu)
+                .or_insert_with(|| orig.clone());
+            if orig == governor {
+                continue;
+            }
+            write_cpufreq_governor(cpu, &governor)?;
+            self.cpu_governor_saved.push((cpu, orig));
+        }
+        if self.cpu_governor_saved.len() > 0 {
+            info!(
+                "Set cpufreq governor to {:?}, originals: {:?}",
+                &governor, &self.cpu_governor_saved
+            );
+        }
+        Ok(())
+    }
+
+    /// Per-CPU governors from before set_cpu_governor() pinning took
+    /// effect. Empty if the governor wasn't pinned.
+    pub fn cpu_governor_orig(&self) -> BTreeMap<usize, String> {
+        self.cpu_governor_orig.clone()
+    }
+
+    fn restore_cpu_governor(&mut self) {
+        for (cpu, orig) in self.cpu_governor_saved.drain(..) {
+            if let Err(e) = write_cpufreq_governor(cpu, &orig) {
+                warn!("Failed to restore cpufreq governor ({:#})", &e);
+            }
+        }
+    }
+
     pub fn clear(&mut self) -> &mut Self {
         let mut inner = self.inner.lock().unwrap();
         inner.need_linux_tar = false;
-        inner.prep_testfiles = false;
+        inner.prep_testfiles.clear();
         inner.bypass = false;
         inner.passive_all = false;
         inner.passive_keep_crit_mem_prot = false;
+        inner.minder_retries = MINDER_DFL_RETRIES;
+        inner.minder_retry_backoff = MINDER_DFL_RETRY_BACKOFF;
         drop(inner);
 
         self.agent_init_fns.clear();
         self.commit_bench = false;
+        self.hashd_bench_warmup = None;
+        self.warmed_up = None;
         self.extra_args.clear();
+        self.cpu_governor = None;
         self
     }
 
+    /// When the last hashd bench warmup ended and how long it was in
+    /// seconds, see set_hashd_bench_warmup().
+    pub fn warmed_up(&self) -> Option<(u64, f64)> {
+        self.warmed_up
+    }
+
+    /// The nice level and formatted io priority resctl-bench itself runs at
+    /// if overridden.
+    pub fn self_prio(&self) -> (Option<i32>, Option<String>) {
+        (
+            self.args.nice,
+            self.args.ionice.map(|(class, level)| {
+                format!(
+                    "{}:{}",
+                    resctl_bench_intf::Args::IOPRIO_CLASS_NAMES[class as usize],
+                    level
+                )
+            }),
+        )
+    }
+
+    pub fn minder_state(&self) -> MinderState {
+        self.inner.lock().unwrap().minder_state.clone()
+    }
+
+    pub fn annotations(&self) -> &BTreeMap<String, String> {
+        &self.args.annotations
+    }
+
+    pub fn rapl(&self) -> bool {
+        self.args.rapl
+    }
+
+    pub fn fail_on_warn(&self) -> bool {
+        self.args.fail_on_warn
+    }
+
+    pub fn raw_reports(&self) -> bool {
+        self.args.raw_reports
+    }
+
+    pub fn strict_sysreqs(&self) -> Option<&BTreeSet<SysReq>> {
+        self.args.strict_sysreqs.as_ref()
+    }
+
     pub fn study_mode(&self) -> bool {
         self.args.mode == Mode::Study
     }
 
-    pub fn update_incremental_jctx(&mut self, jctx: &JobCtx) {
+    pub fn update_incremental_jctx(&mut self, jctx: &JobCtx) -> Result<()> {
         static UPDATE_SEQ: AtomicU64 = AtomicU64::new(1);
 
         let mut jobs = self.jobs.lock().unwrap();
@@ -380,30 +793,87 @@ impl<'a, 'b> RunCtx<'a, 'b> {
         if !self.study_mode() {
             jobs.sort_by_update_seq();
         }
-        jobs.save_results(self.result_path);
+        jobs.save_results(self.result_path)
     }
 
-    pub fn update_incremental_record(&mut self, record: serde_json::Value) {
+    pub fn update_incremental_record(&mut self, record: serde_json::Value) -> Result<()> {
         let mut jobs = self.jobs.lock().unwrap();
         let mut prev = jobs.by_uid_mut(self.uid).unwrap();
         if prev.data.period.0 == 0 {
             prev.data.period.0 = self.run_started_at;
         }
         prev.data.period.1 = prev.data.period.1.max(unix_now());
+        prev.data.init_cid();
         prev.data.record = Some(record);
-        jobs.save_results(self.result_path);
+        jobs.save_results(self.result_path)
+    }
+
+    fn minder_retry_wait(backoff: Duration, nr_tries: u32) -> Duration {
+        let wait = backoff * 2u32.pow(nr_tries.min(10));
+        wait.mul_f64(1.0 + rand::random::<f64>() * 0.5)
+    }
+
+    // Returns the ceilings workload.slice is currently over.
+    fn watchdog_check(spec: &WatchdogSpec, cgrp: &str) -> Vec<String> {
+        let read = |knob: &str| -> Option<u64> {
+            read_one_line(&format!("{}/{}", cgrp, knob))
+                .ok()
+                .and_then(|line| line.trim().parse::<u64>().ok())
+        };
+        let mut over = vec![];
+        if let (Some(max), Some(cur)) = (spec.pids_max, read("pids.current")) {
+            if cur > max {
+                over.push(format!("pids.current={} > {}", cur, max));
+            }
+        }
+        if let (Some(max), Some(cur)) = (spec.mem_max, read("memory.current")) {
+            if cur > max {
+                over.push(format!(
+                    "memory.current={} > {}",
+                    format_size(cur),
+                    format_size(max)
+                ));
+            }
+        }
+        over
+    }
+
+    fn watchdog_dump_diag(cgrp: &str) {
+        let entries = match std::fs::read_dir(cgrp) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("minder: watchdog: failed to list {:?} ({})", cgrp, &e);
+                return;
+            }
+        };
+        for path in entries.filter_map(|x| x.ok()).map(|x| x.path()) {
+            if !path.is_dir() {
+                continue;
+            }
+            let read = |knob: &str| read_one_line(path.join(knob)).unwrap_or("-".into());
+            error!(
+                "minder: watchdog: {:?} pids.current={} memory.current={}",
+                path.file_name().unwrap(),
+                read("pids.current").trim(),
+                read("memory.current").trim()
+            );
+        }
     }
 
     fn minder(inner: Arc<Mutex<RunCtxInner>>) {
         let mut last_status_at = SystemTime::now();
         let mut last_report_at = SystemTime::now();
-        let mut next_at = unix_now() + 1;
+        let mut runaway_since: Option<Instant> = None;
+        let intv = inner.lock().unwrap().minder_intv;
+        // Don't give up on the agent before it had a chance to be polled a
+        // few times even if the interval is long.
+        let timeout = MINDER_AGENT_TIMEOUT.max(intv * 10);
+        let mut next_at = Instant::now() + intv;
 
         'outer: loop {
-            let sleep_till = UNIX_EPOCH + Duration::from_secs(next_at);
             'sleep: loop {
-                match sleep_till.duration_since(SystemTime::now()) {
-                    Ok(dur) => {
+                match next_at.checked_duration_since(Instant::now()) {
+                    Some(dur) if dur > Duration::ZERO => {
                         if wait_prog_state(dur) == ProgState::Exiting {
                             break 'outer;
                         }
@@ -411,33 +881,41 @@ impl<'a, 'b> RunCtx<'a, 'b> {
                     _ => break 'sleep,
                 }
             }
-            next_at = unix_now() + 1;
+            next_at = Instant::now() + intv;
 
-            let mut ctx = inner.lock().unwrap();
+            let mut nr_tries = 0;
+            let mut ctx = 'status: loop {
+                let mut ctx = inner.lock().unwrap();
+                let (retries, backoff) = (ctx.minder_retries, ctx.minder_retry_backoff);
 
-            let svc = match ctx.agent_svc.as_mut() {
-                Some(v) => v,
-                None => {
-                    debug!("minder: agent_svc is None, exiting");
-                    break 'outer;
-                }
-            };
+                let svc = match ctx.agent_svc.as_mut() {
+                    Some(v) => v,
+                    None => {
+                        debug!("minder: agent_svc is None, exiting");
+                        break 'outer;
+                    }
+                };
 
-            let mut nr_tries = 3;
-            'status: loop {
                 match svc.unit.refresh() {
                     Ok(()) => {
                         last_status_at = SystemTime::now();
                         if svc.unit.state == systemd::UnitState::Running {
-                            break 'status;
+                            break 'status ctx;
                         }
 
-                        if nr_tries > 0 {
+                        if nr_tries < retries {
+                            let wait = Self::minder_retry_wait(backoff, nr_tries);
                             warn!(
-                                "minder: agent status != running ({:?}), re-verifying...",
-                                &svc.unit.state
+                                "minder: agent status != running ({:?}), re-verifying in {:.2}s...",
+                                &svc.unit.state,
+                                wait.as_secs_f64()
                             );
-                            nr_tries -= 1;
+                            nr_tries += 1;
+                            // Don't hold the lock while waiting.
+                            drop(ctx);
+                            if wait_prog_state(wait) == ProgState::Exiting {
+                                break 'outer;
+                            }
                             continue 'status;
                         }
 
@@ -446,23 +924,21 @@ impl<'a, 'b> RunCtx<'a, 'b> {
                         break 'outer;
                     }
                     Err(e) => {
-                        if SystemTime::now().duration_since(last_status_at).unwrap()
-                            <= MINDER_AGENT_TIMEOUT
-                        {
+                        if SystemTime::now().duration_since(last_status_at).unwrap() <= timeout {
                             warn!("minder: failed to refresh agent status ({:#})", &e);
-                            break 'status;
+                            break 'status ctx;
                         }
 
                         error!(
                             "minder: failed to update agent status for over {}s, giving up ({:#})",
-                            MINDER_AGENT_TIMEOUT.as_secs(),
+                            timeout.as_secs(),
                             &e
                         );
                         ctx.minder_state = MinderState::AgentTimeout;
                         break 'outer;
                     }
                 }
-            }
+            };
 
             ctx.agent_files.refresh();
             prog_kick();
@@ -473,19 +949,45 @@ impl<'a, 'b> RunCtx<'a, 'b> {
             }
 
             match SystemTime::now().duration_since(last_report_at) {
-                Ok(dur) if dur > MINDER_AGENT_TIMEOUT => {
+                Ok(dur) if dur > timeout => {
                     error!(
                         "minder: agent report is older than {}s, giving up",
-                        MINDER_AGENT_TIMEOUT.as_secs()
+                        timeout.as_secs()
                     );
                     ctx.minder_state = MinderState::ReportTimeout;
                     break 'outer;
                 }
                 _ => (),
             }
+
+            if let Some(spec) = ctx.watchdog.as_ref() {
+                let cgrp = Slice::Work.cgrp(&ctx.agent_files.args.data.cgroup_root);
+                let over = Self::watchdog_check(spec, &cgrp);
+                if over.is_empty() {
+                    runaway_since = None;
+                } else {
+                    let since = *runaway_since.get_or_insert_with(Instant::now);
+                    if since.elapsed().as_secs_f64() >= spec.period {
+                        error!(
+                            "minder: watchdog: {} over the ceiling for over {}, aborting ({})",
+                            Slice::Work.name(),
+                            format_duration(spec.period),
+                            over.join(", ")
+                        );
+                        Self::watchdog_dump_diag(&cgrp);
+                        ctx.minder_state = MinderState::WorkloadRunaway;
+                        break 'outer;
+                    }
+                }
+            }
+
+            ctx.update_status_file();
         }
 
-        inner.lock().unwrap().agent_files.refresh();
+        let mut ctx = inner.lock().unwrap();
+        ctx.update_status_file();
+        ctx.agent_files.refresh();
+        drop(ctx);
         prog_kick();
     }
 
@@ -540,6 +1042,11 @@ impl<'a, 'b> RunCtx<'a, 'b> {
             self.init_mem_profile()?;
         }
 
+        if let Err(e) = self.apply_cpu_governor() {
+            self.restore_cpu_governor();
+            return Err(e);
+        }
+
         let mut ctx = self.inner.lock().unwrap();
         ctx.minder_state = MinderState::Ok;
 
@@ -559,7 +1066,7 @@ impl<'a, 'b> RunCtx<'a, 'b> {
                 rep.timestamp.timestamp() > started_at && rep.state == RunnerState::Running
             },
             Some(CMD_TIMEOUT),
-            None,
+            Some(BenchProgress::new().label("rd-agent start-up")),
         ) {
             self.stop_agent();
             return Err(e.context("Waiting for rd-agent to report back after start-up"));
@@ -582,8 +1089,26 @@ impl<'a, 'b> RunCtx<'a, 'b> {
             );
         }
 
+        // Fail before the bench gets going rather than after.
+        let offenders: Vec<String> = match self.strict_sysreqs() {
+            Some(allow) => ctx
+                .missed_sysreqs
+                .difference(allow)
+                .map(|x| format!("{:?}", x))
+                .collect(),
+            None => vec![],
+        };
+
         drop(ctx);
 
+        if offenders.len() > 0 {
+            self.stop_agent();
+            bail!(
+                "Strict sysreqs: missed {} not in the allow list",
+                offenders.join(", ")
+            );
+        }
+
         // Configure memory profile.
         if !self.skip_mem_profile {
             let work_mem_low = self.base.workload_mem_low();
@@ -632,9 +1157,18 @@ impl<'a, 'b> RunCtx<'a, 'b> {
         Ok(())
     }
 
-    fn stop_agent_no_clear(&mut self) {
+    fn stop_agent_no_clear(&mut self, keep: bool) {
         let agent_svc = self.inner.lock().unwrap().agent_svc.take();
-        if let Some(svc) = agent_svc {
+        if let Some(mut svc) = agent_svc {
+            if keep {
+                svc.keep = true;
+                info!(
+                    "Keeping {} running, reports in {:?}, stop with \"systemctl stop {}\"",
+                    &svc.unit.name,
+                    &self.inner.lock().unwrap().agent_files.index.data.report_d,
+                    &svc.unit.name
+                );
+            }
             drop(svc);
         }
 
@@ -645,18 +1179,21 @@ impl<'a, 'b> RunCtx<'a, 'b> {
             jh.join().unwrap();
         }
 
-        for svc in self.svcs.iter() {
-            Self::stop_svc(svc);
+        if !keep {
+            for svc in self.svcs.iter() {
+                Self::stop_svc(svc);
+            }
         }
     }
 
     pub fn stop_agent(&mut self) {
-        self.stop_agent_no_clear();
+        self.stop_agent_no_clear(self.keep_agent);
+        self.restore_cpu_governor();
         self.clear();
     }
 
     pub fn restart_agent(&mut self) -> Result<()> {
-        self.stop_agent_no_clear();
+        self.stop_agent_no_clear(false);
         self.start_agent(self.extra_args.clone())
             .context("Restarting agent...")
     }
@@ -696,7 +1233,13 @@ impl<'a, 'b> RunCtx<'a, 'b> {
 
             let dur = match expires.duration_since(SystemTime::now()) {
                 Ok(v) => v,
-                _ => return Err(RunCtxErr::WaitCondTimeout { timeout }.into()),
+                _ => {
+                    let what = match progress.get_label() {
+                        Some(label) => format!("'{}'", label),
+                        None => "wait_cond".to_owned(),
+                    };
+                    return Err(RunCtxErr::WaitCondTimeout { what, timeout }.into());
+                }
             };
             if wait_prog_state(dur) == ProgState::Exiting {
                 bail!("Program exiting");
@@ -757,6 +1300,9 @@ impl<'a, 'b> RunCtx<'a, 'b> {
 
     pub const BENCH_FAKE_CPU_RPS_MAX: u32 = 2000;
 
+    // rd-agent always benchmarks with hashd-A's params and testfiles and
+    // the result applies to both instances. Failures, including the ones
+    // while waiting for the bench to start, are returned to the caller.
     pub fn start_hashd_bench(
         &mut self,
         log_bps: Option<u64>,
@@ -769,7 +1315,7 @@ impl<'a, 'b> RunCtx<'a, 'b> {
         // On consecutive runs, some memory charges can shift to
         // workload.slice causing inaccuracies. Let's start with a clean
         // state.
-        write_one_line("/proc/sys/vm/drop_caches", "3").unwrap();
+        write_one_line("/proc/sys/vm/drop_caches", "3").context("Dropping page cache")?;
 
         if self.base.mem_initialized {
             extra_args.push(format!("--total-memory={}", self.base.mem.share));
@@ -780,16 +1326,19 @@ impl<'a, 'b> RunCtx<'a, 'b> {
         }
 
         let dfl_params = rd_hashd_intf::Params::default();
-        let mut next_seq = 0;
-        self.access_agent_files(|af| {
-            next_seq = af.bench.data.hashd_seq + 1;
-            af.cmd.data = Default::default();
-            af.cmd.data.hashd[0].log_bps = log_bps.unwrap_or(dfl_params.log_bps);
-            af.cmd.data.bench_hashd_balloon_size = self.base.balloon_size_hashd_bench();
-            af.cmd.data.bench_hashd_args = extra_args;
-            af.cmd.data.bench_hashd_seq = next_seq;
-            af.cmd.save().unwrap();
-        });
+        let balloon_size = self.base.balloon_size_hashd_bench();
+        let next_seq = self
+            .access_agent_files(|af| -> Result<u64> {
+                let next_seq = af.bench.data.hashd_seq + 1;
+                af.cmd.data = Default::default();
+                af.cmd.data.hashd[0].log_bps = log_bps.unwrap_or(dfl_params.log_bps);
+                af.cmd.data.bench_hashd_balloon_size = balloon_size;
+                af.cmd.data.bench_hashd_args = extra_args;
+                af.cmd.data.bench_hashd_seq = next_seq;
+                af.cmd.save()?;
+                Ok(next_seq)
+            })
+            .context("Updating rd-agent command file for hashd bench")?;
 
         self.wait_cond(
             |af, _| {
@@ -797,9 +1346,34 @@ impl<'a, 'b> RunCtx<'a, 'b> {
                     || af.bench.data.hashd_seq >= next_seq
             },
             Some(CMD_TIMEOUT),
-            None,
+            Some(BenchProgress::new().label("hashd bench start")),
         )
-        .context("Waiting for hashd bench to start")
+        .context("Waiting for hashd bench to start")?;
+
+        if let Some(warmup) = self.hashd_bench_warmup {
+            info!(
+                "Warming up hashd bench for {}, reports will be discarded",
+                format_duration(warmup.as_secs_f64())
+            );
+            // The bench finishing early ends the warmup too.
+            if let Err(e) = self.wait_cond(
+                |af, _| af.bench.data.hashd_seq >= next_seq,
+                Some(warmup),
+                Some(
+                    BenchProgress::new()
+                        .label("hashd bench warmup")
+                        .monitor_systemd_unit(HASHD_BENCH_SVC_NAME),
+                ),
+            ) {
+                match e.downcast_ref::<RunCtxErr>() {
+                    Some(RunCtxErr::WaitCondTimeout { .. }) => {}
+                    Some(_) | None => return Err(e.context("Warming up hashd bench")),
+                }
+            }
+            self.inner.lock().unwrap().record_rep(true);
+            self.warmed_up = Some((unix_now(), warmup.as_secs_f64()));
+        }
+        Ok(())
     }
 
     pub fn stop_hashd_bench(&self) -> Result<()> {
@@ -823,18 +1397,25 @@ impl<'a, 'b> RunCtx<'a, 'b> {
     }
 
     pub fn start_hashd(&mut self, load: f64) -> Result<()> {
-        debug!("Starting hashd ({})", &HASHD_A_SVC_NAME);
-        self.svcs.insert(HASHD_A_SVC_NAME.to_owned());
+        self.start_hashd_inst(0, load)
+    }
+
+    /// Start the hashd instance `idx` (0 for hashd-A, 1 for hashd-B).
+    pub fn start_hashd_inst(&mut self, idx: usize, load: f64) -> Result<()> {
+        check_hashd_idx(idx)?;
+        let svc_name = hashd_svc_name(idx);
+        debug!("Starting hashd ({})", svc_name);
+        self.svcs.insert(svc_name.to_owned());
 
         self.access_agent_files(|af| {
             af.cmd.data.cmd_seq += 1;
-            af.cmd.data.hashd[0].active = true;
-            af.cmd.data.hashd[0].rps_target_ratio = load;
+            af.cmd.data.hashd[idx].active = true;
+            af.cmd.data.hashd[idx].rps_target_ratio = load;
             af.cmd.save().unwrap();
         });
         self.cmd_barrier().context("Waiting for hashd start ack")?;
         self.wait_cond(
-            |af, _| af.report.data.hashd[0].svc.state == SvcStateReport::Running,
+            |af, _| af.report.data.hashd[idx].svc.state == SvcStateReport::Running,
             Some(CMD_TIMEOUT),
             None,
         )
@@ -848,6 +1429,25 @@ impl<'a, 'b> RunCtx<'a, 'b> {
         mem_slope_thr: Option<(f64, f64)>,
         timeout: Option<Duration>,
     ) -> Result<()> {
+        self.stabilize_hashd_inst_with_params(
+            0,
+            target_load,
+            rps_and_err_slope_thr,
+            mem_slope_thr,
+            timeout,
+        )
+    }
+
+    pub fn stabilize_hashd_inst_with_params(
+        &self,
+        idx: usize,
+        target_load: Option<(f64, f64)>,
+        rps_and_err_slope_thr: Option<(f64, f64)>,
+        mem_slope_thr: Option<(f64, f64)>,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        check_hashd_idx(idx)?;
+        let svc_name = hashd_svc_name(idx);
         let mut rps_sloper = Sloper::new(HASHD_SLOPER_SLOTS);
         let mut mem_sloper = Sloper::new(HASHD_SLOPER_SLOTS);
         let mut last_at = 0;
@@ -864,14 +1464,14 @@ impl<'a, 'b> RunCtx<'a, 'b> {
                 }
                 last_at = ts;
 
-                if rep.hashd[0].svc.state != SvcStateReport::Running {
-                    err = Some(anyhow!("rd-hashd not running ({:?})", rep.hashd[0].svc.state));
+                if rep.hashd[idx].svc.state != SvcStateReport::Running {
+                    err = Some(anyhow!("rd-hashd not running ({:?})", rep.hashd[idx].svc.state));
                     return true;
                 }
 
-                let load = rep.hashd[0].rps / bench.hashd.rps_max as f64;
-                let rps_slopes = rps_sloper.push(rep.hashd[0].rps);
-                let mem_slopes = mem_sloper.push(match rep.usages.get(HASHD_A_SVC_NAME) {
+                let load = rep.hashd[idx].rps / bench.hashd.rps_max as f64;
+                let rps_slopes = rps_sloper.push(rep.hashd[idx].rps);
+                let mem_slopes = mem_sloper.push(match rep.usages.get(svc_name) {
                     Some (usage) => usage.mem_bytes as f64,
                     None => 0.0,
                 });
@@ -886,10 +1486,10 @@ impl<'a, 'b> RunCtx<'a, 'b> {
                 progress.set_status(&format!(
                     "load:{:>5}% lat:{:>5} rps-slp/err:{:+6.2}%/{:+6.2}% mem-sz/slp/err:{:>5}/{:+6.2}%/{:+6.2}%",
                     format_pct(load),
-                    format_duration(rep.hashd[0].lat.ctl),
+                    format_duration(rep.hashd[idx].lat.ctl),
                     rps_slope * TO_PCT,
                     rps_eslope * TO_PCT,
-                    format_size(rep.usages[HASHD_A_SVC_NAME].mem_bytes),
+                    format_size(rep.usages[svc_name].mem_bytes),
                     mem_slope * TO_PCT,
                     mem_eslope * TO_PCT,
                 ));
@@ -915,10 +1515,14 @@ impl<'a, 'b> RunCtx<'a, 'b> {
                 true
             },
             timeout,
-            Some(BenchProgress::new().monitor_systemd_unit(HASHD_A_SVC_NAME)),
+            Some(
+                BenchProgress::new()
+                    .label("hashd steady state")
+                    .monitor_systemd_unit(svc_name),
+            ),
         ) {
             match e.downcast_ref::<RunCtxErr>() {
-                Some(RunCtxErr::WaitCondTimeout { timeout }) => {
+                Some(RunCtxErr::WaitCondTimeout { timeout, .. }) => {
                     return Err(RunCtxErr::HashdStabilizationTimeout { timeout: *timeout }.into());
                 }
                 Some(_) | None => return Err(e),
@@ -933,15 +1537,21 @@ impl<'a, 'b> RunCtx<'a, 'b> {
     }
 
     pub fn stabilize_hashd(&self, target_load: Option<f64>) -> Result<()> {
+        self.stabilize_hashd_inst(0, target_load)
+    }
+
+    pub fn stabilize_hashd_inst(&self, idx: usize, target_load: Option<f64>) -> Result<()> {
         if self.test {
-            self.stabilize_hashd_with_params(
+            self.stabilize_hashd_inst_with_params(
+                idx,
                 target_load.map(|v| (v, 1.0)),
                 Some((1.0, 1.0)),
                 Some((1.0, 1.0)),
                 Some(Duration::from_secs(30)),
             )
         } else {
-            self.stabilize_hashd_with_params(
+            self.stabilize_hashd_inst_with_params(
+                idx,
                 target_load.map(|v| (v, 0.025)),
                 Some((0.0025, 0.025)),
                 Some((0.0025, 0.025)),
@@ -950,23 +1560,87 @@ impl<'a, 'b> RunCtx<'a, 'b> {
         }
     }
 
+    /// Start hashd pinned at `rps` instead of a load ratio and wait until
+    /// the reported RPS stays within `tolerance` (fraction of `rps`) for
+    /// `dwell`.
+    pub fn start_hashd_at_rps(
+        &mut self,
+        rps: u32,
+        tolerance: f64,
+        dwell: Duration,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        let rps_max = self.access_agent_files(|af| af.bench.data.hashd.rps_max);
+        if rps_max == 0 {
+            bail!("hashd rps_max unknown, hashd bench must be run first");
+        }
+        if rps == 0 || rps > rps_max {
+            bail!("target rps {} out of range (1-{})", rps, rps_max);
+        }
+
+        self.start_hashd(rps as f64 / rps_max as f64)?;
+
+        let mut last_at = 0;
+        let mut in_band_since = None;
+        self.wait_cond(
+            |af, progress| {
+                let rep = &af.report.data;
+                let ts = rep.timestamp.timestamp();
+                if ts == last_at {
+                    progress.set_status("Report stale");
+                    return false;
+                }
+                last_at = ts;
+
+                let err = (rep.hashd[0].rps - rps as f64) / rps as f64;
+                progress.set_status(&format!(
+                    "rps:{:>7.1}/{} err:{:+6.2}% lat:{:>5}",
+                    rep.hashd[0].rps,
+                    rps,
+                    err * TO_PCT,
+                    format_duration(rep.hashd[0].lat.ctl),
+                ));
+
+                if err.abs() > tolerance {
+                    in_band_since = None;
+                    return false;
+                }
+                let since = *in_band_since.get_or_insert(ts);
+                (ts - since) as u64 >= dwell.as_secs()
+            },
+            timeout,
+            Some(
+                BenchProgress::new()
+                    .label("hashd target rps")
+                    .monitor_systemd_unit(HASHD_A_SVC_NAME),
+            ),
+        )
+        .with_context(|| format!("Waiting for hashd to settle at {} rps", rps))
+    }
+
     pub fn stop_hashd(&self) -> Result<()> {
-        debug!("Stopping hashd ({})", HASHD_A_SVC_NAME);
+        self.stop_hashd_inst(0)
+    }
+
+    pub fn stop_hashd_inst(&self, idx: usize) -> Result<()> {
+        check_hashd_idx(idx)?;
+        let svc_name = hashd_svc_name(idx);
+        debug!("Stopping hashd ({})", svc_name);
 
         self.access_agent_files(|af| {
             af.cmd.data.cmd_seq += 1;
-            af.cmd.data.hashd[0].active = false;
+            af.cmd.data.hashd[idx].active = false;
             af.cmd.save().unwrap();
         });
         self.cmd_barrier().context("Waiting for hashd stop ack")?;
         self.wait_cond(
-            |af, _| af.report.data.hashd[0].svc.state != SvcStateReport::Running,
+            |af, _| af.report.data.hashd[idx].svc.state != SvcStateReport::Running,
             Some(CMD_TIMEOUT),
             None,
         )
         .context("Waiting for hashd to stop")?;
 
-        Self::stop_svc(&HASHD_A_SVC_NAME);
+        Self::stop_svc(svc_name);
         Ok(())
     }
 
@@ -1087,6 +1761,10 @@ impl<'a, 'b> RunCtx<'a, 'b> {
             &FormatOpts {
                 full: false,
                 rstat: 0,
+                decimal_size: false,
+                width: None,
+                format_version: FormatOpts::FORMAT_VERSION,
+                result_hash: None,
             },
             &vec![Default::default()],
         )
@@ -1138,7 +1816,7 @@ impl<'a, 'b> RunCtx<'a, 'b> {
         // rd-agent isn't running for this instance.
         if self.args.mem_profile.is_some() && self.base.mem.avail == 0 {
             let was_running = self.inner.lock().unwrap().agent_svc.is_some();
-            self.stop_agent_no_clear();
+            self.stop_agent_no_clear(false);
             self.base.estimate_available_memory()?;
             if was_running {
                 self.restart_agent()?;
@@ -1163,10 +1841,47 @@ impl<'a, 'b> RunCtx<'a, 'b> {
         &self.base.mem
     }
 
+    pub fn current_vrate(&self) -> Option<f64> {
+        self.access_agent_files(|af| af.current_vrate())
+    }
+
+    /// One-line summary of the latest agent report for external monitors.
+    /// Only looks at the already loaded report and is cheap enough to be
+    /// called every second.
+    pub fn status_line(&self) -> String {
+        self.access_agent_files(|af| {
+            if af.report.path.is_none() {
+                return "no-report rps=- lat99=- mempsi=- vrate=-".to_string();
+            }
+            let rep = &af.report.data;
+            let mempsi = match rep.psi.get(Slice::Work.name()).and_then(|psi| psi.mem) {
+                Some((some, _full)) => format!("{}%", format_pct(some)),
+                None => "-".to_string(),
+            };
+            let vrate = match rep.iocost.current_vrate() {
+                Some(vrate) => format!("{}%", format_pct(vrate)),
+                None => "-".to_string(),
+            };
+            format!(
+                "{:?} rps={:.1} lat99={} mempsi={} vrate={}",
+                rep.state,
+                rep.hashd[0].rps,
+                format_duration(rep.hashd[0].lat.p99),
+                mempsi,
+                vrate
+            )
+        })
+    }
+
     pub fn sysreqs_report(&self) -> Option<Arc<rd_agent_intf::SysReqsReport>> {
         self.inner.lock().unwrap().sysreqs_rep.clone()
     }
 
+    /// The full argument vector the agent was last launched with.
+    pub fn agent_args(&self) -> Vec<String> {
+        self.inner.lock().unwrap().agent_args.clone()
+    }
+
     pub fn missed_sysreqs(&self) -> BTreeSet<SysReq> {
         self.inner.lock().unwrap().missed_sysreqs.clone()
     }
@@ -1201,6 +1916,41 @@ impl<'a, 'b> RunCtx<'a, 'b> {
         ReportIter::new(&self.report_path(), period)
     }
 
+    /// Returns the (first, last) timestamps of the reports on disk.
+    fn report_range(&self) -> Option<(u64, u64)> {
+        let mut range: Option<(u64, u64)> = None;
+        for entry in std::fs::read_dir(&self.report_path())
+            .ok()?
+            .filter_map(|e| e.ok())
+        {
+            let name = entry.file_name();
+            let at = match name
+                .to_str()
+                .and_then(|x| x.strip_suffix(".json"))
+                .and_then(|x| x.parse::<u64>().ok())
+            {
+                Some(v) => v,
+                None => continue,
+            };
+            range = Some(match range {
+                Some((first, last)) => (first.min(at), last.max(at)),
+                None => (at, at),
+            });
+        }
+        range
+    }
+
+    /// Iterate the reports in the `dur` window ending at `end`, or now if
+    /// `None`, clamped to the reports which are available.
+    pub fn report_iter_last(&self, dur: Duration, end: Option<u64>) -> ReportIter {
+        let end = end.unwrap_or_else(unix_now);
+        let mut period = (end.saturating_sub(dur.as_secs()), end);
+        if let Some((first, last)) = self.report_range() {
+            period = (period.0.max(first), period.1.min(last + 1));
+        }
+        self.report_iter(period)
+    }
+
     pub fn first_report(&self, period: (u64, u64)) -> Option<(rd_agent_intf::Report, u64)> {
         let ctx = self.inner.lock().unwrap();
         for (rep, at) in ReportIter::new(&ctx.agent_files.index.data.report_d, period) {
@@ -1220,11 +1970,24 @@ impl<'a, 'b> RunCtx<'a, 'b> {
         }
         return None;
     }
+
+    /// The first and last reports in `period` for computing deltas over
+    /// it, `None` unless both are available.
+    pub fn first_last_reports(
+        &self,
+        period: (u64, u64),
+    ) -> Option<(rd_agent_intf::Report, rd_agent_intf::Report)> {
+        match (self.first_report(period), self.last_report(period)) {
+            (Some((first, _)), Some((last, _))) => Some((first, last)),
+            _ => None,
+        }
+    }
 }
 
 impl Drop for RunCtx<'_, '_> {
     fn drop(&mut self) {
         self.stop_agent();
+        let _ = std::fs::remove_file(self.inner.lock().unwrap().status_path());
     }
 }
 
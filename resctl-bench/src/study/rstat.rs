@@ -310,6 +310,7 @@ impl ResourceStat {
     ) {
         writeln!(out, "").unwrap();
         print_pcts_header(out, field_name_len, name, None);
+        let fmt_size = |v: f64| opts.format_size(v);
         for key in keys
             .iter()
             .filter(|key| if opts.rstat == 1 { !key.hidden } else { true })
@@ -320,9 +321,9 @@ impl ResourceStat {
                 &key.key,
                 rstat.get(&key.key).unwrap(),
                 if key.base10 {
-                    format_count
+                    &format_count::<f64> as &dyn Fn(f64) -> String
                 } else {
-                    format_size
+                    &fmt_size
                 },
                 None,
             );
@@ -347,10 +348,11 @@ impl ResourceStat {
         print_pcts_header(out, fn_len, name, None);
         print_pcts_line(out, fn_len, "cpu%", &self.cpu_util, format_pct, None);
         print_pcts_line(out, fn_len, "sys%", &self.cpu_sys, format_pct, None);
-        print_pcts_line(out, fn_len, "mem", &self.mem_bytes, format_size, None);
+        let fmt_size = |v: f64| opts.format_size(v);
+        print_pcts_line(out, fn_len, "mem", &self.mem_bytes, fmt_size, None);
         print_pcts_line(out, fn_len, "io%", &self.io_util, format_pct, None);
-        print_pcts_line(out, fn_len, "rbps", &self.io_bps.0, format_size, None);
-        print_pcts_line(out, fn_len, "wbps", &self.io_bps.1, format_size, None);
+        print_pcts_line(out, fn_len, "rbps", &self.io_bps.0, fmt_size, None);
+        print_pcts_line(out, fn_len, "wbps", &self.io_bps.1, fmt_size, None);
         print_pcts_line(out, fn_len, "cpu-some%", &self.psi_cpu, format_pct, None);
         print_pcts_line(out, fn_len, "mem-some%", &self.psi_mem.0, format_pct, None);
         print_pcts_line(out, fn_len, "mem-full%", &self.psi_mem.1, format_pct, None);
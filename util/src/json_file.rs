@@ -32,6 +32,75 @@ fn read_json<P: AsRef<Path>>(path: P) -> Result<(String, String)> {
     Ok((preamble, body))
 }
 
+/// Strip `//` and `/* */` comments and trailing commas from a JSON
+/// superset so that hand-edited files can be fed to serde_json. String
+/// literals are left alone and newlines are preserved so that parse
+/// errors still point at the right line.
+pub fn strip_json_comments(input: &str) -> String {
+    let chars: Vec<char> = input.chars().collect();
+    let mut out = String::with_capacity(input.len());
+    let mut in_str = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).cloned();
+
+        if in_str {
+            out.push(c);
+            if c == '\\' {
+                if let Some(n) = next {
+                    out.push(n);
+                    i += 1;
+                }
+            } else if c == '"' {
+                in_str = false;
+            }
+            i += 1;
+            continue;
+        }
+
+        match (c, next) {
+            ('"', _) => {
+                in_str = true;
+                out.push(c);
+                i += 1;
+            }
+            ('/', Some('/')) => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            ('/', Some('*')) => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    if chars[i] == '\n' {
+                        out.push('\n');
+                    }
+                    i += 1;
+                }
+                i += 2;
+            }
+            (']', _) | ('}', _) => {
+                // Drop a trailing comma before the closing bracket.
+                let trimmed = out.trim_end().len();
+                if out[..trimmed].ends_with(',') {
+                    let ws = out.split_off(trimmed);
+                    out.pop();
+                    out.push_str(&ws);
+                }
+                out.push(c);
+                i += 1;
+            }
+            _ => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
 pub trait JsonLoad
 where
     Self: DeserializeOwned,
@@ -330,3 +399,27 @@ impl JsonRawFile {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::strip_json_comments;
+
+    #[test]
+    fn test_strip_json_comments() {
+        let input = r#"{
+    // leading comment
+    "a": "http://x/*y*/", /* inline */
+    "b": [1, 2,],
+    "c": "quote\" // not a comment",
+}"#;
+        let stripped = strip_json_comments(input);
+        assert_eq!(stripped.lines().count(), input.lines().count());
+        let v: serde_json::Value = serde_json::from_str(&stripped).unwrap();
+        assert_eq!(v["a"], "http://x/*y*/");
+        assert_eq!(v["b"], serde_json::json!([1, 2]));
+        assert_eq!(v["c"], "quote\" // not a comment");
+
+        let strict = r#"{"a": [1, {"b": "c,}"}]}"#;
+        assert_eq!(strip_json_comments(strict), strict);
+    }
+}
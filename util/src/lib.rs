@@ -1,6 +1,6 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
 use anyhow::{anyhow, bail, Context, Result};
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
 use crossbeam::channel::Sender;
 use glob::glob;
 use log::{info, warn};
@@ -36,7 +36,8 @@ pub mod systemd;
 pub use iocost::{IoCostModelParams, IoCostQoSParams, IoCostSysSave};
 pub use journal_tailer::*;
 pub use json_file::{
-    JsonArgs, JsonArgsHelper, JsonConfigFile, JsonLoad, JsonRawFile, JsonReportFile, JsonSave,
+    strip_json_comments, JsonArgs, JsonArgsHelper, JsonConfigFile, JsonLoad, JsonRawFile,
+    JsonReportFile, JsonSave,
 };
 pub use storage_info::*;
 pub use systemd::TransientService;
@@ -111,6 +112,46 @@ pub fn read_swappiness() -> Result<u32> {
         .context("Parsing swappiness")?)
 }
 
+fn cpufreq_path(cpu: usize) -> String {
+    format!("/sys/devices/system/cpu/cpu{}/cpufreq", cpu)
+}
+
+/// Returns (cpu, governor, min_freq, max_freq) with the frequencies in kHz
+/// for each CPU with cpufreq support. Empty if cpufreq isn't available.
+pub fn read_cpufreq() -> Vec<(usize, String, u64, u64)> {
+    let mut infos = vec![];
+    for path in glob("/sys/devices/system/cpu/cpu[0-9]*/cpufreq")
+        .unwrap()
+        .filter_map(|x| x.ok())
+    {
+        let cpu = match path
+            .parent()
+            .and_then(|x| x.file_name())
+            .and_then(|x| scan_fmt!(&x.to_string_lossy(), "cpu{d}", usize).ok())
+        {
+            Some(v) => v,
+            None => continue,
+        };
+        let read_freq = |name: &str| -> Result<u64> {
+            Ok(read_one_line(path.join(name))?.trim().parse::<u64>()?)
+        };
+        if let (Ok(gov), Ok(min), Ok(max)) = (
+            read_one_line(path.join("scaling_governor")),
+            read_freq("scaling_min_freq"),
+            read_freq("scaling_max_freq"),
+        ) {
+            infos.push((cpu, gov.trim().to_string(), min, max));
+        }
+    }
+    infos.sort();
+    infos
+}
+
+pub fn write_cpufreq_governor(cpu: usize, governor: &str) -> Result<()> {
+    write_one_line(&(cpufreq_path(cpu) + "/scaling_governor"), governor)
+        .with_context(|| format!("Setting cpufreq governor of cpu{} to {:?}", cpu, governor))
+}
+
 pub fn override_system_configuration(
     total_memory: Option<usize>,
     total_swap: Option<usize>,
@@ -209,6 +250,56 @@ pub fn underline(content: &str) -> String {
     custom_underline(content, "-")
 }
 
+/// 64bit FNV-1a hash. Unlike `std::collections::hash_map::DefaultHasher`,
+/// the result is stable across builds and can be persisted.
+pub fn fnv1a64(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data.iter() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard padded base64, for embedding binary blobs in JSON.
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let mut buf = [0u8; 3];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let v = (buf[0] as u32) << 16 | (buf[1] as u32) << 8 | buf[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_CHARS[(v >> (18 - i * 6)) as usize & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub fn base64_decode(input: &str) -> Result<Vec<u8>> {
+    let input = input.trim_end_matches('=').as_bytes();
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    let (mut acc, mut nr_bits) = (0u32, 0);
+    for &c in input.iter() {
+        let v = match BASE64_CHARS.iter().position(|&x| x == c) {
+            Some(v) => v as u32,
+            None => bail!("invalid base64 character {:?}", c as char),
+        };
+        acc = (acc << 6) | v;
+        nr_bits += 6;
+        if nr_bits >= 8 {
+            nr_bits -= 8;
+            out.push((acc >> nr_bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
 pub fn double_underline(content: &str) -> String {
     custom_underline(content, "=")
 }
@@ -256,6 +347,38 @@ where
     format_size_internal(size, "0")
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeBase {
+    Binary,
+    Decimal,
+}
+
+/// Format `size` with the specified unit base and number of fractional
+/// digits, e.g. "1.50G" for binary or "1.61GB" for decimal.
+pub fn format_size_prec<T>(size: T, base: SizeBase, prec: usize) -> String
+where
+    T: num::ToPrimitive,
+{
+    let (unit, suffixes) = match base {
+        SizeBase::Binary => (1024.0, ["", "K", "M", "G", "T", "P", "E"]),
+        SizeBase::Decimal => (1000.0, ["", "kB", "MB", "GB", "TB", "PB", "EB"]),
+    };
+
+    let size = size.to_u64().unwrap();
+    let mut val = size as f64;
+    let mut idx = 0;
+    while val >= unit && idx < suffixes.len() - 1 {
+        val /= unit;
+        idx += 1;
+    }
+
+    if idx == 0 {
+        format!("{}", size)
+    } else {
+        format!("{:.*}{}", prec, val, suffixes[idx])
+    }
+}
+
 pub fn format_size_dashed<T>(size: T) -> String
 where
     T: num::ToPrimitive,
@@ -601,6 +724,34 @@ pub fn format_unix_time(time: u64) -> String {
         .to_string()
 }
 
+/// Parse a local date/time, e.g. "2021-03-04 12:30", "2021-03-04" or a
+/// unix timestamp, into unix time.
+pub fn parse_unix_time(input: &str) -> Result<u64> {
+    let input = input.trim();
+    if let Ok(v) = input.parse::<u64>() {
+        return Ok(v);
+    }
+
+    let mut ndt = None;
+    for fmt in &["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"] {
+        if let Ok(v) = NaiveDateTime::parse_from_str(input, fmt) {
+            ndt = Some(v);
+            break;
+        }
+    }
+    if ndt.is_none() {
+        if let Ok(v) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+            ndt = Some(v.and_hms(0, 0, 0));
+        }
+    }
+    let ndt = ndt.ok_or_else(|| anyhow!("invalid date/time {:?}", input))?;
+
+    match Local.from_local_datetime(&ndt).earliest() {
+        Some(dt) if dt.timestamp() >= 0 => Ok(dt.timestamp() as u64),
+        _ => bail!("invalid local date/time {:?}", input),
+    }
+}
+
 pub fn format_period(per: (u64, u64)) -> String {
     format!(
         "{} - {} ({}-{})",
@@ -611,9 +762,63 @@ pub fn format_period(per: (u64, u64)) -> String {
     )
 }
 
+lazy_static::lazy_static! {
+    static ref LOG_CAPTURE: Mutex<Option<Vec<String>>> = Mutex::new(None);
+}
+
+// Forwards to the actual logger while recording warn and error messages
+// if capturing is active.
+struct CapturingLogger {
+    inner: Box<dyn log::Log>,
+}
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.level() <= log::Level::Warn {
+            if let Some(lines) = LOG_CAPTURE.lock().unwrap().as_mut() {
+                lines.push(format!("{}: {}", record.level(), record.args()));
+            }
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Records warn and error log messages while alive. Recording stops when
+/// dropped, including on early returns.
+pub struct LogCapture {}
+
+impl LogCapture {
+    /// Start recording, discarding whatever was recorded so far.
+    pub fn start() -> Self {
+        LOG_CAPTURE.lock().unwrap().replace(vec![]);
+        Self {}
+    }
+
+    /// Stop recording and return the messages logged since start().
+    pub fn finish(self) -> Vec<String> {
+        LOG_CAPTURE.lock().unwrap().take().unwrap_or_default()
+    }
+}
+
+impl Drop for LogCapture {
+    fn drop(&mut self) {
+        LOG_CAPTURE.lock().unwrap().take();
+    }
+}
+
 pub fn init_logging(verbosity: u32) {
-    if std::env::var("RUST_LOG").is_ok() {
-        env_logger::init();
+    let (logger, level): (Box<dyn log::Log>, _) = if std::env::var("RUST_LOG").is_ok() {
+        let logger = env_logger::Builder::from_default_env().build();
+        let level = logger.filter();
+        (Box::new(logger), level)
     } else {
         let sl_level = match verbosity {
             0 | 1 => sl::LevelFilter::Info,
@@ -625,18 +830,20 @@ pub fn init_logging(verbosity: u32) {
             .set_location_level(sl::LevelFilter::Off)
             .set_target_level(sl::LevelFilter::Off)
             .set_thread_level(sl::LevelFilter::Off);
-        if !console::user_attended_stderr()
-            || sl::TermLogger::init(
+        let logger: Box<dyn log::Log> = match console::user_attended_stderr() {
+            true => sl::TermLogger::new(
                 sl_level,
                 lcfg.build(),
                 sl::TerminalMode::Stderr,
                 sl::ColorChoice::Auto,
-            )
-            .is_err()
-        {
-            sl::SimpleLogger::init(sl_level, lcfg.build()).unwrap();
-        }
-    }
+            ),
+            false => sl::SimpleLogger::new(sl_level, lcfg.build()),
+        };
+        (logger, sl_level)
+    };
+
+    log::set_max_level(level);
+    log::set_boxed_logger(Box::new(CapturingLogger { inner: logger })).unwrap();
 }
 
 pub fn child_reader_thread(name: String, stdout: process::ChildStdout, tx: Sender<String>) {
@@ -841,6 +1048,68 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_unix_time() {
+        use chrono::{DateTime, Local};
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let at = 1614861000;
+        let dt = DateTime::<Local>::from(UNIX_EPOCH + Duration::from_secs(at));
+        for input in &[
+            format!("{}", at),
+            dt.format("%Y-%m-%d %H:%M:%S").to_string(),
+            dt.format("%Y-%m-%d %H:%M").to_string(),
+        ] {
+            assert_eq!(super::parse_unix_time(input).unwrap(), at);
+        }
+        assert!(super::parse_unix_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_fnv1a64() {
+        assert_eq!(super::fnv1a64(b""), 0xcbf29ce484222325);
+        assert_eq!(super::fnv1a64(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(super::fnv1a64(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn test_format_size_prec() {
+        use super::SizeBase::*;
+        for (size, base, prec, expected) in &[
+            (0_u64, Binary, 1, "0"),
+            (1023, Binary, 1, "1023"),
+            (1536, Binary, 2, "1.50K"),
+            (1610612736, Binary, 1, "1.5G"),
+            (1610612736, Decimal, 2, "1.61GB"),
+            (999, Decimal, 1, "999"),
+            (1000, Decimal, 0, "1kB"),
+        ] {
+            assert_eq!(&super::format_size_prec(*size, *base, *prec), expected);
+        }
+    }
+
+    #[test]
+    fn test_base64() {
+        for pair in &[
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(super::base64_encode(pair.0.as_bytes()), pair.1);
+            assert_eq!(super::base64_decode(pair.1).unwrap(), pair.0.as_bytes());
+        }
+        let bin: Vec<u8> = (0..=255).collect();
+        assert_eq!(
+            super::base64_decode(&super::base64_encode(&bin)).unwrap(),
+            bin
+        );
+        assert!(super::base64_decode("Zm9v!").is_err());
+    }
+
     #[test]
     fn test_parse_size() {
         for pair in &[
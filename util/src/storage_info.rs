@@ -9,6 +9,7 @@ use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::io::Read;
 use std::os::linux::fs::MetadataExt;
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 
 lazy_static::lazy_static! {
@@ -35,6 +36,42 @@ pub fn path_to_mountpoint<P: AsRef<Path>>(path_in: P) -> Result<MountInfo> {
     }
 }
 
+// (statfs magic, name)
+const FS_MAGICS: &[(u64, &str)] = &[
+    (0x9123683e, "btrfs"),
+    (0x2fc12fc1, "zfs"),
+    (0xca451a4e, "bcachefs"),
+    (0xef53, "ext4"),
+    (0x58465342, "xfs"),
+    (0xf2f52010, "f2fs"),
+    (0x01021994, "tmpfs"),
+    (0x794c7630, "overlay"),
+];
+
+/// Given a path, determine the type of the containing filesystem from its
+/// statfs magic. Unknown filesystems are named by their magic in hex.
+pub fn path_to_fstype<P: AsRef<Path>>(path_in: P) -> Result<String> {
+    let path = path_in.as_ref();
+    let cpath = match std::ffi::CString::new(path.as_os_str().as_bytes()) {
+        Ok(v) => v,
+        Err(e) => bail!("Invalid path {:?} ({})", path, &e),
+    };
+    let mut sfs = std::mem::MaybeUninit::<libc::statfs>::uninit();
+    if unsafe { libc::statfs(cpath.as_ptr(), sfs.as_mut_ptr()) } < 0 {
+        bail!(
+            "Failed to statfs {:?} ({})",
+            path,
+            std::io::Error::last_os_error()
+        );
+    }
+    let magic = unsafe { sfs.assume_init() }.f_type as u64;
+
+    match FS_MAGICS.iter().find(|(m, _)| *m == magic) {
+        Some((_, name)) => Ok(name.to_string()),
+        None => Ok(format!("0x{:x}", magic)),
+    }
+}
+
 fn match_devnr<P: AsRef<Path>>(path_in: P, devnr: u64) -> bool {
     let path = path_in.as_ref();
     let mut buf = String::new();
@@ -119,15 +156,14 @@ pub fn devname_to_model_fwrev_size<D: AsRef<OsStr>>(name_in: D) -> Result<(Strin
     let mut fwrev_path = dev_path.clone();
     fwrev_path.push("device");
     fwrev_path.push("firmware_rev");
-    if fwrev_path.exists() {
-        f.read_to_string(&mut fwrev)?;
-    } else {
+    if !fwrev_path.exists() {
         fwrev_path.pop();
         fwrev_path.push("rev");
-        if fwrev_path.exists() {
-            f.read_to_string(&mut fwrev)?;
-        }
     }
+    if fwrev_path.exists() {
+        fs::File::open(&fwrev_path)?.read_to_string(&mut fwrev)?;
+    }
+    let fwrev = fwrev.trim_end().to_string();
 
     let mut size_path = dev_path.clone();
     size_path.push("size");
@@ -140,6 +176,30 @@ pub fn devname_to_model_fwrev_size<D: AsRef<OsStr>>(name_in: D) -> Result<(Strin
     Ok((model, fwrev, size))
 }
 
+fn read_queue_attr<D: AsRef<OsStr>>(name_in: D, attr: &str) -> Result<u64> {
+    let mut path = PathBuf::from("/sys/block");
+    path.push(name_in.as_ref());
+    path.push("queue");
+    path.push(attr);
+
+    let mut buf = String::new();
+    fs::File::open(&path)?.read_to_string(&mut buf)?;
+    Ok(buf.trim().parse::<u64>()?)
+}
+
+/// Given a device name, determine whether it's rotational.
+pub fn devname_to_rotational<D: AsRef<OsStr>>(name_in: D) -> Result<bool> {
+    Ok(read_queue_attr(name_in, "rotational")? != 0)
+}
+
+/// Given a device name, determine its logical and physical block sizes.
+pub fn devname_to_block_sizes<D: AsRef<OsStr>>(name_in: D) -> Result<(u64, u64)> {
+    Ok((
+        read_queue_attr(name_in.as_ref(), "logical_block_size")?,
+        read_queue_attr(name_in.as_ref(), "physical_block_size")?,
+    ))
+}
+
 /// Find all devices hosting swap
 pub fn swap_devnames() -> Result<Vec<OsString>> {
     let mut devnames = Vec::new();
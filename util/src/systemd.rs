@@ -13,6 +13,8 @@ use std::thread::{sleep, LocalKey};
 use std::time::{Duration, Instant};
 
 pub const SYSTEMD_DFL_TIMEOUT: f64 = 15.0;
+const DAEMON_RELOAD_TRIES: u32 = 3;
+const DAEMON_RELOAD_RETRY_INTV: Duration = Duration::from_secs(1);
 const SD1_DST: &str = "org.freedesktop.systemd1";
 const SD1_PATH: &str = "/org/freedesktop/systemd1";
 
@@ -205,10 +207,27 @@ impl SystemdDbus {
         wrap_rustbus_result(self.send_msg_and_wait_int(msg))
     }
 
+    // Turn a dbus error reply into an error carrying the error name, e.g.
+    // "org.freedesktop.systemd1.UnitExists: ...".
+    fn check_error_reply(resp: &MarshalledMessage) -> Result<()> {
+        if let MessageType::Error = resp.typ {
+            let name = resp
+                .dynheader
+                .error_name
+                .as_deref()
+                .unwrap_or("unknown error");
+            match resp.body.parser().get::<String>() {
+                Ok(text) => bail!("{}: {}", name, text),
+                Err(_) => bail!("{}", name),
+            }
+        }
+        Ok(())
+    }
+
     pub fn daemon_reload(&mut self) -> Result<()> {
         let mut msg = systemd_sd1_call("Reload");
-        self.send_msg_and_wait(&mut msg)?;
-        Ok(())
+        let resp = self.send_msg_and_wait(&mut msg)?;
+        Self::check_error_reply(&resp)
     }
 
     pub fn get_unit_props<'u>(&mut self, name: &str) -> Result<params::Param<'static, 'static>> {
@@ -267,13 +286,30 @@ impl SystemdDbus {
         extra_props: Vec<(String, PropVariant)>,
     ) -> Result<()> {
         let mut msg = systemd_start_transient_svc_call(name, args, envs, extra_props);
-        self.send_msg_and_wait(&mut msg)?;
-        Ok(())
+        let resp = self.send_msg_and_wait(&mut msg)?;
+        Self::check_error_reply(&resp)
     }
 }
 
+/// Reload systemd configuration, retrying a few times as reloads can fail
+/// transiently on a busy systemd. The final error carries the dbus error
+/// name and message, e.g. a timeout vs. a rejected configuration.
 pub fn daemon_reload() -> Result<()> {
-    SYS_SD_BUS.with(|s| s.borrow_mut().daemon_reload())
+    let mut tries = 0;
+    loop {
+        tries += 1;
+        match SYS_SD_BUS.with(|s| s.borrow_mut().daemon_reload()) {
+            Ok(()) => return Ok(()),
+            Err(e) if tries < DAEMON_RELOAD_TRIES && !super::prog_exiting() => {
+                warn!(
+                    "systemd: daemon-reload failed, retrying ({}/{}) ({:#})",
+                    tries, DAEMON_RELOAD_TRIES, &e
+                );
+                sleep(DAEMON_RELOAD_RETRY_INTV);
+            }
+            Err(e) => bail!("daemon-reload failed after {} tries ({:#})", tries, &e),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]